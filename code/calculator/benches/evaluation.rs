@@ -0,0 +1,98 @@
+// Compares the tree-walking `Evaluator` with compiled bytecode, closures and
+// column-wise batch evaluation on formulas evaluated once per row of input. Run with `cargo bench`.
+use calculator::parsemath::batch::BatchEvaluator;
+use calculator::parsemath::bytecode::{Program, Vm};
+use calculator::parsemath::closures;
+use calculator::parsemath::parser::Parser;
+use calculator::parsemath::visitors::{Evaluator, ExpressionVisitor};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ROWS: usize = 200_000;
+
+const FORMULAS: [&str; 3] = [
+    "2 * x + 1",
+    "a * x ^ 2 + b * x + c - sqrt(abs(x)) / (1 + x ^ 2)",
+    "sin(x) * cos(y) + exp(-(x ^ 2 + y ^ 2) / 2) * ln(1 + x * x)",
+];
+
+fn time(mut f: impl FnMut(f64) -> f64) -> Duration {
+    let start = Instant::now();
+    let mut total = 0.0;
+    for row in 0..ROWS {
+        total += f(black_box(row as f64 / ROWS as f64));
+    }
+    black_box(total);
+    start.elapsed()
+}
+
+fn main() {
+    for formula in FORMULAS {
+        let ast = Parser::new(formula).unwrap().parse().unwrap();
+        let program = Program::compile(&ast).unwrap();
+        let variables = program.variables().to_vec();
+
+        let mut evaluator = Evaluator::new();
+        let tree = time(|x| {
+            for (i, name) in variables.iter().enumerate() {
+                evaluator.set_variable(name, x + i as f64);
+            }
+            evaluator
+                .visit_expression(&ast)
+                .unwrap()
+                .as_number()
+                .unwrap()
+        });
+
+        let mut vm = Vm::new();
+        let mut inputs = vec![0.0; variables.len()];
+        let bytecode = time(|x| {
+            for (i, input) in inputs.iter_mut().enumerate() {
+                *input = x + i as f64;
+            }
+            vm.run(&program, &inputs).unwrap()
+        });
+
+        let compiled = closures::compile(&ast).unwrap();
+        let closure = time(|x| {
+            for (i, input) in inputs.iter_mut().enumerate() {
+                *input = x + i as f64;
+            }
+            compiled(&inputs).unwrap()
+        });
+
+        let columns: Vec<Vec<f64>> = (0..variables.len())
+            .map(|i| {
+                (0..ROWS)
+                    .map(|row| row as f64 / ROWS as f64 + i as f64)
+                    .collect()
+            })
+            .collect();
+        let start = Instant::now();
+        let mut batch = BatchEvaluator::new(ROWS);
+        for (name, column) in variables.iter().zip(&columns) {
+            batch = batch.with_column(name, column);
+        }
+        black_box(batch.evaluate(&ast).unwrap());
+        let batch = start.elapsed();
+
+        println!("{}", formula);
+        for (name, elapsed) in [
+            ("bytecode", bytecode),
+            ("closures", closure),
+            ("batch", batch),
+        ] {
+            println!(
+                "    {:<12} {:>8.1} ns/row, speed-up {:.1}x",
+                name,
+                elapsed.as_nanos() as f64 / ROWS as f64,
+                tree.as_secs_f64() / elapsed.as_secs_f64()
+            );
+        }
+        println!(
+            "    {:<12} {:>8.1} ns/row",
+            "tree-walker",
+            tree.as_nanos() as f64 / ROWS as f64
+        );
+    }
+}
@@ -1,8 +1,18 @@
 use crate::parsemath::{
-    parser::Parser,
-    visitors::{Evaluator, ExpressionVisitor},
+    ascii_art::AsciiArtVisitor,
+    derivative::DerivativeVisitor,
+    dot::DotVisitor,
+    latex::LatexVisitor,
+    mathml::MathMLVisitor,
+    parser::{Expression, Parser, ParserError},
+    rpn::RpnStack,
+    sexpr::SExprVisitor,
+    simplifier::Simplifier,
+    unicode::UnicodeVisitor,
+    visitors::{Evaluator, EvaluatorError, ExpressionVisitor, PrettyPrinterVisitor},
 };
 use mockall::automock;
+use thiserror::Error;
 
 #[automock]
 pub trait Console {
@@ -11,49 +21,169 @@ pub trait Console {
     fn print(&self, text: &str);
 }
 
+#[derive(Error, Debug)]
+enum CommandError {
+    #[error(transparent)]
+    Parser(#[from] ParserError),
+    #[error(transparent)]
+    Evaluator(#[from] EvaluatorError),
+    #[error("Usage: {0}")]
+    Usage(String),
+    #[error("Unknown command ':{0}'")]
+    UnknownCommand(String),
+}
+
+// How symbolic results such as derivatives are printed; chosen with `:format`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Plain,
+    Unicode,
+    Latex,
+    MathML,
+}
+
 pub struct Calculator<'a> {
     console: &'a dyn Console,
+    format: OutputFormat,
+    // The operand stack while in RPN mode, toggled with `:rpn`.
+    rpn: Option<RpnStack>,
 }
 
 impl<'a> Calculator<'a> {
     pub fn new(console: &'a dyn Console) -> Calculator<'a> {
-        Calculator { console }
+        Calculator {
+            console,
+            format: OutputFormat::Plain,
+            rpn: None,
+        }
+    }
+
+    pub fn with_format(mut self, format: OutputFormat) -> Calculator<'a> {
+        self.format = format;
+        self
     }
 
     pub fn run(&mut self) {
         self.console.println("### Calculator ver. 1.0 ###");
 
         loop {
-            self.console.print(">>> ");
-            let input = self.console.readline().to_uppercase();
+            self.console.print(if self.rpn.is_some() { "rpn> " } else { ">>> " });
+            let input = self.console.readline();
 
-            if input == "EXIT" {
+            if input.eq_ignore_ascii_case("exit") {
                 break;
             }
 
-            match Parser::new(&input) {
-                Ok(mut parser) => match parser.parse() {
-                    Ok(ast) => {
-                        let mut evaluator = Evaluator {};
-                        match evaluator.visit_expression(&ast) {
-                            Ok(result) => {
-                                self.console.println(&format!("{}", result));
-                            }
-                            Err(error) => {
-                                self.console.println(&format!("{:?}", error));
-                            }
-                        }
-                    }
-                    Err(error) => {
-                        self.console.println(&format!("{}", error));
+            match self.execute(&input) {
+                Ok(output) => self.console.println(&output),
+                Err(error) => self.console.println(&format!("{}", error)),
+            }
+        }
+    }
+
+    fn execute(&mut self, input: &str) -> Result<String, CommandError> {
+        if let Some(rest) = input.strip_prefix("d/d") {
+            return self.differentiate(rest);
+        }
+        if let Some(command) = input.strip_prefix(':') {
+            let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+            return self.command(name, argument.trim());
+        }
+
+        if let Some(stack) = &mut self.rpn {
+            stack.push_input(input)?;
+            return Ok(self.show_stack());
+        }
+
+        let ast = Self::parse(input)?;
+        let mut evaluator = Evaluator::new();
+        Ok(format!("{}", evaluator.visit_expression(&ast)?))
+    }
+
+    // `d/dx <expression>` prints the symbolic derivative with respect to `x`.
+    fn differentiate(&mut self, input: &str) -> Result<String, CommandError> {
+        let (variable, expression) = input
+            .split_once(' ')
+            .ok_or_else(|| CommandError::Usage("d/dx <expression>".to_string()))?;
+
+        let ast = Self::parse(expression)?;
+        let derivative = DerivativeVisitor::new(variable).visit_expression(&ast)?;
+        Ok(self.print(&derivative))
+    }
+
+    fn command(&mut self, name: &str, argument: &str) -> Result<String, CommandError> {
+        match name {
+            "simplify" => Ok(self.print(&Simplifier::simplify(&Self::parse(argument)?))),
+            "show" => {
+                let layout = AsciiArtVisitor
+                    .visit_expression(&Self::parse(argument)?)
+                    .expect("rendering an expression cannot fail");
+                Ok(layout.to_string())
+            }
+            "ast" => Ok(SExprVisitor
+                .visit_expression(&Self::parse(argument)?)
+                .expect("printing an expression cannot fail")),
+            "dot" => Ok(DotVisitor::render(&Self::parse(argument)?)),
+            "format" => {
+                self.format = match argument {
+                    "plain" => OutputFormat::Plain,
+                    "unicode" => OutputFormat::Unicode,
+                    "latex" => OutputFormat::Latex,
+                    "mathml" => OutputFormat::MathML,
+                    _ => {
+                        return Err(CommandError::Usage(
+                            ":format plain|unicode|latex|mathml".to_string(),
+                        ));
                     }
-                },
-                Err(error) => {
-                    self.console.println(&format!("{}", error));
-                }
+                };
+                Ok(format!("Output format: {}", argument))
             }
+            "rpn" => match self.rpn.take() {
+                Some(_) => Ok("RPN mode off".to_string()),
+                None => {
+                    self.rpn = Some(RpnStack::new());
+                    Ok("RPN mode on".to_string())
+                }
+            },
+            _ => Err(CommandError::UnknownCommand(name.to_string())),
         }
     }
+
+    // Lists the RPN stack with the top item last, at level 1. Items that cannot
+    // be evaluated yet, e.g. because they contain variables, are printed as is.
+    fn show_stack(&self) -> String {
+        let items = self.rpn.as_ref().map(RpnStack::items).unwrap_or_default();
+        if items.is_empty() {
+            return "(empty)".to_string();
+        }
+
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let value = match Evaluator::new().visit_expression(item) {
+                    Ok(value) => value.to_string(),
+                    Err(_) => self.print(item),
+                };
+                format!("{}: {}", items.len() - i, value)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn parse(input: &str) -> Result<Expression, ParserError> {
+        Parser::new(input)?.parse()
+    }
+
+    fn print(&self, expression: &Expression) -> String {
+        let printed = match self.format {
+            OutputFormat::Plain => PrettyPrinterVisitor::new().visit_expression(expression),
+            OutputFormat::Unicode => UnicodeVisitor.visit_expression(expression),
+            OutputFormat::Latex => LatexVisitor.visit_expression(expression),
+            OutputFormat::MathML => Ok(MathMLVisitor::render(expression)),
+        };
+        printed.expect("printing an expression cannot fail")
+    }
 }
 
 #[cfg(test)]
@@ -184,4 +314,234 @@ mod tests {
 
         calculator.run();
     }
+
+    #[test]
+    fn calculator_run_loop_prints_derivatives() {
+        let mut mock_console = MockConsole::new();
+
+        let mut seq = Sequence::new();
+
+        mock_console
+            .expect_println()
+            .with(eq("### Calculator ver. 1.0 ###"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| ());
+
+        mock_console
+            .expect_print()
+            .with(eq(">>> "))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| ());
+
+        mock_console
+            .expect_readline()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|| "d/dx x^2*sin(x)".to_string());
+
+        mock_console
+            .expect_println()
+            .times(1)
+            .in_sequence(&mut seq)
+            .with(eq("2 * x * sin(x) + x ^ 2 * cos(x)"))
+            .returning(|_| ());
+
+        mock_console
+            .expect_print()
+            .with(eq(">>> "))
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_| ());
+
+        mock_console
+            .expect_readline()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|| "Exit".to_string());
+
+        let mut calculator = Calculator::new(&mock_console);
+
+        calculator.run();
+    }
+
+    #[test]
+    fn calculator_run_loop_simplifies_expressions() {
+        let mut mock_console = MockConsole::new();
+
+        let mut seq = Sequence::new();
+
+        mock_console
+            .expect_println()
+            .with(eq("### Calculator ver. 1.0 ###"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| ());
+
+        mock_console
+            .expect_print()
+            .with(eq(">>> "))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| ());
+
+        mock_console
+            .expect_readline()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|| ":simplify x + 2*x*1 - (y - y)".to_string());
+
+        mock_console
+            .expect_println()
+            .times(1)
+            .in_sequence(&mut seq)
+            .with(eq("3 * x"))
+            .returning(|_| ());
+
+        mock_console
+            .expect_print()
+            .with(eq(">>> "))
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_| ());
+
+        mock_console
+            .expect_readline()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|| "Exit".to_string());
+
+        let mut calculator = Calculator::new(&mock_console);
+
+        calculator.run();
+    }
+
+    #[test]
+    fn calculator_run_loop_switches_output_format() {
+        let mut mock_console = MockConsole::new();
+
+        let mut seq = Sequence::new();
+
+        mock_console
+            .expect_println()
+            .with(eq("### Calculator ver. 1.0 ###"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| ());
+
+        for (input, output) in [
+            (":format unicode", "Output format: unicode"),
+            ("d/dx x^3", "3 · x²"),
+            (":format latex", "Output format: latex"),
+            (":simplify x / (2 * y) * 4", "\\frac{2 \\cdot x}{y}"),
+            (":format html", "Usage: :format plain|unicode|latex|mathml"),
+            (":show x^2 / 2", " 2\nx\n--\n2"),
+            (":ast 2 * (x - 1)", "(* 2 (- x 1))"),
+            (
+                ":dot -x",
+                "digraph Expression {\n    n0 [label=\"neg\"];\n    n1 [label=\"x\"];\n    n0 -> n1;\n}",
+            ),
+            (":plot x", "Unknown command ':plot'"),
+        ] {
+            mock_console
+                .expect_print()
+                .with(eq(">>> "))
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|_| ());
+
+            mock_console
+                .expect_readline()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(move || input.to_string());
+
+            mock_console
+                .expect_println()
+                .times(1)
+                .in_sequence(&mut seq)
+                .with(eq(output))
+                .returning(|_| ());
+        }
+
+        mock_console
+            .expect_print()
+            .with(eq(">>> "))
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_| ());
+
+        mock_console
+            .expect_readline()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|| "Exit".to_string());
+
+        let mut calculator = Calculator::new(&mock_console);
+
+        calculator.run();
+    }
+
+    #[test]
+    fn calculator_run_loop_keeps_an_rpn_stack() {
+        let mut mock_console = MockConsole::new();
+
+        let mut seq = Sequence::new();
+
+        mock_console
+            .expect_println()
+            .with(eq("### Calculator ver. 1.0 ###"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| ());
+
+        for (prompt, input, output) in [
+            (">>> ", ":rpn", "RPN mode on"),
+            ("rpn> ", "3 4", "2: 3\n1: 4"),
+            ("rpn> ", "+ 2 *", "1: 14"),
+            ("rpn> ", "x dup", "3: 14\n2: x\n1: x"),
+            ("rpn> ", "*", "2: 14\n1: x * x"),
+            ("rpn> ", "swap drop -", "Syntax error: Not enough operands for '-'."),
+            ("rpn> ", "clear", "(empty)"),
+            ("rpn> ", ":rpn", "RPN mode off"),
+        ] {
+            mock_console
+                .expect_print()
+                .with(eq(prompt))
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|_| ());
+
+            mock_console
+                .expect_readline()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(move || input.to_string());
+
+            mock_console
+                .expect_println()
+                .times(1)
+                .in_sequence(&mut seq)
+                .with(eq(output))
+                .returning(|_| ());
+        }
+
+        mock_console
+            .expect_print()
+            .with(eq(">>> "))
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_once(|_| ());
+
+        mock_console
+            .expect_readline()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|| "Exit".to_string());
+
+        let mut calculator = Calculator::new(&mock_console);
+
+        calculator.run();
+    }
 }
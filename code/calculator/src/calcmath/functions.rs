@@ -0,0 +1,404 @@
+use crate::calcmath::matrix::Matrix;
+use crate::calcmath::number_theory::{self, to_integer, to_number};
+use crate::calcmath::statistics;
+use crate::calcmath::value::Value;
+use crate::parsemath::limits::{self, Budget, EvalLimits};
+use crate::parsemath::visitors::EvaluatorError;
+
+// The largest matrix a built-in function will allocate, in elements (8 MiB).
+pub const MAX_ELEMENTS: usize = 1 << 20;
+
+// Named constants, looked up when no variable of that name is bound.
+pub fn constant(name: &str) -> Option<f64> {
+    match name {
+        "pi" => Some(std::f64::consts::PI),
+        "e" => Some(std::f64::consts::E),
+        _ => None,
+    }
+}
+
+// The real function behind a single-argument built-in such as `sin`.
+pub fn elementary_function(name: &str) -> Option<fn(f64) -> f64> {
+    let f: fn(f64) -> f64 = match name {
+        "sqrt" => f64::sqrt,
+        "exp" => f64::exp,
+        "ln" => f64::ln,
+        "log" => f64::log10,
+        "abs" => f64::abs,
+        "sin" => f64::sin,
+        "cos" => f64::cos,
+        "tan" => f64::tan,
+        "asin" => f64::asin,
+        "acos" => f64::acos,
+        "atan" => f64::atan,
+        "sinh" => f64::sinh,
+        "cosh" => f64::cosh,
+        "tanh" => f64::tanh,
+        _ => return None,
+    };
+    Some(f)
+}
+
+// Whether the built-in `name` always returns a number, never a matrix.
+pub fn returns_number(name: &str) -> bool {
+    elementary_function(name).is_some()
+        || matches!(
+            name,
+            "det"
+                | "mean"
+                | "median"
+                | "mode"
+                | "variance"
+                | "pvariance"
+                | "stdev"
+                | "pstdev"
+                | "min"
+                | "max"
+                | "range"
+                | "percentile"
+                | "correlation"
+                | "nCr"
+                | "nPr"
+                | "gcd"
+                | "lcm"
+                | "isprime"
+                | "nextprime"
+                | "mod_pow"
+                | "mod_inv"
+                | "integrate"
+                | "diff"
+        )
+}
+
+// Built-in functions callable from expressions, e.g. `det([[1, 2], [3, 4]])`.
+pub fn call(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    call_with_limits(name, args, &EvalLimits::default(), None)
+}
+
+// Like `call`, but functions whose result can be much larger than their
+// arguments, such as `identity(n)`, check `limits.max_value_size` before
+// allocating it, and those whose running time is not bounded by the size of
+// their arguments, such as `factor(n)`, charge `budget` as they go.
+pub fn call_with_limits(
+    name: &str,
+    args: &[Value],
+    limits: &EvalLimits,
+    budget: Option<&mut Budget>,
+) -> Result<Value, EvaluatorError> {
+    match name {
+        _ if let Some(f) = elementary_function(name) => elementary(name, args, f),
+        "transpose" => {
+            let [m] = expect_args(name, args)?;
+            Ok(m.as_matrix()?.transpose().into())
+        }
+        "det" => {
+            let [m] = expect_args(name, args)?;
+            Ok(m.as_matrix()?.determinant()?.into())
+        }
+        "inv" => {
+            let [m] = expect_args(name, args)?;
+            Ok(m.as_matrix()?.inverse()?.into())
+        }
+        "solve" => {
+            let [a, b] = expect_args(name, args)?;
+            Ok(a.as_matrix()?.solve(b.as_matrix()?)?.into())
+        }
+        "identity" => {
+            let [n] = expect_args(name, args)?;
+            let n = to_size(name, n.as_number()?)?;
+            match n.checked_mul(n) {
+                Some(elements) if elements <= MAX_ELEMENTS => {
+                    limits::check_value_size(elements, limits)?;
+                    Ok(Matrix::identity(n).into())
+                }
+                _ => Err(EvaluatorError::InvalidArgument(format!(
+                    "{}({}) would have more than {} elements",
+                    name, n, MAX_ELEMENTS
+                ))),
+            }
+        }
+        "mean" => Ok(statistics::mean(&flatten(args))?.into()),
+        "median" => Ok(statistics::median(&flatten(args))?.into()),
+        "mode" => Ok(statistics::mode(&flatten(args))?.into()),
+        "variance" => Ok(statistics::variance(&flatten(args), true)?.into()),
+        "pvariance" => Ok(statistics::variance(&flatten(args), false)?.into()),
+        "stdev" => Ok(statistics::stdev(&flatten(args), true)?.into()),
+        "pstdev" => Ok(statistics::stdev(&flatten(args), false)?.into()),
+        "min" => Ok(statistics::min(&flatten(args))?.into()),
+        "max" => Ok(statistics::max(&flatten(args))?.into()),
+        "range" => Ok(statistics::range(&flatten(args))?.into()),
+        "percentile" => {
+            let [data, p] = expect_args(name, args)?;
+            Ok(
+                statistics::percentile(&flatten(std::slice::from_ref(data)), p.as_number()?)?
+                    .into(),
+            )
+        }
+        "correlation" => {
+            let [xs, ys] = expect_args(name, args)?;
+            Ok(statistics::correlation(xs.as_matrix()?.values(), ys.as_matrix()?.values())?.into())
+        }
+        "linreg" => {
+            let [xs, ys] = expect_args(name, args)?;
+            let (slope, intercept) =
+                statistics::linreg(xs.as_matrix()?.values(), ys.as_matrix()?.values())?;
+            Ok(Matrix::row_vector(vec![slope, intercept]).into())
+        }
+        "nCr" => {
+            let [n, r] = integer_args(name, args)?;
+            Ok(to_number(number_theory::ncr(n, r)?)?.into())
+        }
+        "nPr" => {
+            let [n, r] = integer_args(name, args)?;
+            Ok(to_number(number_theory::npr(n, r)?)?.into())
+        }
+        "gcd" => {
+            let values = integers(&flatten(args))?;
+            Ok(to_number(values.into_iter().fold(0, number_theory::gcd) as i128)?.into())
+        }
+        "lcm" => {
+            let mut result = 1;
+            for value in integers(&flatten(args))? {
+                result = number_theory::lcm(result as i64, value)?;
+            }
+            Ok(to_number(result)?.into())
+        }
+        "isprime" => {
+            let [n] = integer_args(name, args)?;
+            Ok(Value::Number(if number_theory::is_prime(n) {
+                1.0
+            } else {
+                0.0
+            }))
+        }
+        "nextprime" => {
+            let [n] = integer_args(name, args)?;
+            Ok(to_number(number_theory::next_prime(n, budget)? as i128)?.into())
+        }
+        "factor" => {
+            let [n] = integer_args(name, args)?;
+            let factors = number_theory::factor(n, budget)?
+                .into_iter()
+                .map(|f| f as f64)
+                .collect();
+            Ok(Matrix::row_vector(factors).into())
+        }
+        "mod_pow" => {
+            let [base, exponent, modulus] = integer_args(name, args)?;
+            Ok(to_number(number_theory::mod_pow(base, exponent, modulus)? as i128)?.into())
+        }
+        "mod_inv" => {
+            let [a, modulus] = integer_args(name, args)?;
+            Ok(to_number(number_theory::mod_inv(a, modulus)? as i128)?.into())
+        }
+        _ => Err(EvaluatorError::UnknownFunction(name.to_string())),
+    }
+}
+
+pub(crate) fn expect_args<'a, T, const N: usize>(
+    name: &str,
+    args: &'a [T],
+) -> Result<&'a [T; N], EvaluatorError> {
+    args.try_into().map_err(|_| EvaluatorError::ArgumentCount {
+        function: name.to_string(),
+        expected: N,
+        found: args.len(),
+    })
+}
+
+// Single-argument real function; results outside the real domain are reported
+// as errors rather than NaN.
+fn elementary(name: &str, args: &[Value], f: fn(f64) -> f64) -> Result<Value, EvaluatorError> {
+    let [x] = expect_args(name, args)?;
+    Ok(Value::Number(apply_elementary(name, f, x.as_number()?)?))
+}
+
+// Also used by compiled expressions, which resolve `f` ahead of time.
+pub fn apply_elementary(name: &str, f: fn(f64) -> f64, x: f64) -> Result<f64, EvaluatorError> {
+    let result = f(x);
+    if result.is_nan() && !x.is_nan() {
+        return Err(EvaluatorError::InvalidArgument(format!(
+            "{} is undefined for {}",
+            name, x
+        )));
+    }
+    Ok(result)
+}
+
+fn integer_args<const N: usize>(name: &str, args: &[Value]) -> Result<[i64; N], EvaluatorError> {
+    let args: &[Value; N] = expect_args(name, args)?;
+    let mut integers = [0; N];
+    for (integer, arg) in integers.iter_mut().zip(args) {
+        *integer = to_integer(arg.as_number()?)?;
+    }
+    Ok(integers)
+}
+
+fn integers(values: &[f64]) -> Result<Vec<i64>, EvaluatorError> {
+    if values.is_empty() {
+        return Err(EvaluatorError::InvalidArgument(
+            "expected at least one integer".to_string(),
+        ));
+    }
+    values.iter().map(|&v| to_integer(v)).collect()
+}
+
+// Variadic arguments: numbers are taken as-is, matrices contribute all their
+// elements, so `mean(1, 2, 3)` and `mean([1, 2, 3])` are equivalent.
+fn flatten(args: &[Value]) -> Vec<f64> {
+    let mut values = Vec::new();
+    for arg in args {
+        match arg {
+            Value::Number(n) => values.push(*n),
+            Value::Matrix(m) => values.extend_from_slice(m.values()),
+        }
+    }
+    values
+}
+
+fn to_size(name: &str, value: f64) -> Result<usize, EvaluatorError> {
+    if value.fract() != 0.0 || !(1.0..=MAX_ELEMENTS as f64).contains(&value) {
+        return Err(EvaluatorError::InvalidArgument(format!(
+            "{} expects a positive integer up to {}, got {}",
+            name, MAX_ELEMENTS, value
+        )));
+    }
+    Ok(value as usize)
+}
+
+#[cfg(test)]
+mod functions_tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    fn identity_builds_unit_matrix() {
+        let result = call("identity", &[Value::Number(2.0)]).unwrap();
+        assert_eq!(result, Value::Matrix(Matrix::identity(2)));
+    }
+
+    #[rstest]
+    #[case(0.0)]
+    #[case(1.5)]
+    #[case(f64::INFINITY)]
+    #[case(1e10)]
+    #[case(100000.0)]
+    #[case(1025.0)]
+    fn identity_rejects_invalid_size(#[case] n: f64) {
+        let result = call("identity", &[Value::Number(n)]);
+        assert!(matches!(result, Err(EvaluatorError::InvalidArgument(_))));
+    }
+
+    #[rstest]
+    fn identity_allows_the_largest_size() {
+        let result = call("identity", &[Value::Number(1024.0)]).unwrap();
+        assert_eq!(result.size(), MAX_ELEMENTS);
+    }
+
+    #[rstest]
+    fn det_of_number_is_type_mismatch() {
+        let result = call("det", &[Value::Number(2.0)]);
+        assert!(matches!(result, Err(EvaluatorError::TypeMismatch(_))));
+    }
+
+    #[rstest]
+    fn wrong_argument_count() {
+        let result = call("solve", &[Value::Matrix(Matrix::identity(2))]);
+        assert_eq!(
+            result,
+            Err(EvaluatorError::ArgumentCount {
+                function: "solve".to_string(),
+                expected: 2,
+                found: 1
+            })
+        );
+    }
+
+    #[rstest]
+    #[case("mean", 2.5)]
+    #[case("median", 2.5)]
+    #[case("min", 1.0)]
+    #[case("max", 4.0)]
+    #[case("range", 3.0)]
+    fn statistics_accept_numbers_and_lists(#[case] name: &str, #[case] expected: f64) {
+        let variadic = [1.0, 2.0, 3.0, 4.0].map(Value::Number);
+        let mixed = [
+            Value::Matrix(Matrix::row_vector(vec![1.0, 2.0])),
+            Value::Number(3.0),
+            Value::Number(4.0),
+        ];
+
+        assert_eq!(call(name, &variadic).unwrap(), expected);
+        assert_eq!(call(name, &mixed).unwrap(), expected);
+    }
+
+    #[rstest]
+    fn linreg_returns_slope_and_intercept() {
+        let xs = Value::Matrix(Matrix::row_vector(vec![0.0, 1.0, 2.0]));
+        let ys = Value::Matrix(Matrix::row_vector(vec![1.0, 3.0, 5.0]));
+        let result = call("linreg", &[xs, ys]).unwrap();
+        assert_eq!(result, Value::Matrix(Matrix::row_vector(vec![2.0, 1.0])));
+    }
+
+    #[rstest]
+    fn statistics_of_no_values_is_an_error() {
+        assert!(matches!(
+            call("mean", &[]),
+            Err(EvaluatorError::InvalidArgument(_))
+        ));
+    }
+
+    #[rstest]
+    #[case("nCr", vec![5.0, 2.0], "10")]
+    #[case("nPr", vec![5.0, 2.0], "20")]
+    #[case("gcd", vec![12.0, 18.0, 27.0], "3")]
+    #[case("lcm", vec![4.0, 6.0, 10.0], "60")]
+    #[case("isprime", vec![97.0], "1")]
+    #[case("isprime", vec![91.0], "0")]
+    #[case("nextprime", vec![97.0], "101")]
+    #[case("factor", vec![360.0], "[2, 2, 2, 3, 3, 5]")]
+    #[case("mod_pow", vec![2.0, 10.0, 1000.0], "24")]
+    #[case("mod_inv", vec![3.0, 7.0], "5")]
+    fn number_theory_functions(#[case] name: &str, #[case] args: Vec<f64>, #[case] expected: &str) {
+        let args: Vec<Value> = args.into_iter().map(Value::Number).collect();
+        assert_eq!(call(name, &args).unwrap().to_string(), expected);
+    }
+
+    #[rstest]
+    #[case("nCr", vec![5.5, 2.0])]
+    #[case("gcd", vec![4.0, 0.5])]
+    #[case("isprime", vec![7.1])]
+    fn number_theory_rejects_non_integers(#[case] name: &str, #[case] args: Vec<f64>) {
+        let args: Vec<Value> = args.into_iter().map(Value::Number).collect();
+        assert!(matches!(
+            call(name, &args),
+            Err(EvaluatorError::NotAnInteger(_))
+        ));
+    }
+
+    #[rstest]
+    #[case("sqrt", 9.0, 3.0)]
+    #[case("exp", 0.0, 1.0)]
+    #[case("ln", 1.0, 0.0)]
+    #[case("log", 1000.0, 3.0)]
+    #[case("abs", -2.0, 2.0)]
+    #[case("cos", 0.0, 1.0)]
+    fn elementary_functions(#[case] name: &str, #[case] x: f64, #[case] expected: f64) {
+        assert_eq!(call(name, &[Value::Number(x)]).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("sqrt", -1.0)]
+    #[case("ln", -1.0)]
+    #[case("acos", 2.0)]
+    fn elementary_functions_outside_domain(#[case] name: &str, #[case] x: f64) {
+        let result = call(name, &[Value::Number(x)]);
+        assert!(matches!(result, Err(EvaluatorError::InvalidArgument(_))));
+    }
+
+    #[rstest]
+    fn unknown_function() {
+        let result = call("foo", &[]);
+        assert_eq!(result, Err(EvaluatorError::UnknownFunction("foo".to_string())));
+    }
+}
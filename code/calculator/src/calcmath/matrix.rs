@@ -0,0 +1,414 @@
+use std::fmt;
+
+use crate::parsemath::visitors::EvaluatorError;
+
+// Pivots smaller than this are treated as zero when inverting or solving.
+const SINGULARITY_EPSILON: f64 = 1e-12;
+
+// Dense, row-major matrix of f64 values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<f64>,
+}
+
+impl Matrix {
+    pub fn new(rows: usize, cols: usize, data: Vec<f64>) -> Result<Self, EvaluatorError> {
+        if rows * cols != data.len() {
+            return Err(EvaluatorError::DimensionMismatch(format!(
+                "{} values do not fill a {}x{} matrix",
+                data.len(),
+                rows,
+                cols
+            )));
+        }
+
+        Ok(Matrix { rows, cols, data })
+    }
+
+    pub fn from_rows(rows: Vec<Vec<f64>>) -> Result<Self, EvaluatorError> {
+        let row_count = rows.len();
+        let col_count = rows.first().map_or(0, |row| row.len());
+
+        let mut data = Vec::with_capacity(row_count * col_count);
+        for row in rows {
+            if row.len() != col_count {
+                return Err(EvaluatorError::DimensionMismatch(format!(
+                    "rows must have equal length, found {} and {}",
+                    col_count,
+                    row.len()
+                )));
+            }
+            data.extend(row);
+        }
+
+        Matrix::new(row_count, col_count, data)
+    }
+
+    pub fn row_vector(values: Vec<f64>) -> Self {
+        Matrix {
+            rows: 1,
+            cols: values.len(),
+            data: values,
+        }
+    }
+
+    pub fn identity(n: usize) -> Self {
+        let mut data = vec![0.0; n * n];
+        for i in 0..n {
+            data[i * n + i] = 1.0;
+        }
+
+        Matrix {
+            rows: n,
+            cols: n,
+            data,
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn values(&self) -> &[f64] {
+        &self.data
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f64 {
+        self.data[row * self.cols + col]
+    }
+
+    pub fn row(&self, row: usize) -> Matrix {
+        let start = row * self.cols;
+        Matrix::row_vector(self.data[start..start + self.cols].to_vec())
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.rows == self.cols
+    }
+
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Matrix {
+        Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data: self.data.iter().map(|&v| f(v)).collect(),
+        }
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let mut data = Vec::with_capacity(self.data.len());
+        for col in 0..self.cols {
+            for row in 0..self.rows {
+                data.push(self.get(row, col));
+            }
+        }
+
+        Matrix {
+            rows: self.cols,
+            cols: self.rows,
+            data,
+        }
+    }
+
+    pub fn add(&self, other: &Matrix) -> Result<Matrix, EvaluatorError> {
+        self.zip_with(other, "add", |a, b| a + b)
+    }
+
+    pub fn subtract(&self, other: &Matrix) -> Result<Matrix, EvaluatorError> {
+        self.zip_with(other, "subtract", |a, b| a - b)
+    }
+
+    pub fn multiply(&self, other: &Matrix) -> Result<Matrix, EvaluatorError> {
+        if self.cols != other.rows {
+            return Err(EvaluatorError::DimensionMismatch(format!(
+                "cannot multiply {} by {}",
+                self.shape(),
+                other.shape()
+            )));
+        }
+
+        let mut data = vec![0.0; self.rows * other.cols];
+        for row in 0..self.rows {
+            for col in 0..other.cols {
+                data[row * other.cols + col] =
+                    (0..self.cols).map(|k| self.get(row, k) * other.get(k, col)).sum();
+            }
+        }
+
+        Ok(Matrix {
+            rows: self.rows,
+            cols: other.cols,
+            data,
+        })
+    }
+
+    // Integer power by repeated squaring; negative exponents use the inverse.
+    pub fn power(&self, exponent: i64) -> Result<Matrix, EvaluatorError> {
+        self.require_square("^")?;
+
+        let mut base = if exponent < 0 {
+            self.inverse()?
+        } else {
+            self.clone()
+        };
+        let mut exponent = exponent.unsigned_abs();
+        let mut result = Matrix::identity(self.rows);
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.multiply(&base)?;
+            }
+            base = base.multiply(&base)?;
+            exponent >>= 1;
+        }
+
+        Ok(result)
+    }
+
+    pub fn determinant(&self) -> Result<f64, EvaluatorError> {
+        self.require_square("det")?;
+
+        let n = self.rows;
+        let mut a = self.data.clone();
+        let mut det = 1.0;
+
+        for pivot in 0..n {
+            let best = (pivot..n)
+                .max_by(|&i, &j| a[i * n + pivot].abs().total_cmp(&a[j * n + pivot].abs()))
+                .unwrap();
+            if a[best * n + pivot] == 0.0 {
+                return Ok(0.0);
+            }
+            if best != pivot {
+                swap_rows(&mut a, n, best, pivot);
+                det = -det;
+            }
+
+            let p = a[pivot * n + pivot];
+            det *= p;
+            for row in pivot + 1..n {
+                let factor = a[row * n + pivot] / p;
+                for col in pivot..n {
+                    a[row * n + col] -= factor * a[pivot * n + col];
+                }
+            }
+        }
+
+        Ok(det)
+    }
+
+    pub fn inverse(&self) -> Result<Matrix, EvaluatorError> {
+        self.require_square("inv")?;
+        self.solve(&Matrix::identity(self.rows))
+    }
+
+    // Solves `self * x = b` with Gauss-Jordan elimination and partial pivoting.
+    // A row vector `b` of matching length is treated as a column vector and the
+    // solution is returned in the same orientation.
+    pub fn solve(&self, b: &Matrix) -> Result<Matrix, EvaluatorError> {
+        self.require_square("solve")?;
+
+        if b.rows == 1 && b.cols == self.rows && self.rows != 1 {
+            return Ok(self.solve(&b.transpose())?.transpose());
+        }
+        if b.rows != self.rows {
+            return Err(EvaluatorError::DimensionMismatch(format!(
+                "cannot solve {} system with right-hand side {}",
+                self.shape(),
+                b.shape()
+            )));
+        }
+
+        let n = self.rows;
+        let m = b.cols;
+        let mut a = self.data.clone();
+        let mut x = b.data.clone();
+
+        for pivot in 0..n {
+            let best = (pivot..n)
+                .max_by(|&i, &j| a[i * n + pivot].abs().total_cmp(&a[j * n + pivot].abs()))
+                .unwrap();
+            if a[best * n + pivot].abs() < SINGULARITY_EPSILON {
+                return Err(EvaluatorError::SingularMatrix);
+            }
+            swap_rows(&mut a, n, best, pivot);
+            swap_rows(&mut x, m, best, pivot);
+
+            let p = a[pivot * n + pivot];
+            for col in 0..n {
+                a[pivot * n + col] /= p;
+            }
+            for col in 0..m {
+                x[pivot * m + col] /= p;
+            }
+
+            for row in (0..n).filter(|&row| row != pivot) {
+                let factor = a[row * n + pivot];
+                if factor == 0.0 {
+                    continue;
+                }
+                for col in 0..n {
+                    a[row * n + col] -= factor * a[pivot * n + col];
+                }
+                for col in 0..m {
+                    x[row * m + col] -= factor * x[pivot * m + col];
+                }
+            }
+        }
+
+        Matrix::new(n, m, x)
+    }
+
+    fn zip_with(
+        &self,
+        other: &Matrix,
+        operation: &str,
+        f: impl Fn(f64, f64) -> f64,
+    ) -> Result<Matrix, EvaluatorError> {
+        if self.rows != other.rows || self.cols != other.cols {
+            return Err(EvaluatorError::DimensionMismatch(format!(
+                "cannot {} {} and {}",
+                operation,
+                self.shape(),
+                other.shape()
+            )));
+        }
+
+        Ok(Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data: self
+                .data
+                .iter()
+                .zip(&other.data)
+                .map(|(&a, &b)| f(a, b))
+                .collect(),
+        })
+    }
+
+    fn require_square(&self, function: &str) -> Result<(), EvaluatorError> {
+        if !self.is_square() {
+            return Err(EvaluatorError::DimensionMismatch(format!(
+                "{} requires a square matrix, got {}",
+                function,
+                self.shape()
+            )));
+        }
+        Ok(())
+    }
+
+    fn shape(&self) -> String {
+        format!("{}x{}", self.rows, self.cols)
+    }
+}
+
+fn swap_rows(data: &mut [f64], width: usize, a: usize, b: usize) {
+    if a != b {
+        for col in 0..width {
+            data.swap(a * width + col, b * width + col);
+        }
+    }
+}
+
+// A single row prints as a flat list (`[1, 2]`), anything else as a list of
+// rows (`[[1, 2], [3, 4]]`), so that the output parses back to the same matrix.
+impl fmt::Display for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format_row = |row: usize| {
+            let values: Vec<String> = (0..self.cols).map(|col| self.get(row, col).to_string()).collect();
+            format!("[{}]", values.join(", "))
+        };
+
+        if self.rows == 1 {
+            return write!(f, "{}", format_row(0));
+        }
+
+        let rows: Vec<String> = (0..self.rows).map(format_row).collect();
+        write!(f, "[{}]", rows.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod matrix_tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn assert_matrix_approx_eq(actual: &Matrix, expected: &Matrix) {
+        assert_eq!((actual.rows(), actual.cols()), (expected.rows(), expected.cols()));
+        for (a, e) in actual.values().iter().zip(expected.values()) {
+            assert!((a - e).abs() < 1e-9, "{} != {}", actual, expected);
+        }
+    }
+
+    #[rstest]
+    fn from_rows_rejects_ragged_rows() {
+        let result = Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0]]);
+        assert!(matches!(result, Err(EvaluatorError::DimensionMismatch(_))));
+    }
+
+    #[rstest]
+    fn transpose_swaps_rows_and_columns() {
+        let m = Matrix::from_rows(vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]).unwrap();
+        let expected = Matrix::from_rows(vec![vec![1.0, 4.0], vec![2.0, 5.0], vec![3.0, 6.0]]).unwrap();
+        assert_eq!(m.transpose(), expected);
+    }
+
+    #[rstest]
+    fn multiply_computes_matrix_product() {
+        let a = Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0, 4.0]]).unwrap();
+        let b = Matrix::from_rows(vec![vec![5.0], vec![6.0]]).unwrap();
+        let expected = Matrix::from_rows(vec![vec![17.0], vec![39.0]]).unwrap();
+        assert_eq!(a.multiply(&b).unwrap(), expected);
+    }
+
+    #[rstest]
+    fn multiply_checks_inner_dimensions() {
+        let a = Matrix::identity(2);
+        let b = Matrix::identity(3);
+        assert_eq!(
+            a.multiply(&b),
+            Err(EvaluatorError::DimensionMismatch("cannot multiply 2x2 by 3x3".to_string()))
+        );
+    }
+
+    #[rstest]
+    #[case(vec![vec![1.0, 2.0], vec![3.0, 4.0]], -2.0)]
+    #[case(vec![vec![2.0, 0.0, 1.0], vec![1.0, 3.0, 2.0], vec![1.0, 1.0, 2.0]], 6.0)]
+    #[case(vec![vec![1.0, 2.0], vec![2.0, 4.0]], 0.0)]
+    fn determinant_of_square_matrix(#[case] rows: Vec<Vec<f64>>, #[case] expected: f64) {
+        let m = Matrix::from_rows(rows).unwrap();
+        assert!((m.determinant().unwrap() - expected).abs() < 1e-9);
+    }
+
+    #[rstest]
+    fn inverse_times_matrix_is_identity() {
+        let m = Matrix::from_rows(vec![vec![4.0, 7.0], vec![2.0, 6.0]]).unwrap();
+        let product = m.multiply(&m.inverse().unwrap()).unwrap();
+        assert_matrix_approx_eq(&product, &Matrix::identity(2));
+    }
+
+    #[rstest]
+    fn inverse_of_singular_matrix_fails() {
+        let m = Matrix::from_rows(vec![vec![1.0, 2.0], vec![2.0, 4.0]]).unwrap();
+        assert_eq!(m.inverse(), Err(EvaluatorError::SingularMatrix));
+    }
+
+    #[rstest]
+    fn solve_accepts_row_vector_right_hand_side() {
+        let a = Matrix::from_rows(vec![vec![2.0, 1.0], vec![1.0, 3.0]]).unwrap();
+        let b = Matrix::row_vector(vec![3.0, 5.0]);
+        let x = a.solve(&b).unwrap();
+        assert_matrix_approx_eq(&x, &Matrix::row_vector(vec![0.8, 1.4]));
+    }
+
+    #[rstest]
+    #[case(Matrix::row_vector(vec![1.0, 2.0]), "[1, 2]")]
+    #[case(Matrix::identity(2), "[[1, 0], [0, 1]]")]
+    fn display_matrix(#[case] m: Matrix, #[case] expected: &str) {
+        assert_eq!(m.to_string(), expected);
+    }
+}
@@ -1 +1,8 @@
-pub mod calc;
\ No newline at end of file
+pub mod calc;
+pub mod calculus;
+pub mod functions;
+pub mod matrix;
+pub mod number_theory;
+pub mod roots;
+pub mod statistics;
+pub mod value;
\ No newline at end of file
@@ -0,0 +1,354 @@
+use crate::parsemath::limits::Budget;
+use crate::parsemath::visitors::EvaluatorError;
+
+// Largest integer magnitude an f64 represents exactly (2^53).
+pub const MAX_EXACT_INTEGER: i64 = 1 << 53;
+
+// Converts a number to an exact integer, rejecting fractions and values too
+// large to be represented exactly.
+pub fn to_integer(value: f64) -> Result<i64, EvaluatorError> {
+    if !value.is_finite() || value.fract() != 0.0 {
+        return Err(EvaluatorError::NotAnInteger(value));
+    }
+    if value.abs() > MAX_EXACT_INTEGER as f64 {
+        return Err(EvaluatorError::IntegerOverflow(format!(
+            "{} exceeds the exact integer range",
+            value
+        )));
+    }
+    Ok(value as i64)
+}
+
+// Converts an integer result back to a number, failing if it would lose precision.
+pub fn to_number(value: i128) -> Result<f64, EvaluatorError> {
+    if value.abs() > MAX_EXACT_INTEGER as i128 {
+        return Err(EvaluatorError::IntegerOverflow(format!(
+            "result {} exceeds the exact integer range",
+            value
+        )));
+    }
+    Ok(value as f64)
+}
+
+pub fn ncr(n: i64, r: i64) -> Result<i128, EvaluatorError> {
+    require_non_negative("nCr", &[n, r])?;
+    if r > n {
+        return Ok(0);
+    }
+
+    // C(n, r) = C(n, r - 1) * (n - r + 1) / r stays integral at every step.
+    let r = r.min(n - r) as i128;
+    let mut result: i128 = 1;
+    for k in 1..=r {
+        result = checked("nCr", result.checked_mul(n as i128 - k + 1))? / k;
+        to_number(result)?;
+    }
+    Ok(result)
+}
+
+pub fn npr(n: i64, r: i64) -> Result<i128, EvaluatorError> {
+    require_non_negative("nPr", &[n, r])?;
+    if r > n {
+        return Ok(0);
+    }
+
+    let mut result: i128 = 1;
+    for k in (n - r + 1)..=n {
+        result = checked("nPr", result.checked_mul(k as i128))?;
+        to_number(result)?;
+    }
+    Ok(result)
+}
+
+pub fn gcd(a: i64, b: i64) -> i64 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+pub fn lcm(a: i64, b: i64) -> Result<i128, EvaluatorError> {
+    if a == 0 || b == 0 {
+        return Ok(0);
+    }
+    let result = (a / gcd(a, b)) as i128 * b as i128;
+    to_number(result)?;
+    Ok(result.abs())
+}
+
+// Deterministic Miller-Rabin; these bases are sufficient for every 64-bit integer.
+pub fn is_prime(n: i64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    let n = n as u64;
+    for p in [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37] {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+
+    let (mut d, mut s) = (n - 1, 0);
+    while d.is_multiple_of(2) {
+        d /= 2;
+        s += 1;
+    }
+
+    'witness: for a in [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37] {
+        let mut x = pow_mod_u64(a, d, n);
+        if x == 1 || x == n - 1 {
+            continue;
+        }
+        for _ in 1..s {
+            x = mul_mod_u64(x, x, n);
+            if x == n - 1 {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+pub fn next_prime(n: i64, mut budget: Option<&mut Budget>) -> Result<i64, EvaluatorError> {
+    let mut candidate = n.max(1) + 1;
+    while !is_prime(candidate) {
+        charge(&mut budget, 1)?;
+        candidate += 1;
+    }
+    to_number(candidate as i128)?;
+    Ok(candidate)
+}
+
+// Prime factorisation in ascending order, with repeated factors, e.g. 12 -> [2, 2, 3].
+// Trial division can take around 10^8 steps for a product of two large primes,
+// so it charges `budget` for every `DIVISORS_PER_STEP` divisors tried.
+pub fn factor(n: i64, mut budget: Option<&mut Budget>) -> Result<Vec<i64>, EvaluatorError> {
+    if n < 2 {
+        return Err(EvaluatorError::InvalidArgument(format!(
+            "factor expects an integer greater than 1, got {}",
+            n
+        )));
+    }
+
+    let mut factors = Vec::new();
+    let mut rest = n;
+    let mut divisor = 2;
+    // Trial division, stopping early once the remaining cofactor is prime.
+    let mut rest_is_prime = is_prime(rest);
+    let mut tried = 0u64;
+    while !rest_is_prime && divisor * divisor <= rest {
+        tried += 1;
+        if tried.is_multiple_of(DIVISORS_PER_STEP) {
+            charge(&mut budget, 1)?;
+        }
+        if rest % divisor == 0 {
+            while rest % divisor == 0 {
+                factors.push(divisor);
+                rest /= divisor;
+            }
+            rest_is_prime = is_prime(rest);
+        }
+        divisor += if divisor == 2 { 1 } else { 2 };
+    }
+    if rest > 1 {
+        factors.push(rest);
+    }
+    Ok(factors)
+}
+
+// Trial divisions that cost as much as one evaluation step.
+const DIVISORS_PER_STEP: u64 = 16;
+
+fn charge(budget: &mut Option<&mut Budget>, steps: u64) -> Result<(), EvaluatorError> {
+    if let Some(budget) = budget {
+        budget.charge(steps)?;
+    }
+    Ok(())
+}
+
+pub fn mod_pow(base: i64, exponent: i64, modulus: i64) -> Result<i64, EvaluatorError> {
+    require_modulus("mod_pow", modulus)?;
+    if exponent < 0 {
+        let inverse = mod_inv(base, modulus)?;
+        return mod_pow(inverse, -exponent, modulus);
+    }
+
+    let base = base.rem_euclid(modulus) as u64;
+    Ok(pow_mod_u64(base, exponent as u64, modulus as u64) as i64)
+}
+
+pub fn mod_inv(a: i64, modulus: i64) -> Result<i64, EvaluatorError> {
+    require_modulus("mod_inv", modulus)?;
+
+    // Extended Euclid: keeps old_s * a = old_r (mod modulus).
+    let (mut old_r, mut r) = (a.rem_euclid(modulus) as i128, modulus as i128);
+    let (mut old_s, mut s) = (1i128, 0i128);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_s, s) = (s, old_s - q * s);
+    }
+
+    if old_r != 1 {
+        return Err(EvaluatorError::InvalidArgument(format!(
+            "{} has no inverse modulo {}",
+            a, modulus
+        )));
+    }
+    Ok(old_s.rem_euclid(modulus as i128) as i64)
+}
+
+fn mul_mod_u64(a: u64, b: u64, m: u64) -> u64 {
+    ((a as u128 * b as u128) % m as u128) as u64
+}
+
+fn pow_mod_u64(mut base: u64, mut exponent: u64, m: u64) -> u64 {
+    let mut result = 1 % m;
+    base %= m;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod_u64(result, base, m);
+        }
+        base = mul_mod_u64(base, base, m);
+        exponent >>= 1;
+    }
+    result
+}
+
+fn checked(name: &str, value: Option<i128>) -> Result<i128, EvaluatorError> {
+    value.ok_or_else(|| EvaluatorError::IntegerOverflow(format!("{} overflowed", name)))
+}
+
+fn require_non_negative(name: &str, values: &[i64]) -> Result<(), EvaluatorError> {
+    if let Some(v) = values.iter().find(|&&v| v < 0) {
+        return Err(EvaluatorError::InvalidArgument(format!(
+            "{} expects non-negative integers, got {}",
+            name, v
+        )));
+    }
+    Ok(())
+}
+
+fn require_modulus(name: &str, modulus: i64) -> Result<(), EvaluatorError> {
+    if modulus < 1 {
+        return Err(EvaluatorError::InvalidArgument(format!(
+            "{} expects a positive modulus, got {}",
+            name, modulus
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod number_theory_tests {
+    use super::*;
+    use crate::parsemath::limits::{EvalLimits, LimitError};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(1.5)]
+    #[case(f64::NAN)]
+    fn to_integer_rejects_fractions(#[case] value: f64) {
+        assert!(matches!(
+            to_integer(value),
+            Err(EvaluatorError::NotAnInteger(_))
+        ));
+    }
+
+    #[rstest]
+    fn to_integer_rejects_inexact_values() {
+        assert!(matches!(
+            to_integer(1e20),
+            Err(EvaluatorError::IntegerOverflow(_))
+        ));
+    }
+
+    #[rstest]
+    #[case(5, 2, 10)]
+    #[case(52, 5, 2_598_960)]
+    #[case(10, 0, 1)]
+    #[case(3, 5, 0)]
+    fn binomial_coefficient(#[case] n: i64, #[case] r: i64, #[case] expected: i128) {
+        assert_eq!(ncr(n, r).unwrap(), expected);
+    }
+
+    #[rstest]
+    fn binomial_coefficient_overflow() {
+        assert!(matches!(
+            ncr(200, 100),
+            Err(EvaluatorError::IntegerOverflow(_))
+        ));
+    }
+
+    #[rstest]
+    #[case(5, 2, 20)]
+    #[case(5, 5, 120)]
+    fn permutations(#[case] n: i64, #[case] r: i64, #[case] expected: i128) {
+        assert_eq!(npr(n, r).unwrap(), expected);
+    }
+
+    #[rstest]
+    fn gcd_and_lcm() {
+        assert_eq!(gcd(12, -18), 6);
+        assert_eq!(gcd(0, 5), 5);
+        assert_eq!(lcm(4, 6).unwrap(), 12);
+    }
+
+    #[rstest]
+    #[case(1, false)]
+    #[case(2, true)]
+    #[case(91, false)]
+    #[case(97, true)]
+    #[case(3_215_031_751, false)]
+    #[case(9_007_199_254_740_881, true)]
+    fn primality(#[case] n: i64, #[case] expected: bool) {
+        assert_eq!(is_prime(n), expected);
+    }
+
+    #[rstest]
+    #[case(0, 2)]
+    #[case(13, 17)]
+    #[case(89, 97)]
+    fn next_prime_is_strictly_greater(#[case] n: i64, #[case] expected: i64) {
+        assert_eq!(next_prime(n, None).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case(12, vec![2, 2, 3])]
+    #[case(97, vec![97])]
+    #[case(600_851_475_143, vec![71, 839, 1471, 6857])]
+    fn prime_factorisation(#[case] n: i64, #[case] expected: Vec<i64>) {
+        assert_eq!(factor(n, None).unwrap(), expected);
+    }
+
+    #[rstest]
+    fn factor_charges_the_budget() {
+        let limits = EvalLimits {
+            max_steps: 1000,
+            ..EvalLimits::default()
+        };
+        // 94906247 * 94906249, twin primes just below the square root of 2^53.
+        let semiprime = 9_007_195_909_437_503;
+        assert_eq!(
+            factor(semiprime, Some(&mut Budget::start(&limits))),
+            Err(LimitError::StepBudgetExhausted(1000).into())
+        );
+        assert_eq!(
+            factor(600_851_475_143, Some(&mut Budget::start(&limits))).unwrap(),
+            vec![71, 839, 1471, 6857]
+        );
+    }
+
+    #[rstest]
+    fn modular_arithmetic() {
+        assert_eq!(mod_pow(4, 13, 497).unwrap(), 445);
+        assert_eq!(mod_inv(3, 11).unwrap(), 4);
+        assert_eq!(mod_pow(3, -1, 11).unwrap(), 4);
+        assert!(matches!(
+            mod_inv(2, 4),
+            Err(EvaluatorError::InvalidArgument(_))
+        ));
+    }
+}
@@ -0,0 +1,372 @@
+use crate::parsemath::visitors::EvaluatorError;
+
+const TOLERANCE: f64 = 1e-12;
+const MAX_ITERATIONS: usize = 100;
+// Number of subintervals scanned for sign changes by `find_roots`.
+const SCAN_INTERVALS: usize = 1000;
+
+// Newton's method from the initial guess `x0`, with a central-difference derivative.
+pub fn newton(
+    mut f: impl FnMut(f64) -> Result<f64, EvaluatorError>,
+    x0: f64,
+) -> Result<f64, EvaluatorError> {
+    let mut x = x0;
+
+    for _ in 0..MAX_ITERATIONS {
+        let fx = f(x)?;
+        if fx == 0.0 {
+            return Ok(x);
+        }
+
+        let h = 1e-7 * x.abs().max(1.0);
+        let derivative = (f(x + h)? - f(x - h)?) / (2.0 * h);
+        if derivative == 0.0 || !derivative.is_finite() {
+            return Err(EvaluatorError::NoConvergence(format!(
+                "Newton's method hit a flat spot at {}",
+                x
+            )));
+        }
+
+        let step = fx / derivative;
+        x -= step;
+        if !x.is_finite() {
+            break;
+        }
+        if step.abs() <= TOLERANCE * (1.0 + x.abs()) {
+            return Ok(x);
+        }
+    }
+
+    Err(EvaluatorError::NoConvergence(format!(
+        "Newton's method did not converge from {}",
+        x0
+    )))
+}
+
+// Brent's method on a bracket `[a, b]` where f(a) and f(b) differ in sign.
+pub fn brent(
+    mut f: impl FnMut(f64) -> Result<f64, EvaluatorError>,
+    a: f64,
+    b: f64,
+) -> Result<f64, EvaluatorError> {
+    let (from, to) = (a, b);
+    let (mut a, mut b) = (a, b);
+    let (mut fa, mut fb) = (f(a)?, f(b)?);
+
+    if fa == 0.0 {
+        return Ok(a);
+    }
+    if fb == 0.0 {
+        return Ok(b);
+    }
+    if (fa > 0.0) == (fb > 0.0) {
+        return Err(EvaluatorError::NoSignChange(from, to));
+    }
+
+    let (mut c, mut fc) = (b, fb);
+    let (mut d, mut e) = (b - a, b - a);
+
+    for _ in 0..MAX_ITERATIONS {
+        // Keep the root bracketed between b and c, with b the best estimate.
+        if (fb > 0.0) == (fc > 0.0) {
+            (c, fc) = (a, fa);
+            d = b - a;
+            e = d;
+        }
+        if fc.abs() < fb.abs() {
+            (a, fa) = (b, fb);
+            (b, fb) = (c, fc);
+            (c, fc) = (a, fa);
+        }
+
+        let tolerance = 2.0 * f64::EPSILON * b.abs() + 0.5 * TOLERANCE;
+        let midpoint = 0.5 * (c - b);
+        if midpoint.abs() <= tolerance || fb == 0.0 {
+            return Ok(b);
+        }
+
+        if e.abs() >= tolerance && fa.abs() > fb.abs() {
+            // Try inverse quadratic interpolation, or the secant method when a == c.
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2.0 * midpoint * s, 1.0 - s)
+            } else {
+                let q = fa / fc;
+                let r = fb / fc;
+                (
+                    s * (2.0 * midpoint * q * (q - r) - (b - a) * (r - 1.0)),
+                    (q - 1.0) * (r - 1.0) * (s - 1.0),
+                )
+            };
+            if p > 0.0 {
+                q = -q;
+            } else {
+                p = -p;
+            }
+
+            if 2.0 * p < (3.0 * midpoint * q - (tolerance * q).abs()).min((e * q).abs()) {
+                e = d;
+                d = p / q;
+            } else {
+                d = midpoint;
+                e = d;
+            }
+        } else {
+            d = midpoint;
+            e = d;
+        }
+
+        (a, fa) = (b, fb);
+        b += if d.abs() > tolerance {
+            d
+        } else {
+            tolerance.copysign(midpoint)
+        };
+        fb = f(b)?;
+    }
+
+    Err(EvaluatorError::NoConvergence(format!(
+        "Brent's method did not converge in [{}, {}]",
+        from, to
+    )))
+}
+
+// All roots in `[a, b]`, found by scanning for sign changes and refining each
+// bracket with Brent's method. Sign changes across poles are discarded. Points
+// where `f` fails, like x = 0 for `1 / x` or x < 0 for `sqrt(x)`, are gaps in
+// the scan: the intervals next to them are skipped. Adjacent samples where `f`
+// is exactly zero count as one root, at the first of them; a function that is
+// zero at every sample has no isolated roots and is rejected. It only fails if
+// `f` fails everywhere, or with a limit error, which always ends the search.
+pub fn find_roots(
+    mut f: impl FnMut(f64) -> Result<f64, EvaluatorError>,
+    a: f64,
+    b: f64,
+) -> Result<Vec<f64>, EvaluatorError> {
+    let (a, b) = (a.min(b), a.max(b));
+    if a == b {
+        return match f(a)? {
+            0.0 => Ok(vec![a]),
+            _ => Err(EvaluatorError::NoSignChange(a, b)),
+        };
+    }
+    let step = (b - a) / SCAN_INTERVALS as f64;
+
+    let mut roots: Vec<f64> = Vec::new();
+    let mut zeros = 0;
+    let mut failure = None;
+    let mut x0 = a;
+    let mut f0 = sample(&mut f, x0, &mut failure)?;
+    if f0 == Some(0.0) {
+        roots.push(x0);
+        zeros += 1;
+    }
+
+    for i in 1..=SCAN_INTERVALS {
+        let x1 = if i == SCAN_INTERVALS {
+            b
+        } else {
+            a + step * i as f64
+        };
+        let f1 = sample(&mut f, x1, &mut failure)?;
+
+        match (f0, f1) {
+            (_, Some(0.0)) => {
+                if f0 != Some(0.0) {
+                    roots.push(x1);
+                }
+                zeros += 1;
+            }
+            (Some(y0), Some(y1)) if y0 != 0.0 && (y0 > 0.0) != (y1 > 0.0) => {
+                let refined = brent(&mut f, x0, x1).and_then(|root| Ok((root, f(root)?)));
+                match refined {
+                    Ok((root, y)) if y.abs() <= y0.abs().min(y1.abs()) => roots.push(root),
+                    Err(error @ EvaluatorError::LimitExceeded(_)) => return Err(error),
+                    // A pole, or a gap inside the bracket.
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+
+        (x0, f0) = (x1, f1);
+    }
+
+    match failure {
+        Some((error, samples)) if samples == SCAN_INTERVALS + 1 => Err(error),
+        _ if zeros == SCAN_INTERVALS + 1 => Err(EvaluatorError::InvalidArgument(format!(
+            "the function is zero everywhere in [{}, {}]",
+            a, b
+        ))),
+        _ if roots.is_empty() => Err(EvaluatorError::NoSignChange(a, b)),
+        _ => Ok(roots),
+    }
+}
+
+// `f(x)`, or `None` where it fails. `failure` keeps the first error and counts
+// the failed samples.
+fn sample(
+    f: &mut impl FnMut(f64) -> Result<f64, EvaluatorError>,
+    x: f64,
+    failure: &mut Option<(EvaluatorError, usize)>,
+) -> Result<Option<f64>, EvaluatorError> {
+    match f(x) {
+        Ok(y) => Ok(Some(y)),
+        Err(error @ EvaluatorError::LimitExceeded(_)) => Err(error),
+        Err(error) => {
+            failure.get_or_insert((error, 0)).1 += 1;
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod roots_tests {
+    use super::*;
+    use crate::parsemath::limits::LimitError;
+    use rstest::rstest;
+
+    fn assert_approx_eq(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[rstest]
+    fn newton_finds_square_root_of_two() {
+        let root = newton(|x| Ok(x * x - 2.0), 1.0).unwrap();
+        assert_approx_eq(root, 2f64.sqrt());
+    }
+
+    #[rstest]
+    fn newton_fails_without_real_root() {
+        let result = newton(|x| Ok(x * x + 1.0), 1.0);
+        assert!(matches!(result, Err(EvaluatorError::NoConvergence(_))));
+    }
+
+    #[rstest]
+    #[case(1.0, 2.0, 2f64.sqrt())]
+    #[case(-2.0, -1.0, -(2f64.sqrt()))]
+    fn brent_finds_bracketed_root(#[case] a: f64, #[case] b: f64, #[case] expected: f64) {
+        let root = brent(|x| Ok(x * x - 2.0), a, b).unwrap();
+        assert_approx_eq(root, expected);
+    }
+
+    #[rstest]
+    fn brent_requires_sign_change() {
+        let result = brent(|x| Ok(x * x - 2.0), 2.0, 3.0);
+        assert_eq!(result, Err(EvaluatorError::NoSignChange(2.0, 3.0)));
+    }
+
+    #[rstest]
+    fn find_roots_reports_every_root_in_range() {
+        let roots = find_roots(|x| Ok((x - 1.0) * (x - 2.5) * (x - 4.0)), 0.0, 5.0).unwrap();
+        assert_eq!(roots.len(), 3);
+        for (root, expected) in roots.iter().zip([1.0, 2.5, 4.0]) {
+            assert_approx_eq(*root, expected);
+        }
+    }
+
+    #[rstest]
+    fn find_roots_ignores_poles() {
+        let roots = find_roots(|x| Ok((x - 2.0) / (x - 1.0)), 0.3, 3.0).unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_approx_eq(roots[0], 2.0);
+    }
+
+    #[rstest]
+    fn find_roots_skips_points_where_the_function_fails() {
+        let reciprocal = |x: f64| {
+            if x == 0.0 {
+                return Err(EvaluatorError::DivisionByZero);
+            }
+            Ok(1.0 / x - 1.0)
+        };
+        let roots = find_roots(reciprocal, -2.0, 2.0).unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_approx_eq(roots[0], 1.0);
+
+        let sqrt = |x: f64| {
+            if x < 0.0 {
+                return Err(EvaluatorError::InvalidArgument("negative".to_string()));
+            }
+            Ok(x.sqrt() - 1.0)
+        };
+        let roots = find_roots(sqrt, -1.0, 4.0).unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_approx_eq(roots[0], 1.0);
+    }
+
+    #[rstest]
+    fn find_roots_fails_when_no_point_evaluates() {
+        let result = find_roots(|_| Err(EvaluatorError::DivisionByZero), 0.0, 5.0);
+        assert_eq!(result, Err(EvaluatorError::DivisionByZero));
+    }
+
+    #[rstest]
+    fn find_roots_stops_at_limits() {
+        let limit = LimitError::StepBudgetExhausted(10);
+        let result = find_roots(
+            |x| {
+                if x > 1.0 {
+                    return Err(limit.clone().into());
+                }
+                Ok(x)
+            },
+            -1.0,
+            5.0,
+        );
+        assert_eq!(result, Err(limit.into()));
+    }
+
+    #[rstest]
+    #[case(|x| Ok(x), Ok(vec![0.0]))]
+    #[case(|x| Ok(x - 1.0), Err(EvaluatorError::NoSignChange(0.0, 0.0)))]
+    #[case(|_| Err(EvaluatorError::DivisionByZero), Err(EvaluatorError::DivisionByZero))]
+    fn find_roots_in_an_empty_range_evaluates_once(
+        #[case] f: fn(f64) -> Result<f64, EvaluatorError>,
+        #[case] expected: Result<Vec<f64>, EvaluatorError>,
+    ) {
+        let mut calls = 0;
+        let result = find_roots(
+            |x| {
+                calls += 1;
+                f(x)
+            },
+            0.0,
+            0.0,
+        );
+        assert_eq!(result, expected);
+        assert_eq!(calls, 1);
+    }
+
+    #[rstest]
+    fn find_roots_merges_adjacent_zeros() {
+        // Zero on [1, 2], and at 4.
+        let f = |x: f64| Ok((x - 1.0).min(0.0) + (x - 2.0).max(0.0) * (x - 4.0));
+        let roots = find_roots(f, 0.0, 5.0).unwrap();
+        assert_eq!(roots.len(), 2);
+        assert_approx_eq(roots[0], 1.0);
+        assert_approx_eq(roots[1], 4.0);
+    }
+
+    #[rstest]
+    fn find_roots_rejects_a_function_that_is_zero_everywhere() {
+        let result = find_roots(|x| Ok(x - x), 0.0, 5.0);
+        assert_eq!(
+            result,
+            Err(EvaluatorError::InvalidArgument(
+                "the function is zero everywhere in [0, 5]".to_string()
+            ))
+        );
+    }
+
+    #[rstest]
+    fn find_roots_without_sign_change() {
+        let result = find_roots(|x| Ok(x * x + 1.0), 0.0, 5.0);
+        assert_eq!(result, Err(EvaluatorError::NoSignChange(0.0, 5.0)));
+    }
+}
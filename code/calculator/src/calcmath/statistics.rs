@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use crate::parsemath::visitors::EvaluatorError;
+
+pub fn mean(data: &[f64]) -> Result<f64, EvaluatorError> {
+    require_len("mean", data, 1)?;
+    Ok(data.iter().sum::<f64>() / data.len() as f64)
+}
+
+pub fn median(data: &[f64]) -> Result<f64, EvaluatorError> {
+    require_len("median", data, 1)?;
+    let sorted = sorted(data);
+    let mid = sorted.len() / 2;
+
+    if sorted.len().is_multiple_of(2) {
+        Ok((sorted[mid - 1] + sorted[mid]) / 2.0)
+    } else {
+        Ok(sorted[mid])
+    }
+}
+
+// Most frequent value; ties are resolved in favour of the value seen first.
+pub fn mode(data: &[f64]) -> Result<f64, EvaluatorError> {
+    require_len("mode", data, 1)?;
+
+    // Counts and first positions, keyed by bit pattern with -0 folded into 0.
+    // NaN equals nothing, so it is never counted.
+    let mut counts: HashMap<u64, (usize, usize)> = HashMap::new();
+    for (position, &value) in data.iter().enumerate().filter(|(_, v)| !v.is_nan()) {
+        let key = if value == 0.0 { 0.0 } else { value };
+        counts.entry(key.to_bits()).or_insert((0, position)).0 += 1;
+    }
+
+    let best = counts
+        .values()
+        .max_by(|(count_a, first_a), (count_b, first_b)| {
+            count_a.cmp(count_b).then(first_b.cmp(first_a))
+        })
+        .map_or(0, |&(_, first)| first);
+    Ok(data[best])
+}
+
+// Sample variance divides by n - 1, population variance by n.
+pub fn variance(data: &[f64], sample: bool) -> Result<f64, EvaluatorError> {
+    let name = if sample { "variance" } else { "pvariance" };
+    spread(name, data, sample)
+}
+
+pub fn stdev(data: &[f64], sample: bool) -> Result<f64, EvaluatorError> {
+    let name = if sample { "stdev" } else { "pstdev" };
+    Ok(spread(name, data, sample)?.sqrt())
+}
+
+// The variance, reporting errors under the name of the calling function.
+fn spread(name: &str, data: &[f64], sample: bool) -> Result<f64, EvaluatorError> {
+    require_len(name, data, if sample { 2 } else { 1 })?;
+
+    let m = mean(data)?;
+    let sum_of_squares: f64 = data.iter().map(|v| (v - m).powi(2)).sum();
+    let n = data.len() as f64;
+
+    Ok(if sample {
+        sum_of_squares / (n - 1.0)
+    } else {
+        sum_of_squares / n
+    })
+}
+
+// Percentile `p` in 0..=100, linearly interpolated between closest ranks.
+pub fn percentile(data: &[f64], p: f64) -> Result<f64, EvaluatorError> {
+    require_len("percentile", data, 1)?;
+    if !(0.0..=100.0).contains(&p) {
+        return Err(EvaluatorError::InvalidArgument(format!(
+            "percentile must be between 0 and 100, got {}",
+            p
+        )));
+    }
+
+    let sorted = sorted(data);
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+
+    Ok(sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64))
+}
+
+pub fn min(data: &[f64]) -> Result<f64, EvaluatorError> {
+    require_len("min", data, 1)?;
+    Ok(data.iter().copied().fold(f64::INFINITY, f64::min))
+}
+
+pub fn max(data: &[f64]) -> Result<f64, EvaluatorError> {
+    require_len("max", data, 1)?;
+    Ok(data.iter().copied().fold(f64::NEG_INFINITY, f64::max))
+}
+
+pub fn range(data: &[f64]) -> Result<f64, EvaluatorError> {
+    require_len("range", data, 1)?;
+    Ok(max(data)? - min(data)?)
+}
+
+// Pearson correlation coefficient.
+pub fn correlation(xs: &[f64], ys: &[f64]) -> Result<f64, EvaluatorError> {
+    let (sxx, syy, sxy) = co_moments("correlation", xs, ys)?;
+    if sxx == 0.0 || syy == 0.0 {
+        return Err(EvaluatorError::InvalidArgument(
+            "correlation is undefined for constant data".to_string(),
+        ));
+    }
+
+    Ok(sxy / (sxx * syy).sqrt())
+}
+
+// Least-squares fit `y = slope * x + intercept`, returned as `(slope, intercept)`.
+pub fn linreg(xs: &[f64], ys: &[f64]) -> Result<(f64, f64), EvaluatorError> {
+    let (sxx, _, sxy) = co_moments("linreg", xs, ys)?;
+    if sxx == 0.0 {
+        return Err(EvaluatorError::InvalidArgument(
+            "linreg is undefined for constant x values".to_string(),
+        ));
+    }
+
+    let slope = sxy / sxx;
+    Ok((slope, mean(ys)? - slope * mean(xs)?))
+}
+
+// Sums of squared deviations of xs and ys, and of their cross products.
+fn co_moments(name: &str, xs: &[f64], ys: &[f64]) -> Result<(f64, f64, f64), EvaluatorError> {
+    if xs.len() != ys.len() {
+        return Err(EvaluatorError::DimensionMismatch(format!(
+            "{} requires samples of equal length, got {} and {}",
+            name,
+            xs.len(),
+            ys.len()
+        )));
+    }
+    require_len(name, xs, 2)?;
+
+    let (mx, my) = (mean(xs)?, mean(ys)?);
+    let mut moments = (0.0, 0.0, 0.0);
+    for (x, y) in xs.iter().zip(ys) {
+        moments.0 += (x - mx).powi(2);
+        moments.1 += (y - my).powi(2);
+        moments.2 += (x - mx) * (y - my);
+    }
+
+    Ok(moments)
+}
+
+fn sorted(data: &[f64]) -> Vec<f64> {
+    let mut sorted = data.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    sorted
+}
+
+fn require_len(name: &str, data: &[f64], min_len: usize) -> Result<(), EvaluatorError> {
+    if data.len() < min_len {
+        return Err(EvaluatorError::InvalidArgument(format!(
+            "{} requires at least {} value(s), got {}",
+            name,
+            min_len,
+            data.len()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod statistics_tests {
+    use super::*;
+    use rstest::rstest;
+
+    const DATA: [f64; 8] = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+
+    fn assert_approx_eq(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[rstest]
+    fn central_tendency() {
+        assert_approx_eq(mean(&DATA).unwrap(), 5.0);
+        assert_approx_eq(median(&DATA).unwrap(), 4.5);
+        assert_approx_eq(median(&[3.0, 1.0, 2.0]).unwrap(), 2.0);
+        assert_approx_eq(mode(&DATA).unwrap(), 4.0);
+    }
+
+    #[rstest]
+    #[case(&[3.0, 1.0, 1.0, 3.0], 3.0)]
+    #[case(&[1.0, 2.0, 3.0], 1.0)]
+    #[case(&[-0.0, 5.0, 0.0], -0.0)]
+    #[case(&[f64::NAN, 2.0], 2.0)]
+    fn mode_prefers_the_value_seen_first(#[case] data: &[f64], #[case] expected: f64) {
+        assert_eq!(mode(data).unwrap().to_bits(), expected.to_bits());
+    }
+
+    #[rstest]
+    fn spread() {
+        assert_approx_eq(variance(&DATA, false).unwrap(), 4.0);
+        assert_approx_eq(stdev(&DATA, false).unwrap(), 2.0);
+        assert_approx_eq(variance(&DATA, true).unwrap(), 32.0 / 7.0);
+        assert_approx_eq(range(&DATA).unwrap(), 7.0);
+    }
+
+    #[rstest]
+    #[case(0.0, 2.0)]
+    #[case(50.0, 4.5)]
+    #[case(100.0, 9.0)]
+    #[case(25.0, 4.0)]
+    fn percentile_interpolates(#[case] p: f64, #[case] expected: f64) {
+        assert_approx_eq(percentile(&DATA, p).unwrap(), expected);
+    }
+
+    #[rstest]
+    fn percentile_out_of_range() {
+        assert!(matches!(
+            percentile(&DATA, 101.0),
+            Err(EvaluatorError::InvalidArgument(_))
+        ));
+    }
+
+    #[rstest]
+    fn correlation_and_linreg() {
+        let xs = [1.0, 2.0, 3.0, 4.0];
+        let ys = [3.0, 5.0, 7.0, 9.0];
+        assert_approx_eq(correlation(&xs, &ys).unwrap(), 1.0);
+
+        let (slope, intercept) = linreg(&xs, &ys).unwrap();
+        assert_approx_eq(slope, 2.0);
+        assert_approx_eq(intercept, 1.0);
+    }
+
+    #[rstest]
+    fn paired_samples_must_have_equal_length() {
+        let result = correlation(&[1.0, 2.0], &[1.0]);
+        assert!(matches!(result, Err(EvaluatorError::DimensionMismatch(_))));
+    }
+
+    #[rstest]
+    fn sample_variance_requires_two_values() {
+        assert_eq!(
+            variance(&[1.0], true),
+            Err(EvaluatorError::InvalidArgument(
+                "variance requires at least 2 value(s), got 1".to_string()
+            ))
+        );
+        assert_eq!(
+            stdev(&[1.0], true),
+            Err(EvaluatorError::InvalidArgument(
+                "stdev requires at least 2 value(s), got 1".to_string()
+            ))
+        );
+        assert_eq!(
+            stdev(&[], false),
+            Err(EvaluatorError::InvalidArgument(
+                "pstdev requires at least 1 value(s), got 0".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+
+use crate::calcmath::matrix::Matrix;
+use crate::parsemath::visitors::EvaluatorError;
+
+// Result of evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Matrix(Matrix),
+}
+
+impl Value {
+    pub fn as_number(&self) -> Result<f64, EvaluatorError> {
+        match self {
+            Value::Number(n) => Ok(*n),
+            Value::Matrix(_) => Err(EvaluatorError::TypeMismatch(
+                "expected a number, got a matrix".to_string(),
+            )),
+        }
+    }
+
+    pub fn as_matrix(&self) -> Result<&Matrix, EvaluatorError> {
+        match self {
+            Value::Matrix(m) => Ok(m),
+            Value::Number(_) => Err(EvaluatorError::TypeMismatch(
+                "expected a matrix, got a number".to_string(),
+            )),
+        }
+    }
+
+    // Number of elements.
+    pub fn size(&self) -> usize {
+        match self {
+            Value::Number(_) => 1,
+            Value::Matrix(m) => m.rows() * m.cols(),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl From<Matrix> for Value {
+    fn from(value: Matrix) -> Self {
+        Value::Matrix(value)
+    }
+}
+
+impl PartialEq<f64> for Value {
+    fn eq(&self, other: &f64) -> bool {
+        matches!(self, Value::Number(n) if n == other)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Matrix(m) => write!(f, "{}", m),
+        }
+    }
+}
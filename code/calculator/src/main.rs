@@ -26,7 +26,7 @@ impl Console for Terminal {
 }
 
 fn main() {
-    let mut terminal = Terminal{};
-    let mut calc = Calculator::new(&mut terminal);
+    let terminal = Terminal{};
+    let mut calc = Calculator::new(&terminal);
     calc.run();
 }
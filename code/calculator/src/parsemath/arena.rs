@@ -0,0 +1,542 @@
+use super::constructors::{PRIMARY, UNARY};
+use super::limits::{EvalLimits, LimitError};
+use super::parser::Expression;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher, RandomState};
+
+// Index of a node in an `ExpressionArena`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(u32);
+
+// A number compared and hashed by its bits, so that identical literals (even NaN)
+// are shared and `0` and `-0` are not.
+#[derive(Debug, Clone, Copy)]
+pub struct Literal(pub f64);
+
+impl PartialEq for Literal {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Literal {}
+
+impl Hash for Literal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+// An `Expression` node whose children are arena indices.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Node {
+    Number(Literal),
+    Add(ExprId, ExprId),
+    Subtract(ExprId, ExprId),
+    Multiply(ExprId, ExprId),
+    Divide(ExprId, ExprId),
+    Power(ExprId, ExprId),
+    Negate(ExprId),
+    Grouping(ExprId),
+    Variable(String),
+    Equation(ExprId, ExprId),
+    Range(ExprId, ExprId),
+    List(Vec<ExprId>),
+    Call(String, Vec<ExprId>),
+    Index(ExprId, Vec<ExprId>),
+    Error,
+}
+
+// Stores expression trees as a flat vector of nodes referring to each other by
+// index. Nodes are hash-consed: adding a node equal to an existing one returns
+// the existing id, so repeated sub-trees such as the two `(x + 1)` in
+// `(x + 1) * (x + 1)` are stored once and compare equal by id. Building,
+// converting, visiting and dropping never recurse, so arbitrarily deep trees are
+// safe.
+#[derive(Debug, Default)]
+pub struct ExpressionArena {
+    nodes: Vec<Node>,
+    // Ids of the nodes with each hash, to find an existing equal node.
+    ids: HashMap<u64, Vec<ExprId>>,
+    hasher: RandomState,
+}
+
+// The id-based counterpart of `ExpressionVisitor`. `ExpressionArena::visit` calls
+// `visit_node` once per distinct node, children first, with the results for the
+// node's children in order; a sub-tree shared by several parents is visited once.
+pub trait NodeVisitor<T, E> {
+    fn visit_node(&mut self, arena: &ExpressionArena, id: ExprId, children: Vec<T>)
+    -> Result<T, E>;
+
+    // Called before the children of `id` are visited. Returning a result skips
+    // them, for nodes that need their children unvisited, like `solve(...)` in
+    // the evaluator.
+    fn enter(&mut self, _arena: &ExpressionArena, _id: ExprId) -> Option<Result<T, E>> {
+        None
+    }
+
+    // The order in which the children of `id` are visited, as indices into
+    // `arena.children(id)`; left to right unless overridden.
+    fn child_order(&self, arena: &ExpressionArena, id: ExprId) -> Vec<usize> {
+        (0..arena.children(id).len()).collect()
+    }
+
+    // Called with the result for the `index`-th child of `id` as soon as it is
+    // visited. Returning an error stops the visit before the remaining children,
+    // like a zero divisor does before its dividend in the evaluator.
+    fn child_visited(
+        &mut self,
+        _arena: &ExpressionArena,
+        _id: ExprId,
+        _index: usize,
+        _result: &T,
+    ) -> Result<(), E> {
+        Ok(())
+    }
+
+    // The whole visit of the tree `id`, which `ExpressionArena::visit` delegates
+    // to. Like `ExpressionVisitor::visit_expression`, override it to wrap the
+    // visit, as `Evaluator` does to apply its limits.
+    fn visit_tree(&mut self, arena: &ExpressionArena, id: ExprId) -> Result<T, E>
+    where
+        Self: Sized,
+        T: Clone,
+    {
+        arena.walk(id, self)
+    }
+}
+
+impl ExpressionArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Number of distinct nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get(&self, id: ExprId) -> &Node {
+        &self.nodes[id.0 as usize]
+    }
+
+    // Adds `node`, whose children must already be in this arena.
+    pub fn add(&mut self, node: Node) -> ExprId {
+        let same_hash = self.ids.entry(self.hasher.hash_one(&node)).or_default();
+        if let Some(id) = same_hash
+            .iter()
+            .find(|id| self.nodes[id.0 as usize] == node)
+        {
+            return *id;
+        }
+        let id = ExprId(u32::try_from(self.nodes.len()).expect("arena holds at most 2^32 nodes"));
+        same_hash.push(id);
+        self.nodes.push(node);
+        id
+    }
+
+    pub fn insert(&mut self, expr: &Expression) -> ExprId {
+        enum Task<'e> {
+            Visit(&'e Expression),
+            Build(&'e Expression),
+        }
+
+        let mut tasks = vec![Task::Visit(expr)];
+        let mut ids: Vec<ExprId> = Vec::new();
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Visit(expr) => {
+                    tasks.push(Task::Build(expr));
+                    tasks.extend(expr.children().into_iter().rev().map(Task::Visit));
+                }
+                Task::Build(expr) => {
+                    let children = ids.split_off(ids.len() - expr.children().len());
+                    let node = match expr {
+                        Expression::Number(n) => Node::Number(Literal(*n)),
+                        Expression::Add(..) => Node::Add(children[0], children[1]),
+                        Expression::Subtract(..) => Node::Subtract(children[0], children[1]),
+                        Expression::Multiply(..) => Node::Multiply(children[0], children[1]),
+                        Expression::Divide(..) => Node::Divide(children[0], children[1]),
+                        Expression::Power(..) => Node::Power(children[0], children[1]),
+                        Expression::Negate(_) => Node::Negate(children[0]),
+                        Expression::Grouping(_) => Node::Grouping(children[0]),
+                        Expression::Variable(name) => Node::Variable(name.clone()),
+                        Expression::Equation(..) => Node::Equation(children[0], children[1]),
+                        Expression::Range(..) => Node::Range(children[0], children[1]),
+                        Expression::List(_) => Node::List(children),
+                        Expression::Call(name, _) => Node::Call(name.clone(), children),
+                        Expression::Index(..) => Node::Index(children[0], children[1..].to_vec()),
+                        Expression::Error => Node::Error,
+                    };
+                    ids.push(self.add(node));
+                }
+            }
+        }
+        ids.pop().expect("the root was built")
+    }
+
+    // Rebuilds the boxed tree for `id`, expanding shared sub-trees.
+    pub fn to_expression(&self, id: ExprId) -> Expression {
+        enum Task {
+            Visit(ExprId),
+            Build(ExprId),
+        }
+
+        let mut tasks = vec![Task::Visit(id)];
+        let mut built: Vec<Expression> = Vec::new();
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Visit(id) => {
+                    tasks.push(Task::Build(id));
+                    tasks.extend(self.children(id).into_iter().rev().map(Task::Visit));
+                }
+                Task::Build(id) => {
+                    let children = built.split_off(built.len() - self.children(id).len());
+                    let expr = build(self.get(id), children);
+                    built.push(expr);
+                }
+            }
+        }
+        built.pop().expect("the root was built")
+    }
+
+    // Runs `visitor` over the tree `id`, through `NodeVisitor::visit_tree`.
+    pub fn visit<T: Clone, E>(
+        &self,
+        id: ExprId,
+        visitor: &mut impl NodeVisitor<T, E>,
+    ) -> Result<T, E> {
+        visitor.visit_tree(self, id)
+    }
+
+    // Walks the tree `id` node by node with an explicit work stack, remembering
+    // the result for each node so that shared sub-trees are visited once.
+    pub fn walk<T: Clone, E>(
+        &self,
+        id: ExprId,
+        visitor: &mut impl NodeVisitor<T, E>,
+    ) -> Result<T, E> {
+        enum Task {
+            Visit(ExprId),
+            Visited(ExprId, usize, ExprId),
+            Build(ExprId),
+        }
+
+        let mut tasks = vec![Task::Visit(id)];
+        let mut results: HashMap<ExprId, T> = HashMap::new();
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Visit(id) if results.contains_key(&id) => {}
+                Task::Visit(id) => match visitor.enter(self, id) {
+                    Some(result) => {
+                        results.insert(id, result?);
+                    }
+                    None => {
+                        let children = self.children(id);
+                        tasks.push(Task::Build(id));
+                        for index in visitor.child_order(self, id).into_iter().rev() {
+                            tasks.push(Task::Visited(id, index, children[index]));
+                            tasks.push(Task::Visit(children[index]));
+                        }
+                    }
+                },
+                Task::Visited(id, index, child) => {
+                    visitor.child_visited(self, id, index, &results[&child])?;
+                }
+                Task::Build(id) => {
+                    let children = self
+                        .children(id)
+                        .iter()
+                        .map(|c| results[c].clone())
+                        .collect();
+                    let result = visitor.visit_node(self, id, children)?;
+                    results.insert(id, result);
+                }
+            }
+        }
+        Ok(results.remove(&id).expect("the root was visited"))
+    }
+
+    // `limits::check_tree` for the tree `id`, as if its shared sub-trees were
+    // expanded: an arena of 40 nodes can stand for a tree of 2^40. Children are
+    // added before their parents, so one pass over the ids up to `id` sizes
+    // every sub-tree without recursing.
+    pub fn check_limits(&self, id: ExprId, limits: &EvalLimits) -> Result<(), LimitError> {
+        let mut sizes: Vec<(usize, usize)> = Vec::with_capacity(id.0 as usize + 1);
+        for (parent, node) in self.nodes[..=id.0 as usize].iter().enumerate() {
+            let children = self.children(ExprId(parent as u32));
+            let (mut nodes, mut depth) = (1usize, 0);
+            for (index, child) in children.iter().enumerate() {
+                let (child_nodes, child_depth) = sizes[child.0 as usize];
+                nodes = nodes.saturating_add(child_nodes);
+                depth = depth.max(child_depth + nests(node, index) as usize);
+            }
+            sizes.push((nodes, depth));
+        }
+        let (nodes, depth) = sizes[id.0 as usize];
+        if depth > limits.max_depth {
+            return Err(LimitError::TooDeeplyNested(limits.max_depth));
+        }
+        if nodes > limits.max_nodes {
+            return Err(LimitError::TooManyNodes(limits.max_nodes));
+        }
+        Ok(())
+    }
+
+    // `constructors::precedence` of the node `id`.
+    pub(crate) fn precedence(&self, id: ExprId) -> u8 {
+        match self.get(id) {
+            Node::Equation(..) | Node::Range(..) => 0,
+            Node::Add(..) | Node::Subtract(..) => 1,
+            Node::Multiply(..) | Node::Divide(..) => 2,
+            Node::Negate(..) => UNARY,
+            Node::Number(n) if n.0.is_sign_negative() => UNARY,
+            Node::Power(..) => 4,
+            _ => PRIMARY,
+        }
+    }
+
+    pub fn children(&self, id: ExprId) -> Vec<ExprId> {
+        match self.get(id) {
+            Node::Number(_) | Node::Variable(_) | Node::Error => vec![],
+            Node::Add(a, b)
+            | Node::Subtract(a, b)
+            | Node::Multiply(a, b)
+            | Node::Divide(a, b)
+            | Node::Power(a, b)
+            | Node::Equation(a, b)
+            | Node::Range(a, b) => vec![*a, *b],
+            Node::Negate(e) | Node::Grouping(e) => vec![*e],
+            Node::List(elements) | Node::Call(_, elements) => elements.clone(),
+            Node::Index(target, indices) => {
+                let mut children = vec![*target];
+                children.extend(indices);
+                children
+            }
+        }
+    }
+}
+
+// `limits::nests` for arena nodes.
+fn nests(node: &Node, index: usize) -> bool {
+    match node {
+        Node::Add(..)
+        | Node::Subtract(..)
+        | Node::Multiply(..)
+        | Node::Divide(..)
+        | Node::Equation(..)
+        | Node::Range(..) => false,
+        Node::Power(..) | Node::Index(..) => index > 0,
+        _ => true,
+    }
+}
+
+// The expression for `node` given its already built children, in order.
+fn build(node: &Node, children: Vec<Expression>) -> Expression {
+    let mut children = children.into_iter();
+    let mut next = || Box::new(children.next().expect("child was built"));
+    match node {
+        Node::Number(n) => Expression::Number(n.0),
+        Node::Add(..) => Expression::Add(next(), next()),
+        Node::Subtract(..) => Expression::Subtract(next(), next()),
+        Node::Multiply(..) => Expression::Multiply(next(), next()),
+        Node::Divide(..) => Expression::Divide(next(), next()),
+        Node::Power(..) => Expression::Power(next(), next()),
+        Node::Negate(_) => Expression::Negate(next()),
+        Node::Grouping(_) => Expression::Grouping(next()),
+        Node::Variable(name) => Expression::Variable(name.clone()),
+        Node::Equation(..) => Expression::Equation(next(), next()),
+        Node::Range(..) => Expression::Range(next(), next()),
+        Node::List(_) => Expression::List(children.collect()),
+        Node::Call(name, _) => Expression::Call(name.clone(), children.collect()),
+        Node::Index(..) => {
+            let target = next();
+            Expression::Index(target, children.collect())
+        }
+        Node::Error => Expression::Error,
+    }
+}
+
+#[cfg(test)]
+mod arena_tests {
+    use super::*;
+    use crate::calcmath::value::Value;
+    use crate::parsemath::limits::{DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES};
+    use crate::parsemath::parser::Parser;
+    use crate::parsemath::visitors::{
+        Evaluator, EvaluatorError, ExpressionVisitor, PrettyPrinterVisitor,
+    };
+    use rstest::rstest;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input).unwrap().parse().unwrap()
+    }
+
+    #[rstest]
+    #[case("1 + 2 * x")]
+    #[case("-(a - b) ^ 2 / sqrt(c)")]
+    #[case("solve(x ^ 2 = 2, x, 0..5)")]
+    #[case("max([1, 2], [[3, 4]][1, 2], f())")]
+    fn round_trips(#[case] input: &str) {
+        let ast = parse(input);
+        let mut arena = ExpressionArena::new();
+        let id = arena.insert(&ast);
+        assert_eq!(arena.to_expression(id), ast);
+    }
+
+    #[rstest]
+    fn shares_identical_sub_trees() {
+        let mut arena = ExpressionArena::new();
+        let id = arena.insert(&parse("(x + 1) * (x + 1)"));
+        // x, 1, x + 1, (x + 1) and the product.
+        assert_eq!(arena.len(), 5);
+        match arena.get(id) {
+            Node::Multiply(left, right) => assert_eq!(left, right),
+            node => panic!("unexpected {:?}", node),
+        }
+
+        let again = arena.insert(&parse("x + 1"));
+        assert_eq!(arena.len(), 5);
+        assert_eq!(arena.children(arena.children(id)[0]), [again]);
+    }
+
+    #[rstest]
+    fn numbers_are_shared_by_bits() {
+        let mut arena = ExpressionArena::new();
+        let zero = arena.add(Node::Number(Literal(0.0)));
+        let negative_zero = arena.add(Node::Number(Literal(-0.0)));
+        let nan = arena.add(Node::Number(Literal(f64::NAN)));
+        assert_ne!(zero, negative_zero);
+        assert_eq!(arena.add(Node::Number(Literal(f64::NAN))), nan);
+    }
+
+    #[rstest]
+    fn visitors_run_on_arena_trees() {
+        let mut arena = ExpressionArena::new();
+        let id = arena.insert(&parse("(x + 1) * (x + 1)"));
+        assert_eq!(
+            arena.visit(id, &mut Evaluator::new().with_variable("x", 2.0)),
+            Ok(9.0.into())
+        );
+        assert_eq!(
+            arena.visit(id, &mut PrettyPrinterVisitor::new()),
+            Ok("(x + 1) * (x + 1)".to_string())
+        );
+    }
+
+    #[rstest]
+    #[case("-(a - b) ^ 2 / sqrt(c)", Ok(-1.0))]
+    #[case("[[1, 2], [3, 4]][2, 1] * a", Ok(3.0))]
+    #[case("solve(x ^ 2 = 4, x, 0..5) + integrate(t, t, 0, a)", Ok(2.5))]
+    #[case("diff(x ^ 2, x, a)", Ok(2.0))]
+    #[case("a / (b - 3)", Err(EvaluatorError::DivisionByZero))]
+    #[case("a = 1", Err(EvaluatorError::UnexpectedExpression("equations are only allowed inside solve()".to_string())))]
+    #[case("y / z", Err(EvaluatorError::UnknownVariable("z".to_string())))]
+    #[case("y / (a - 1)", Err(EvaluatorError::DivisionByZero))]
+    #[case("a[y]", Err(EvaluatorError::TypeMismatch("expected a matrix, got a number".to_string())))]
+    fn evaluates_like_the_tree(#[case] input: &str, #[case] expected: Result<f64, EvaluatorError>) {
+        let evaluator = || {
+            Evaluator::new()
+                .with_variable("a", 1.0)
+                .with_variable("b", 3.0)
+                .with_variable("c", 16.0)
+        };
+        let mut arena = ExpressionArena::new();
+        let id = arena.insert(&parse(input));
+        let expected = expected.map(Value::Number);
+        assert_eq!(arena.visit(id, &mut evaluator()), expected);
+        assert_eq!(evaluator().visit_expression(&parse(input)), expected);
+    }
+
+    #[rstest]
+    #[case("-(a - b) ^ 2 / sqrt(c)")]
+    #[case("1 - (2 - 3) * -x ^ -y")]
+    #[case("solve(x ^ 2 = 2, x, 0..5)")]
+    #[case("max([1, 2], [[3, 4]][1, 2], f())")]
+    fn prints_like_the_tree(#[case] input: &str) {
+        let ast = parse(input);
+        let mut arena = ExpressionArena::new();
+        let id = arena.insert(&ast);
+        for keep_groupings in [true, false] {
+            let printer = || PrettyPrinterVisitor::new().with_groupings(keep_groupings);
+            assert_eq!(
+                arena.visit(id, &mut printer()),
+                printer().visit_expression(&ast)
+            );
+        }
+    }
+
+    #[rstest]
+    fn deep_trees_do_not_overflow() {
+        let mut arena = ExpressionArena::new();
+        let mut id = arena.add(Node::Variable("x".to_string()));
+        for _ in 0..1_000_000 {
+            id = arena.add(Node::Negate(id));
+        }
+        assert_eq!(arena.len(), 1_000_001);
+        let limits = EvalLimits {
+            max_depth: usize::MAX,
+            max_nodes: usize::MAX,
+            ..EvalLimits::default()
+        };
+        let mut evaluator = Evaluator::new().with_variable("x", 2.0);
+        assert_eq!(
+            arena.visit(id, &mut evaluator),
+            Err(LimitError::TooDeeplyNested(DEFAULT_MAX_DEPTH).into())
+        );
+        assert_eq!(
+            arena.visit(id, &mut evaluator.with_limits(limits)),
+            Ok(2.0.into())
+        );
+    }
+
+    #[rstest]
+    fn shared_sub_trees_are_visited_once() {
+        let mut arena = ExpressionArena::new();
+        let mut id = arena.add(Node::Variable("x".to_string()));
+        for _ in 0..40 {
+            id = arena.add(Node::Add(id, id));
+        }
+        assert_eq!(arena.len(), 41);
+        let limits = EvalLimits {
+            max_nodes: usize::MAX,
+            max_steps: 41,
+            ..EvalLimits::default()
+        };
+        let mut evaluator = Evaluator::new().with_variable("x", 1.0);
+        assert_eq!(
+            arena.visit(id, &mut evaluator),
+            Err(LimitError::TooManyNodes(DEFAULT_MAX_NODES).into())
+        );
+        assert_eq!(
+            arena.visit(id, &mut evaluator.with_limits(limits)),
+            Ok(2f64.powi(40).into())
+        );
+    }
+
+    #[rstest]
+    #[case("1 + 2 * 3", 5, Ok(7.0))]
+    #[case("1 + 2 * 3", 4, Err(LimitError::StepBudgetExhausted(4).into()))]
+    #[case("mean([1, 2, 3])", 8, Ok(2.0))]
+    #[case("mean([1, 2, 3])", 7, Err(LimitError::StepBudgetExhausted(7).into()))]
+    fn step_budget_applies(
+        #[case] input: &str,
+        #[case] max_steps: u64,
+        #[case] expected: Result<f64, EvaluatorError>,
+    ) {
+        let limits = EvalLimits {
+            max_steps,
+            ..EvalLimits::default()
+        };
+        let mut arena = ExpressionArena::new();
+        let id = arena.insert(&parse(input));
+        assert_eq!(
+            arena.visit(id, &mut Evaluator::new().with_limits(limits)),
+            expected.map(Value::Number)
+        );
+    }
+}
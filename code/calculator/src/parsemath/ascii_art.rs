@@ -0,0 +1,422 @@
+use std::fmt;
+
+use super::constructors::{PRIMARY, UNARY};
+use super::parser::Expression;
+use super::render::Renderer;
+use super::visitors::ExpressionVisitor;
+
+// A rectangular block of text with a baseline, the row that lines up with its
+// neighbours when boxes are placed side by side. Every line has the same width.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextBox {
+    lines: Vec<String>,
+    baseline: usize,
+}
+
+impl TextBox {
+    pub fn text(text: &str) -> TextBox {
+        TextBox {
+            lines: vec![text.to_string()],
+            baseline: 0,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.lines[0].chars().count()
+    }
+
+    pub fn height(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn baseline(&self) -> usize {
+        self.baseline
+    }
+
+    // Places boxes left to right with their baselines aligned.
+    pub fn beside(boxes: &[TextBox]) -> TextBox {
+        let above = boxes.iter().map(|b| b.baseline).max().unwrap_or(0);
+        let below = boxes
+            .iter()
+            .map(|b| b.height() - b.baseline - 1)
+            .max()
+            .unwrap_or(0);
+
+        let mut lines = vec![String::new(); above + below + 1];
+        for b in boxes {
+            let top = above - b.baseline;
+            for (row, line) in lines.iter_mut().enumerate() {
+                match row.checked_sub(top).and_then(|r| b.lines.get(r)) {
+                    Some(text) => line.push_str(text),
+                    None => line.push_str(&" ".repeat(b.width())),
+                }
+            }
+        }
+        TextBox {
+            lines,
+            baseline: above,
+        }
+    }
+
+    // Stacks `numerator` over `denominator`, centred on a bar of dashes that
+    // becomes the baseline.
+    pub fn fraction(numerator: &TextBox, denominator: &TextBox) -> TextBox {
+        let width = numerator.width().max(denominator.width());
+        let mut lines = numerator.centered(width);
+        lines.push("-".repeat(width));
+        lines.extend(denominator.centered(width));
+        TextBox {
+            lines,
+            baseline: numerator.height(),
+        }
+    }
+
+    // Raises `exponent` to the upper right of `base`.
+    pub fn superscript(base: &TextBox, exponent: &TextBox) -> TextBox {
+        let lines = exponent
+            .lines
+            .iter()
+            .map(|line| format!("{}{}", " ".repeat(base.width()), line))
+            .chain(
+                base.lines
+                    .iter()
+                    .map(|line| format!("{}{}", line, " ".repeat(exponent.width()))),
+            )
+            .collect();
+        TextBox {
+            lines,
+            baseline: exponent.height() + base.baseline,
+        }
+    }
+
+    // Draws a radical sign with an overline across the whole content.
+    pub fn radical(content: &TextBox) -> TextBox {
+        let height = content.height();
+        let mut lines = vec![format!(
+            "{}{}",
+            " ".repeat(height + 1),
+            "_".repeat(content.width() + 1)
+        )];
+        for (row, line) in content.lines.iter().enumerate() {
+            let sign = if row + 1 == height {
+                format!("\\/{}", " ".repeat(height - 1))
+            } else {
+                format!("{}/{}", " ".repeat(height - row), " ".repeat(row))
+            };
+            lines.push(format!("{} {}", sign, line));
+        }
+        TextBox {
+            lines,
+            baseline: content.baseline + 1,
+        }
+    }
+
+    // Wraps the box in delimiters that stretch to its height, e.g. `(`/`)` or `|`/`|`.
+    pub fn delimited(&self, left: char, right: char) -> TextBox {
+        let height = self.height();
+        let lines = self
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(row, line)| {
+                let (l, r) = match (left, height) {
+                    (_, 1) => (left, right),
+                    ('(', _) if row == 0 => ('/', '\\'),
+                    ('(', _) if row + 1 == height => ('\\', '/'),
+                    ('(', _) => ('|', '|'),
+                    _ => (left, right),
+                };
+                format!("{}{}{}", l, line, r)
+            })
+            .collect();
+        TextBox {
+            lines,
+            baseline: self.baseline,
+        }
+    }
+
+    fn centered(&self, width: usize) -> Vec<String> {
+        let left = (width - self.width()) / 2;
+        let right = width - self.width() - left;
+        self.lines
+            .iter()
+            .map(|line| format!("{}{}{}", " ".repeat(left), line, " ".repeat(right)))
+            .collect()
+    }
+}
+
+impl fmt::Display for TextBox {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines: Vec<&str> = self.lines.iter().map(|line| line.trim_end()).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+// Lays an expression out in two dimensions, with stacked fractions, raised
+// exponents and radical signs:
+//
+//          2
+//         x  + 1
+//     y = ------
+//           ____
+//         \/ 2*z
+pub struct AsciiArtVisitor;
+
+impl Renderer<TextBox> for AsciiArtVisitor {
+    const STACKED_FRACTIONS: bool = true;
+
+    fn bracket(content: TextBox) -> TextBox {
+        content.delimited('(', ')')
+    }
+
+    fn infix(left: TextBox, operator: &str, right: TextBox) -> TextBox {
+        TextBox::beside(&[left, TextBox::text(operator), right])
+    }
+
+    // `a, b, c` laid out on a shared baseline.
+    fn separated(items: Vec<TextBox>) -> TextBox {
+        let mut boxes = Vec::new();
+        for (i, item) in items.into_iter().enumerate() {
+            if i > 0 {
+                boxes.push(TextBox::text(", "));
+            }
+            boxes.push(item);
+        }
+        TextBox::beside(&boxes)
+    }
+}
+
+impl AsciiArtVisitor {
+    // Rows of a matrix with each column padded to its widest cell.
+    fn matrix(&mut self, rows: &[Vec<Expression>]) -> Result<TextBox, ()> {
+        let cells = rows
+            .iter()
+            .map(|row| row.iter().map(|e| self.visit_expression(e)).collect())
+            .collect::<Result<Vec<Vec<TextBox>>, ()>>()?;
+        let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|c| {
+                cells
+                    .iter()
+                    .filter_map(|row| row.get(c).map(TextBox::width))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let mut lines = Vec::new();
+        for row in &cells {
+            let mut boxes = Vec::new();
+            for (c, cell) in row.iter().enumerate() {
+                if c > 0 {
+                    boxes.push(TextBox::text("  "));
+                }
+                let padding = widths[c] - cell.width();
+                boxes.push(TextBox::beside(&[
+                    TextBox::text(&" ".repeat(padding - padding / 2)),
+                    cell.clone(),
+                    TextBox::text(&" ".repeat(padding / 2)),
+                ]));
+            }
+            let row = TextBox::beside(&boxes);
+            let width = widths.iter().sum::<usize>() + 2 * columns.saturating_sub(1);
+            lines.extend(row.lines.iter().map(|line| format!("{:width$}", line)));
+        }
+        let baseline = lines.len() / 2;
+        Ok(TextBox { lines, baseline }.delimited('[', ']'))
+    }
+}
+
+impl ExpressionVisitor<TextBox, ()> for AsciiArtVisitor {
+    fn visit_number(&mut self, value: f64) -> Result<TextBox, ()> {
+        Ok(TextBox::text(&value.to_string()))
+    }
+
+    fn visit_add(&mut self, left: &Expression, right: &Expression) -> Result<TextBox, ()> {
+        self.binary(left, " + ", right, 1)
+    }
+
+    fn visit_subtract(&mut self, left: &Expression, right: &Expression) -> Result<TextBox, ()> {
+        self.binary(left, " - ", right, 1)
+    }
+
+    fn visit_multiply(&mut self, left: &Expression, right: &Expression) -> Result<TextBox, ()> {
+        self.binary(left, "*", right, 2)
+    }
+
+    // A fraction inside another one is padded so that the outer bar is longer,
+    // telling `1/(2/3)` apart from `(1/2)/3`.
+    fn visit_divide(&mut self, left: &Expression, right: &Expression) -> Result<TextBox, ()> {
+        let mut term = |expr: &Expression| -> Result<TextBox, ()> {
+            let rendered = self.visit_expression(expr)?;
+            Ok(if is_fraction(expr) {
+                TextBox::beside(&[TextBox::text(" "), rendered, TextBox::text(" ")])
+            } else {
+                rendered
+            })
+        };
+        Ok(TextBox::fraction(&term(left)?, &term(right)?))
+    }
+
+    fn visit_power(&mut self, base: &Expression, exponent: &Expression) -> Result<TextBox, ()> {
+        Ok(TextBox::superscript(
+            &self.operand(base, PRIMARY)?,
+            &self.visit_expression(exponent)?,
+        ))
+    }
+
+    // `- ` before a fraction, whose bar would otherwise run on from the sign.
+    fn visit_negate(&mut self, expr: &Expression) -> Result<TextBox, ()> {
+        let sign = if is_fraction(expr) { "- " } else { "-" };
+        Ok(TextBox::beside(&[
+            TextBox::text(sign),
+            self.operand(expr, UNARY)?,
+        ]))
+    }
+
+    fn visit_grouping(&mut self, expr: &Expression) -> Result<TextBox, ()> {
+        self.visit_expression(expr)
+    }
+
+    fn visit_variable(&mut self, name: &str) -> Result<TextBox, ()> {
+        Ok(TextBox::text(name))
+    }
+
+    fn visit_equation(&mut self, left: &Expression, right: &Expression) -> Result<TextBox, ()> {
+        Ok(TextBox::beside(&[
+            self.operand(left, 1)?,
+            TextBox::text(" = "),
+            self.operand(right, 1)?,
+        ]))
+    }
+
+    fn visit_range(&mut self, from: &Expression, to: &Expression) -> Result<TextBox, ()> {
+        Ok(TextBox::beside(&[
+            self.operand(from, 1)?,
+            TextBox::text(".."),
+            self.operand(to, 1)?,
+        ]))
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) -> Result<TextBox, ()> {
+        let rows: Option<Vec<Vec<Expression>>> = elements
+            .iter()
+            .map(|element| match element {
+                Expression::List(row) => Some(row.clone()),
+                _ => None,
+            })
+            .collect();
+        match rows {
+            Some(rows) if !rows.is_empty() => self.matrix(&rows),
+            _ => Ok(self.visit_all(elements)?.delimited('[', ']')),
+        }
+    }
+
+    fn visit_call(&mut self, name: &str, args: &[Expression]) -> Result<TextBox, ()> {
+        match (name, args) {
+            ("sqrt", [arg]) => Ok(TextBox::radical(&self.visit_expression(arg)?)),
+            ("abs", [arg]) => Ok(self.visit_expression(arg)?.delimited('|', '|')),
+            _ => Ok(TextBox::beside(&[
+                TextBox::text(name),
+                self.visit_all(args)?.delimited('(', ')'),
+            ])),
+        }
+    }
+
+    fn visit_index(&mut self, target: &Expression, indices: &[Expression]) -> Result<TextBox, ()> {
+        Ok(TextBox::beside(&[
+            self.operand(target, PRIMARY)?,
+            self.visit_all(indices)?.delimited('[', ']'),
+        ]))
+    }
+
+    fn visit_error(&mut self) -> Result<TextBox, ()> {
+        Ok(TextBox::text("?"))
+    }
+}
+
+// Whether `expr` is drawn as a stacked fraction without brackets.
+fn is_fraction(expr: &Expression) -> bool {
+    match expr {
+        Expression::Grouping(inner) => is_fraction(inner),
+        Expression::Divide(..) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod ascii_art_tests {
+    use super::*;
+    use crate::parsemath::parser::Parser;
+    use rstest::rstest;
+
+    fn render(input: &str) -> String {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        AsciiArtVisitor.visit_expression(&ast).unwrap().to_string()
+    }
+
+    #[rstest]
+    #[case("1 + 2 * x", "1 + 2*x")]
+    #[case("1 / x", "1\n-\nx")]
+    #[case("(x + 1) / 2", "x + 1\n-----\n  2")]
+    #[case("x ^ 2", " 2\nx")]
+    #[case("(x + 1) ^ n", "       n\n(x + 1)")]
+    #[case("sqrt(x)", "  __\n\\/ x")]
+    #[case("-(a - b)", "-(a - b)")]
+    #[case("abs(x) + f(x, 2)", "|x| + f(x, 2)")]
+    fn render_single_construct(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(render(input), expected);
+    }
+
+    #[rstest]
+    fn render_nested_formula() {
+        let expected = [
+            "     2",
+            "    x  + 1",
+            "y = ------",
+            "      ____",
+            "    \\/ 2*z",
+        ]
+        .join("\n");
+        assert_eq!(render("y = (x ^ 2 + 1) / sqrt(2 * z)"), expected);
+    }
+
+    #[rstest]
+    fn render_tall_parentheses_and_radical() {
+        let expected = ["       2", "/1    \\", "|- + 1|", "\\x    /"].join("\n");
+        assert_eq!(render("(1 / x + 1) ^ 2"), expected);
+
+        let expected = ["    __", "   / 1", "  /  -", "\\/   x"].join("\n");
+        assert_eq!(render("sqrt(1 / x)"), expected);
+    }
+
+    #[rstest]
+    fn render_nested_fractions() {
+        let expected = [" 1", "---", " 2", " -", " 3"].join("\n");
+        assert_eq!(render("1 / (2 / 3)"), expected);
+
+        let expected = [" 1", " -", " 2", "---", " 3"].join("\n");
+        assert_eq!(render("(1 / 2) / 3"), expected);
+    }
+
+    #[rstest]
+    #[case("-(1 / 2)", "  1\n- -\n  2")]
+    #[case("-x ^ 2", "  2\n-x")]
+    fn render_negated_operands(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(render(input), expected);
+    }
+
+    #[rstest]
+    fn render_matrix() {
+        let expected = ["[ 1   2]", "[10  -3]"].join("\n");
+        assert_eq!(render("[[1, 2], [10, -3]]"), expected);
+    }
+
+    #[rstest]
+    fn text_boxes_align_on_baseline() {
+        let fraction = TextBox::fraction(&TextBox::text("a"), &TextBox::text("bc"));
+        let row = TextBox::beside(&[TextBox::text("x = "), fraction]);
+        assert_eq!((row.width(), row.height(), row.baseline()), (6, 3, 1));
+        assert_eq!(row.to_string(), "    a\nx = --\n    bc");
+    }
+}
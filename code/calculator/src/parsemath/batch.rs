@@ -0,0 +1,381 @@
+use super::closures;
+use super::parser::Expression;
+use super::visitors::{EvaluatorError, ExpressionVisitor};
+use crate::calcmath::{functions, value::Value};
+use std::collections::BTreeMap;
+
+// Evaluates one numeric expression over many rows at once, a node at a time over
+// whole columns, so that the inner loops are simple enough to vectorize. A row
+// that fails, e.g. by dividing by zero, yields NaN and its error is reported with
+// the output instead of aborting the batch; the error is the one the `Evaluator`
+// would report for that row. Only problems affecting every row, such as unknown
+// variables, fail the whole batch.
+pub struct BatchEvaluator<'a> {
+    rows: usize,
+    columns: Vec<(String, &'a [f64])>,
+}
+
+// The output column, with NaN in rows that failed.
+#[derive(Debug, PartialEq)]
+pub struct BatchOutput {
+    pub values: Vec<f64>,
+    // Failed rows in ascending order.
+    pub errors: Vec<(usize, EvaluatorError)>,
+}
+
+impl BatchOutput {
+    pub fn row(&self, row: usize) -> Result<f64, &EvaluatorError> {
+        match self.errors.binary_search_by_key(&row, |(r, _)| *r) {
+            Ok(index) => Err(&self.errors[index].1),
+            Err(_) => Ok(self.values[row]),
+        }
+    }
+}
+
+impl<'a> BatchEvaluator<'a> {
+    pub fn new(rows: usize) -> Self {
+        BatchEvaluator {
+            rows,
+            columns: Vec::new(),
+        }
+    }
+
+    pub fn with_column(mut self, name: &str, values: &'a [f64]) -> Self {
+        self.columns.retain(|(column, _)| column != name);
+        self.columns.push((name.to_string(), values));
+        self
+    }
+
+    pub fn evaluate(&self, expr: &Expression) -> Result<BatchOutput, EvaluatorError> {
+        if let Some((name, values)) = self.columns.iter().find(|(_, v)| v.len() != self.rows) {
+            return Err(EvaluatorError::DimensionMismatch(format!(
+                "column '{}' has {} rows, expected {}",
+                name,
+                values.len(),
+                self.rows
+            )));
+        }
+
+        let mut evaluation = ColumnEvaluation {
+            batch: self,
+            errors: BTreeMap::new(),
+        };
+        let mut values = evaluation.visit_expression(expr)?;
+        for row in evaluation.errors.keys() {
+            values[*row] = f64::NAN;
+        }
+        Ok(BatchOutput {
+            values,
+            errors: evaluation.errors.into_iter().collect(),
+        })
+    }
+}
+
+struct ColumnEvaluation<'b, 'a> {
+    batch: &'b BatchEvaluator<'a>,
+    // The first error of each failed row.
+    errors: BTreeMap<usize, EvaluatorError>,
+}
+
+impl ColumnEvaluation<'_, '_> {
+    fn fail(&mut self, row: usize, error: EvaluatorError) -> f64 {
+        self.errors.entry(row).or_insert(error);
+        f64::NAN
+    }
+
+    fn binary(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+        op: fn(f64, f64) -> f64,
+    ) -> Result<Vec<f64>, EvaluatorError> {
+        let mut left = self.visit_expression(left)?;
+        let right = self.visit_expression(right)?;
+        for (a, b) in left.iter_mut().zip(&right) {
+            *a = op(*a, *b);
+        }
+        Ok(left)
+    }
+
+    // `integrate`, `diff` and `solve` evaluate their body as a function, so they
+    // run row by row as compiled closures.
+    fn call_by_row(&mut self, name: &str, args: &[Expression]) -> Result<Vec<f64>, EvaluatorError> {
+        let names: Vec<&str> = self.batch.columns.iter().map(|(n, _)| n.as_str()).collect();
+        let call = Expression::Call(name.to_string(), args.to_vec());
+        let f = closures::compile_with(&call, &names)?;
+
+        let mut inputs = vec![0.0; names.len()];
+        let mut values = Vec::with_capacity(self.batch.rows);
+        for row in 0..self.batch.rows {
+            for (input, (_, column)) in inputs.iter_mut().zip(&self.batch.columns) {
+                *input = column[row];
+            }
+            values.push(match f(&inputs) {
+                Ok(value) => value,
+                Err(error) => self.fail(row, error),
+            });
+        }
+        Ok(values)
+    }
+}
+
+impl ExpressionVisitor<Vec<f64>, EvaluatorError> for ColumnEvaluation<'_, '_> {
+    fn visit_number(&mut self, value: f64) -> Result<Vec<f64>, EvaluatorError> {
+        Ok(vec![value; self.batch.rows])
+    }
+
+    fn visit_add(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Vec<f64>, EvaluatorError> {
+        self.binary(left, right, |a, b| a + b)
+    }
+
+    fn visit_subtract(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Vec<f64>, EvaluatorError> {
+        self.binary(left, right, |a, b| a - b)
+    }
+
+    fn visit_multiply(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Vec<f64>, EvaluatorError> {
+        self.binary(left, right, |a, b| a * b)
+    }
+
+    // Division by zero is checked before the dividend is evaluated, as in the
+    // evaluator.
+    fn visit_divide(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Vec<f64>, EvaluatorError> {
+        let right = self.visit_expression(right)?;
+        for (row, b) in right.iter().enumerate() {
+            if *b == 0.0 {
+                self.fail(row, EvaluatorError::DivisionByZero);
+            }
+        }
+
+        let mut left = self.visit_expression(left)?;
+        for (a, b) in left.iter_mut().zip(&right) {
+            *a /= b;
+        }
+        Ok(left)
+    }
+
+    fn visit_power(
+        &mut self,
+        base: &Expression,
+        exponent: &Expression,
+    ) -> Result<Vec<f64>, EvaluatorError> {
+        self.binary(base, exponent, f64::powf)
+    }
+
+    fn visit_negate(&mut self, expr: &Expression) -> Result<Vec<f64>, EvaluatorError> {
+        let mut values = self.visit_expression(expr)?;
+        for value in &mut values {
+            *value = -*value;
+        }
+        Ok(values)
+    }
+
+    fn visit_grouping(&mut self, expr: &Expression) -> Result<Vec<f64>, EvaluatorError> {
+        self.visit_expression(expr)
+    }
+
+    fn visit_variable(&mut self, name: &str) -> Result<Vec<f64>, EvaluatorError> {
+        if let Some((_, values)) = self.batch.columns.iter().find(|(n, _)| n == name) {
+            return Ok(values.to_vec());
+        }
+        match functions::constant(name) {
+            Some(value) => self.visit_number(value),
+            None => Err(EvaluatorError::UnknownVariable(name.to_string())),
+        }
+    }
+
+    fn visit_equation(
+        &mut self,
+        _: &Expression,
+        _: &Expression,
+    ) -> Result<Vec<f64>, EvaluatorError> {
+        Err(EvaluatorError::UnexpectedExpression(
+            "equations are only allowed inside solve()".to_string(),
+        ))
+    }
+
+    fn visit_range(&mut self, _: &Expression, _: &Expression) -> Result<Vec<f64>, EvaluatorError> {
+        Err(EvaluatorError::UnexpectedExpression(
+            "ranges are only allowed inside solve()".to_string(),
+        ))
+    }
+
+    fn visit_list(&mut self, _: &[Expression]) -> Result<Vec<f64>, EvaluatorError> {
+        Err(EvaluatorError::UnexpectedExpression(
+            "lists and matrices are not supported in batch evaluation".to_string(),
+        ))
+    }
+
+    fn visit_call(&mut self, name: &str, args: &[Expression]) -> Result<Vec<f64>, EvaluatorError> {
+        if matches!(name, "integrate" | "diff")
+            || (name == "solve" && matches!(args.get(1), Some(Expression::Variable(_))))
+        {
+            return self.call_by_row(name, args);
+        }
+
+        let mut columns = args
+            .iter()
+            .map(|arg| self.visit_expression(arg))
+            .collect::<Result<Vec<Vec<f64>>, EvaluatorError>>()?;
+
+        if let (Some(function), [_]) = (functions::elementary_function(name), &args) {
+            let mut values = columns.pop().expect("one argument");
+            for (row, value) in values.iter_mut().enumerate() {
+                *value = match functions::apply_elementary(name, function, *value) {
+                    Ok(result) => result,
+                    Err(error) => self.fail(row, error),
+                };
+            }
+            return Ok(values);
+        }
+
+        let mut values = Vec::with_capacity(self.batch.rows);
+        for row in 0..self.batch.rows {
+            let args: Vec<Value> = columns.iter().map(|c| Value::Number(c[row])).collect();
+            values.push(
+                match functions::call(name, &args).and_then(|v| v.as_number()) {
+                    Ok(result) => result,
+                    Err(error) => self.fail(row, error),
+                },
+            );
+        }
+        Ok(values)
+    }
+
+    fn visit_index(
+        &mut self,
+        _: &Expression,
+        _: &[Expression],
+    ) -> Result<Vec<f64>, EvaluatorError> {
+        Err(EvaluatorError::UnexpectedExpression(
+            "indexing is not supported in batch evaluation".to_string(),
+        ))
+    }
+
+    fn visit_error(&mut self) -> Result<Vec<f64>, EvaluatorError> {
+        Err(EvaluatorError::UnexpectedExpression(
+            "the expression contains a parse error".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod batch_tests {
+    use super::*;
+    use crate::parsemath::parser::Parser;
+    use crate::parsemath::visitors::Evaluator;
+    use rstest::rstest;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input).unwrap().parse().unwrap()
+    }
+
+    const XS: [f64; 5] = [-2.0, -1.0, 0.0, 1.0, 4.0];
+    const YS: [f64; 5] = [3.0, 0.0, 0.5, 2.0, -1.0];
+
+    #[rstest]
+    #[case("x * y + 1")]
+    #[case("-(x - 1) ^ 2 / 2 + pi")]
+    #[case("1 / x + sqrt(y)")]
+    #[case("sqrt(x) / y")]
+    #[case("ln(x / y)")]
+    #[case("max(x, y, 1) + nCr(x, 2)")]
+    #[case("integrate(t * x, t, 0, y) + diff(t ^ 3, t, x)")]
+    #[case("solve(t ^ 3 = x, t, -5..5)")]
+    fn rows_match_the_evaluator(#[case] input: &str) {
+        let ast = parse(input);
+        let output = BatchEvaluator::new(XS.len())
+            .with_column("x", &XS)
+            .with_column("y", &YS)
+            .evaluate(&ast)
+            .unwrap();
+
+        for row in 0..XS.len() {
+            let expected = Evaluator::new()
+                .with_variable("x", XS[row])
+                .with_variable("y", YS[row])
+                .visit_expression(&ast)
+                .and_then(|v| v.as_number());
+            match (output.row(row), expected) {
+                (Ok(actual), Ok(expected)) => {
+                    assert!(
+                        actual == expected || (actual - expected).abs() < 1e-9,
+                        "row {}",
+                        row
+                    )
+                }
+                (actual, expected) => assert_eq!(actual, expected.as_ref().copied(), "row {}", row),
+            }
+        }
+    }
+
+    #[rstest]
+    fn failed_rows_are_reported_and_nan() {
+        let output = BatchEvaluator::new(XS.len())
+            .with_column("x", &XS)
+            .evaluate(&parse("1 / x + sqrt(x)"))
+            .unwrap();
+        assert_eq!(
+            output.errors,
+            [
+                (
+                    0,
+                    EvaluatorError::InvalidArgument("sqrt is undefined for -2".to_string())
+                ),
+                (
+                    1,
+                    EvaluatorError::InvalidArgument("sqrt is undefined for -1".to_string())
+                ),
+                (2, EvaluatorError::DivisionByZero),
+            ]
+        );
+        assert!(output.values[..3].iter().all(|v| v.is_nan()));
+        assert_eq!(output.values[3..], [2.0, 2.25]);
+    }
+
+    #[rstest]
+    #[case("x + z", EvaluatorError::UnknownVariable("z".to_string()))]
+    #[case("[x, 1]", EvaluatorError::UnexpectedExpression("lists and matrices are not supported in batch evaluation".to_string()))]
+    #[case("x = 1", EvaluatorError::UnexpectedExpression("equations are only allowed inside solve()".to_string()))]
+    fn whole_batch_errors(#[case] input: &str, #[case] expected: EvaluatorError) {
+        let result = BatchEvaluator::new(XS.len())
+            .with_column("x", &XS)
+            .evaluate(&parse(input));
+        assert_eq!(result, Err(expected));
+    }
+
+    #[rstest]
+    fn columns_must_have_one_value_per_row() {
+        let result = BatchEvaluator::new(3)
+            .with_column("x", &XS)
+            .evaluate(&parse("x"));
+        assert_eq!(
+            result,
+            Err(EvaluatorError::DimensionMismatch(
+                "column 'x' has 5 rows, expected 3".to_string()
+            ))
+        );
+    }
+
+    #[rstest]
+    fn constant_expressions_fill_every_row() {
+        let output = BatchEvaluator::new(3).evaluate(&parse("2 ^ 3")).unwrap();
+        assert_eq!(output.values, [8.0, 8.0, 8.0]);
+        assert!(output.errors.is_empty());
+    }
+}
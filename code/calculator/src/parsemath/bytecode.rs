@@ -0,0 +1,601 @@
+use super::lazy::LazyCall;
+use super::parser::Expression;
+use super::visitors::{EvaluatorError, ExpressionVisitor};
+use crate::calcmath::{calculus, functions, roots, value::Value};
+
+// Compiles numeric expressions to bytecode for a stack machine, so a formula can
+// be evaluated many times without walking the tree or looking up variables by
+// name. Free variables become input slots numbered in order of first appearance;
+// `pi` and `e` are compiled as constants. Lists, matrices and indexing are not
+// supported.
+//
+// The bodies of `integrate`, `diff` and `solve` are compiled inline, behind a
+// `Jump`, and run as subroutines with their variable bound to a slot of its own.
+#[derive(Debug, Clone, Copy)]
+pub enum Instruction {
+    Const(f64),
+    Load(usize),
+    Add,
+    Subtract,
+    Multiply,
+    // The divisor is pushed first, so that division by zero is reported before
+    // errors in the dividend, as the tree-walking evaluator does.
+    Divide,
+    Power,
+    Negate,
+    Elementary {
+        name: usize,
+        function: fn(f64) -> f64,
+    },
+    Call {
+        name: usize,
+        args: usize,
+    },
+    Jump(usize),
+    Return,
+    // Pops `at` and runs the body with its variable bound to it, e.g. a
+    // symbolic derivative.
+    Bind {
+        body: usize,
+        slot: usize,
+    },
+    // Pops the bounds and integrates the body.
+    Integrate {
+        body: usize,
+        slot: usize,
+    },
+    // Pops `at` and differentiates the body numerically.
+    Differentiate {
+        body: usize,
+        slot: usize,
+    },
+    // Pops the initial guess and finds a root of the body with Newton's method.
+    Newton {
+        body: usize,
+        slot: usize,
+    },
+    // Pops the bounds of a range that must contain exactly one root.
+    FindRoot {
+        body: usize,
+        slot: usize,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    code: Vec<Instruction>,
+    names: Vec<String>,
+    variables: Vec<String>,
+    slots: usize,
+}
+
+impl Program {
+    pub fn compile(expr: &Expression) -> Result<Program, EvaluatorError> {
+        let mut compiler = Compiler::default();
+        compiler.visit_expression(expr)?;
+        compiler.code.push(Instruction::Return);
+
+        Ok(Program {
+            code: compiler.code,
+            names: compiler.names,
+            slots: compiler.slots,
+            variables: compiler.variables,
+        })
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.code
+    }
+
+    // Names of the input slots, in the order `Vm::run` expects their values.
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    pub fn slot(&self, variable: &str) -> Option<usize> {
+        self.variables.iter().position(|name| name == variable)
+    }
+}
+
+#[derive(Default)]
+struct Compiler {
+    code: Vec<Instruction>,
+    names: Vec<String>,
+    variables: Vec<String>,
+    // Variables bound by `integrate`, `diff` and `solve`, innermost last.
+    bound: Vec<(String, usize)>,
+    slots: usize,
+}
+
+impl Compiler {
+    fn emit(&mut self, instruction: Instruction) -> Result<(), EvaluatorError> {
+        self.code.push(instruction);
+        Ok(())
+    }
+
+    fn binary(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+        instruction: Instruction,
+    ) -> Result<(), EvaluatorError> {
+        self.visit_expression(left)?;
+        self.visit_expression(right)?;
+        self.emit(instruction)
+    }
+
+    fn name(&mut self, name: &str) -> usize {
+        match self.names.iter().position(|n| n == name) {
+            Some(index) => index,
+            None => {
+                self.names.push(name.to_string());
+                self.names.len() - 1
+            }
+        }
+    }
+
+    // Slots are allocated as `[inputs..., bound...]`, so a new input moves every
+    // bound slot allocated before it up by one.
+    fn shift_bound_slots(&mut self, input: usize) {
+        self.slots += 1;
+        for instruction in &mut self.code {
+            match instruction {
+                Instruction::Load(slot) if *slot >= input => *slot += 1,
+                Instruction::Bind { slot, .. }
+                | Instruction::Integrate { slot, .. }
+                | Instruction::Differentiate { slot, .. }
+                | Instruction::Newton { slot, .. }
+                | Instruction::FindRoot { slot, .. } => *slot += 1,
+                _ => {}
+            }
+        }
+        for (_, slot) in &mut self.bound {
+            *slot += 1;
+        }
+    }
+
+    // Emits `body` as a subroutine with `variable` bound to a fresh slot,
+    // followed by `instruction` to run it.
+    fn subroutine(
+        &mut self,
+        body: &Expression,
+        variable: &str,
+        instruction: fn(usize, usize) -> Instruction,
+    ) -> Result<(), EvaluatorError> {
+        let slot = self.slots;
+        self.slots += 1;
+
+        let jump = self.code.len();
+        self.code.push(Instruction::Jump(0));
+        let start = self.code.len();
+
+        self.bound.push((variable.to_string(), slot));
+        let compiled = self.visit_expression(body);
+        // Inputs first seen in the body may have moved the slot.
+        let (_, slot) = self.bound.pop().expect("pushed above");
+        compiled?;
+
+        self.code.push(Instruction::Return);
+        self.code[jump] = Instruction::Jump(self.code.len());
+        self.emit(instruction(start, slot))
+    }
+
+    fn lazy(&mut self, call: LazyCall) -> Result<(), EvaluatorError> {
+        match call {
+            LazyCall::Bind { body, variable, at } => {
+                self.visit_expression(at)?;
+                self.subroutine(&body, variable, |body, slot| Instruction::Bind {
+                    body,
+                    slot,
+                })
+            }
+            LazyCall::Integrate {
+                body,
+                variable,
+                from,
+                to,
+            } => {
+                self.visit_expression(from)?;
+                self.visit_expression(to)?;
+                self.subroutine(&body, variable, |body, slot| Instruction::Integrate {
+                    body,
+                    slot,
+                })
+            }
+            LazyCall::Differentiate { body, variable, at } => {
+                self.visit_expression(at)?;
+                self.subroutine(&body, variable, |body, slot| Instruction::Differentiate {
+                    body,
+                    slot,
+                })
+            }
+            LazyCall::Newton {
+                body,
+                variable,
+                guess,
+            } => {
+                match guess {
+                    Some(guess) => self.visit_expression(guess)?,
+                    None => self.emit(Instruction::Const(1.0))?,
+                }
+                self.subroutine(&body, variable, |body, slot| Instruction::Newton {
+                    body,
+                    slot,
+                })
+            }
+            LazyCall::FindRoot {
+                body,
+                variable,
+                from,
+                to,
+            } => {
+                self.visit_expression(from)?;
+                self.visit_expression(to)?;
+                self.subroutine(&body, variable, |body, slot| Instruction::FindRoot {
+                    body,
+                    slot,
+                })
+            }
+        }
+    }
+}
+
+impl ExpressionVisitor<(), EvaluatorError> for Compiler {
+    fn visit_number(&mut self, value: f64) -> Result<(), EvaluatorError> {
+        self.emit(Instruction::Const(value))
+    }
+
+    fn visit_add(&mut self, left: &Expression, right: &Expression) -> Result<(), EvaluatorError> {
+        self.binary(left, right, Instruction::Add)
+    }
+
+    fn visit_subtract(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<(), EvaluatorError> {
+        self.binary(left, right, Instruction::Subtract)
+    }
+
+    fn visit_multiply(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<(), EvaluatorError> {
+        self.binary(left, right, Instruction::Multiply)
+    }
+
+    fn visit_divide(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<(), EvaluatorError> {
+        self.binary(right, left, Instruction::Divide)
+    }
+
+    fn visit_power(
+        &mut self,
+        base: &Expression,
+        exponent: &Expression,
+    ) -> Result<(), EvaluatorError> {
+        self.binary(base, exponent, Instruction::Power)
+    }
+
+    fn visit_negate(&mut self, expr: &Expression) -> Result<(), EvaluatorError> {
+        self.visit_expression(expr)?;
+        self.emit(Instruction::Negate)
+    }
+
+    fn visit_grouping(&mut self, expr: &Expression) -> Result<(), EvaluatorError> {
+        self.visit_expression(expr)
+    }
+
+    fn visit_variable(&mut self, name: &str) -> Result<(), EvaluatorError> {
+        if let Some((_, slot)) = self.bound.iter().rev().find(|(bound, _)| bound == name) {
+            return self.emit(Instruction::Load(*slot));
+        }
+        if let Some(value) = functions::constant(name) {
+            return self.emit(Instruction::Const(value));
+        }
+
+        let slot = match self.variables.iter().position(|v| v == name) {
+            Some(slot) => slot,
+            None => {
+                // Input slots come first; renumber any bound slots allocated so far.
+                self.variables.push(name.to_string());
+                let slot = self.variables.len() - 1;
+                self.shift_bound_slots(slot);
+                slot
+            }
+        };
+        self.emit(Instruction::Load(slot))
+    }
+
+    fn visit_equation(&mut self, _: &Expression, _: &Expression) -> Result<(), EvaluatorError> {
+        Err(EvaluatorError::UnexpectedExpression(
+            "equations are only allowed inside solve()".to_string(),
+        ))
+    }
+
+    fn visit_range(&mut self, _: &Expression, _: &Expression) -> Result<(), EvaluatorError> {
+        Err(EvaluatorError::UnexpectedExpression(
+            "ranges are only allowed inside solve()".to_string(),
+        ))
+    }
+
+    fn visit_list(&mut self, _: &[Expression]) -> Result<(), EvaluatorError> {
+        Err(EvaluatorError::NotCompilable(
+            "lists and matrices are not supported".to_string(),
+        ))
+    }
+
+    fn visit_call(&mut self, name: &str, args: &[Expression]) -> Result<(), EvaluatorError> {
+        if let Some(call) = LazyCall::decode(name, args) {
+            return self.lazy(call?);
+        }
+
+        for arg in args {
+            self.visit_expression(arg)?;
+        }
+        let index = self.name(name);
+        match functions::elementary_function(name) {
+            Some(function) if args.len() == 1 => self.emit(Instruction::Elementary {
+                name: index,
+                function,
+            }),
+            _ => self.emit(Instruction::Call {
+                name: index,
+                args: args.len(),
+            }),
+        }
+    }
+
+    fn visit_index(&mut self, _: &Expression, _: &[Expression]) -> Result<(), EvaluatorError> {
+        Err(EvaluatorError::NotCompilable(
+            "indexing is not supported".to_string(),
+        ))
+    }
+
+    fn visit_error(&mut self) -> Result<(), EvaluatorError> {
+        Err(EvaluatorError::NotCompilable(
+            "the expression contains a parse error".to_string(),
+        ))
+    }
+}
+
+// Runs compiled programs. The stack and slots are kept between runs, so
+// evaluating a program repeatedly does not allocate.
+#[derive(Default)]
+pub struct Vm {
+    stack: Vec<f64>,
+    slots: Vec<f64>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Evaluates `program` with `inputs` in the order of `Program::variables`.
+    pub fn run(&mut self, program: &Program, inputs: &[f64]) -> Result<f64, EvaluatorError> {
+        if inputs.len() != program.variables.len() {
+            return Err(EvaluatorError::InvalidArgument(format!(
+                "expected {} input(s), got {}",
+                program.variables.len(),
+                inputs.len()
+            )));
+        }
+
+        self.stack.clear();
+        self.slots.clear();
+        self.slots.extend_from_slice(inputs);
+        self.slots.resize(program.slots, 0.0);
+        self.execute(program, 0)
+    }
+
+    fn pop(&mut self) -> f64 {
+        self.stack
+            .pop()
+            .expect("compiled code keeps the stack balanced")
+    }
+
+    fn pop_pair(&mut self) -> (f64, f64) {
+        let b = self.pop();
+        let a = self.pop();
+        (a, b)
+    }
+
+    // Runs from `pc` to the next `Return` and yields the value on top of the stack.
+    fn execute(&mut self, program: &Program, mut pc: usize) -> Result<f64, EvaluatorError> {
+        loop {
+            let instruction = program.code[pc];
+            pc += 1;
+
+            let value = match instruction {
+                Instruction::Const(value) => value,
+                Instruction::Load(slot) => self.slots[slot],
+                Instruction::Add => {
+                    let (a, b) = self.pop_pair();
+                    a + b
+                }
+                Instruction::Subtract => {
+                    let (a, b) = self.pop_pair();
+                    a - b
+                }
+                Instruction::Multiply => {
+                    let (a, b) = self.pop_pair();
+                    a * b
+                }
+                Instruction::Divide => {
+                    let (b, a) = self.pop_pair();
+                    if b == 0.0 {
+                        return Err(EvaluatorError::DivisionByZero);
+                    }
+                    a / b
+                }
+                Instruction::Power => {
+                    let (a, b) = self.pop_pair();
+                    a.powf(b)
+                }
+                Instruction::Negate => -self.pop(),
+                Instruction::Elementary { name, function } => {
+                    let x = self.pop();
+                    functions::apply_elementary(&program.names[name], function, x)?
+                }
+                Instruction::Call { name, args } => {
+                    let values: Vec<Value> = self
+                        .stack
+                        .drain(self.stack.len() - args..)
+                        .map(Value::Number)
+                        .collect();
+                    functions::call(&program.names[name], &values)?.as_number()?
+                }
+                Instruction::Jump(target) => {
+                    pc = target;
+                    continue;
+                }
+                Instruction::Return => return Ok(self.pop()),
+                Instruction::Bind { body, slot } => {
+                    let at = self.pop();
+                    self.call(program, body, slot, at)?
+                }
+                Instruction::Integrate { body, slot } => {
+                    let (from, to) = self.pop_pair();
+                    calculus::integrate(|x| self.call(program, body, slot, x), from, to)?
+                }
+                Instruction::Differentiate { body, slot } => {
+                    let at = self.pop();
+                    calculus::derivative(|x| self.call(program, body, slot, x), at)?
+                }
+                Instruction::Newton { body, slot } => {
+                    let guess = self.pop();
+                    roots::newton(|x| self.call(program, body, slot, x), guess)?
+                }
+                Instruction::FindRoot { body, slot } => {
+                    let (from, to) = self.pop_pair();
+                    let roots = roots::find_roots(|x| self.call(program, body, slot, x), from, to)?;
+                    match roots[..] {
+                        [root] => root,
+                        _ => {
+                            return Err(EvaluatorError::TypeMismatch(
+                                "expected a number, got a matrix".to_string(),
+                            ));
+                        }
+                    }
+                }
+            };
+            self.stack.push(value);
+        }
+    }
+
+    fn call(
+        &mut self,
+        program: &Program,
+        body: usize,
+        slot: usize,
+        x: f64,
+    ) -> Result<f64, EvaluatorError> {
+        self.slots[slot] = x;
+        self.execute(program, body)
+    }
+}
+
+#[cfg(test)]
+mod bytecode_tests {
+    use super::*;
+    use crate::parsemath::lazy::compiler_cases;
+    use crate::parsemath::parser::Parser;
+    use crate::parsemath::visitors::Evaluator;
+    use rstest::rstest;
+
+    fn compile(input: &str) -> Program {
+        Program::compile(&Parser::new(input).unwrap().parse().unwrap()).unwrap()
+    }
+
+    fn evaluate(input: &str, variables: &[(&str, f64)]) -> Result<f64, EvaluatorError> {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        let mut evaluator = Evaluator::new();
+        for (name, value) in variables {
+            evaluator.set_variable(name, *value);
+        }
+        evaluator.visit_expression(&ast)?.as_number()
+    }
+
+    #[rstest]
+    fn runs_like_the_evaluator() {
+        for (input, variables) in compiler_cases::RUNS_LIKE_THE_EVALUATOR {
+            let program = compile(input);
+            let inputs: Vec<f64> = program
+                .variables()
+                .iter()
+                .map(|name| variables.iter().find(|(n, _)| n == name).unwrap().1)
+                .collect();
+
+            let expected = evaluate(input, variables).unwrap();
+            let actual = Vm::new().run(&program, &inputs).unwrap();
+            assert!(
+                (actual - expected).abs() < 1e-9,
+                "{}: {} != {}",
+                input,
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[rstest]
+    fn reports_evaluation_errors() {
+        for (input, expected) in compiler_cases::evaluation_errors() {
+            let program = compile(input);
+            let inputs = vec![1.0; program.variables().len()];
+            assert_eq!(Vm::new().run(&program, &inputs), Err(expected), "{}", input);
+        }
+    }
+
+    #[rstest]
+    fn rejects_unsupported_expressions() {
+        for (input, expected) in compiler_cases::UNSUPPORTED {
+            let ast = Parser::new(input).unwrap().parse().unwrap();
+            assert_eq!(Program::compile(&ast).unwrap_err().to_string(), *expected);
+        }
+    }
+
+    #[rstest]
+    fn variables_get_slots_in_order_of_appearance() {
+        let program = compile("integrate(t * b, t, 0, 1) + a * b");
+        assert_eq!(program.variables(), ["b", "a"]);
+        assert_eq!(program.slot("a"), Some(1));
+        assert_eq!(program.slot("t"), None);
+        assert_eq!(Vm::new().run(&program, &[4.0, 3.0]).unwrap(), 14.0);
+    }
+
+    #[rstest]
+    fn vm_is_reusable() {
+        let program = compile("x ^ 2 - 1");
+        let mut vm = Vm::new();
+        let results: Vec<f64> = (0..4)
+            .map(|x| vm.run(&program, &[x as f64]).unwrap())
+            .collect();
+        assert_eq!(results, [-1.0, 0.0, 3.0, 8.0]);
+        assert_eq!(
+            vm.run(&program, &[]).unwrap_err().to_string(),
+            "Invalid argument: expected 1 input(s), got 0"
+        );
+    }
+
+    #[rstest]
+    fn compiles_to_stack_code() {
+        let program = compile("2 * x + 1");
+        assert!(matches!(
+            program.instructions(),
+            [
+                Instruction::Const(2.0),
+                Instruction::Load(0),
+                Instruction::Multiply,
+                Instruction::Const(1.0),
+                Instruction::Add,
+                Instruction::Return,
+            ]
+        ));
+    }
+}
@@ -0,0 +1,405 @@
+use super::bytecode::Program;
+use super::lazy::LazyCall;
+use super::parser::Expression;
+use super::visitors::{EvaluatorError, ExpressionVisitor};
+use crate::calcmath::{calculus, functions, roots, value::Value};
+
+// A numeric expression compiled to Rust closures, called with the values of its
+// variables in slot order.
+pub type CompiledExpression = Box<dyn Fn(&[f64]) -> Result<f64, EvaluatorError> + Send + Sync>;
+
+// Compiled node: reads inputs by slot, and `locals` for the variables bound by
+// `integrate`, `diff` and `solve`.
+type Node = Box<dyn Fn(&[f64], &mut [f64]) -> Result<f64, EvaluatorError> + Send + Sync>;
+
+// Compiles `expr` into a tree of closures, with variables resolved to slots
+// ahead of time. Slots are numbered as in `Program::variables`, i.e. in order of
+// first appearance. Supports the same expressions as the bytecode compiler.
+pub fn compile(expr: &Expression) -> Result<CompiledExpression, EvaluatorError> {
+    let program = Program::compile(expr)?;
+    let variables: Vec<&str> = program.variables().iter().map(String::as_str).collect();
+    compile_with(expr, &variables)
+}
+
+// Like `compile`, with `variables` giving the slot order. Variables missing from
+// the list are reported as unknown.
+pub fn compile_with(
+    expr: &Expression,
+    variables: &[&str],
+) -> Result<CompiledExpression, EvaluatorError> {
+    let mut compiler = ClosureCompiler {
+        variables: variables.iter().map(|v| v.to_string()).collect(),
+        bound: Vec::new(),
+        locals: 0,
+    };
+    let node = compiler.visit_expression(expr)?;
+    let inputs = variables.len();
+    let locals = compiler.locals;
+
+    Ok(Box::new(move |values: &[f64]| {
+        if values.len() != inputs {
+            return Err(EvaluatorError::InvalidArgument(format!(
+                "expected {} input(s), got {}",
+                inputs,
+                values.len()
+            )));
+        }
+        if locals == 0 {
+            node(values, &mut [])
+        } else {
+            node(values, &mut vec![0.0; locals])
+        }
+    }))
+}
+
+struct ClosureCompiler {
+    variables: Vec<String>,
+    // Variables bound by `integrate`, `diff` and `solve`, innermost last.
+    bound: Vec<(String, usize)>,
+    locals: usize,
+}
+
+impl ClosureCompiler {
+    fn binary(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+        op: fn(f64, f64) -> f64,
+    ) -> Result<Node, EvaluatorError> {
+        let left = self.visit_expression(left)?;
+        let right = self.visit_expression(right)?;
+        Ok(Box::new(move |inputs, locals| {
+            Ok(op(left(inputs, locals)?, right(inputs, locals)?))
+        }))
+    }
+
+    // Compiles `body` with `variable` bound to a fresh local.
+    fn function_of(
+        &mut self,
+        body: &Expression,
+        variable: &str,
+    ) -> Result<(Node, usize), EvaluatorError> {
+        let local = self.locals;
+        self.locals += 1;
+        self.bound.push((variable.to_string(), local));
+        let body = self.visit_expression(body);
+        self.bound.pop();
+        Ok((body?, local))
+    }
+
+    fn lazy(&mut self, call: LazyCall) -> Result<Node, EvaluatorError> {
+        match call {
+            LazyCall::Bind { body, variable, at } => {
+                let at = self.visit_expression(at)?;
+                let (body, local) = self.function_of(&body, variable)?;
+                Ok(Box::new(move |inputs, locals| {
+                    locals[local] = at(inputs, locals)?;
+                    body(inputs, locals)
+                }))
+            }
+            LazyCall::Integrate {
+                body,
+                variable,
+                from,
+                to,
+            } => {
+                let from = self.visit_expression(from)?;
+                let to = self.visit_expression(to)?;
+                let (body, local) = self.function_of(&body, variable)?;
+                Ok(Box::new(move |inputs, locals| {
+                    let (a, b) = (from(inputs, locals)?, to(inputs, locals)?);
+                    calculus::integrate(
+                        |x| {
+                            locals[local] = x;
+                            body(inputs, locals)
+                        },
+                        a,
+                        b,
+                    )
+                }))
+            }
+            LazyCall::Differentiate { body, variable, at } => {
+                let at = self.visit_expression(at)?;
+                let (body, local) = self.function_of(&body, variable)?;
+                Ok(Box::new(move |inputs, locals| {
+                    let at = at(inputs, locals)?;
+                    calculus::derivative(
+                        |x| {
+                            locals[local] = x;
+                            body(inputs, locals)
+                        },
+                        at,
+                    )
+                }))
+            }
+            LazyCall::Newton {
+                body,
+                variable,
+                guess,
+            } => {
+                let guess = match guess {
+                    Some(guess) => self.visit_expression(guess)?,
+                    None => Box::new(|_: &[f64], _: &mut [f64]| Ok(1.0)),
+                };
+                let (body, local) = self.function_of(&body, variable)?;
+                Ok(Box::new(move |inputs, locals| {
+                    let guess = guess(inputs, locals)?;
+                    roots::newton(
+                        |x| {
+                            locals[local] = x;
+                            body(inputs, locals)
+                        },
+                        guess,
+                    )
+                }))
+            }
+            LazyCall::FindRoot {
+                body,
+                variable,
+                from,
+                to,
+            } => {
+                let from = self.visit_expression(from)?;
+                let to = self.visit_expression(to)?;
+                let (body, local) = self.function_of(&body, variable)?;
+                Ok(Box::new(move |inputs, locals| {
+                    let (a, b) = (from(inputs, locals)?, to(inputs, locals)?);
+                    let f = |x| {
+                        locals[local] = x;
+                        body(inputs, locals)
+                    };
+                    match roots::find_roots(f, a, b)?[..] {
+                        [root] => Ok(root),
+                        _ => Err(EvaluatorError::TypeMismatch(
+                            "expected a number, got a matrix".to_string(),
+                        )),
+                    }
+                }))
+            }
+        }
+    }
+}
+
+impl ExpressionVisitor<Node, EvaluatorError> for ClosureCompiler {
+    fn visit_number(&mut self, value: f64) -> Result<Node, EvaluatorError> {
+        Ok(Box::new(move |_, _| Ok(value)))
+    }
+
+    fn visit_add(&mut self, left: &Expression, right: &Expression) -> Result<Node, EvaluatorError> {
+        self.binary(left, right, |a, b| a + b)
+    }
+
+    fn visit_subtract(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Node, EvaluatorError> {
+        self.binary(left, right, |a, b| a - b)
+    }
+
+    fn visit_multiply(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Node, EvaluatorError> {
+        self.binary(left, right, |a, b| a * b)
+    }
+
+    // The divisor is evaluated first, as in the evaluator.
+    fn visit_divide(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Node, EvaluatorError> {
+        let left = self.visit_expression(left)?;
+        let right = self.visit_expression(right)?;
+        Ok(Box::new(move |inputs, locals| {
+            let divisor = right(inputs, locals)?;
+            if divisor == 0.0 {
+                return Err(EvaluatorError::DivisionByZero);
+            }
+            Ok(left(inputs, locals)? / divisor)
+        }))
+    }
+
+    fn visit_power(
+        &mut self,
+        base: &Expression,
+        exponent: &Expression,
+    ) -> Result<Node, EvaluatorError> {
+        self.binary(base, exponent, f64::powf)
+    }
+
+    fn visit_negate(&mut self, expr: &Expression) -> Result<Node, EvaluatorError> {
+        let expr = self.visit_expression(expr)?;
+        Ok(Box::new(move |inputs, locals| Ok(-expr(inputs, locals)?)))
+    }
+
+    fn visit_grouping(&mut self, expr: &Expression) -> Result<Node, EvaluatorError> {
+        self.visit_expression(expr)
+    }
+
+    fn visit_variable(&mut self, name: &str) -> Result<Node, EvaluatorError> {
+        if let Some((_, local)) = self.bound.iter().rev().find(|(bound, _)| bound == name) {
+            let local = *local;
+            return Ok(Box::new(move |_, locals| Ok(locals[local])));
+        }
+        if let Some(slot) = self.variables.iter().position(|v| v == name) {
+            return Ok(Box::new(move |inputs, _| Ok(inputs[slot])));
+        }
+        match functions::constant(name) {
+            Some(value) => self.visit_number(value),
+            None => Err(EvaluatorError::UnknownVariable(name.to_string())),
+        }
+    }
+
+    fn visit_equation(&mut self, _: &Expression, _: &Expression) -> Result<Node, EvaluatorError> {
+        Err(EvaluatorError::UnexpectedExpression(
+            "equations are only allowed inside solve()".to_string(),
+        ))
+    }
+
+    fn visit_range(&mut self, _: &Expression, _: &Expression) -> Result<Node, EvaluatorError> {
+        Err(EvaluatorError::UnexpectedExpression(
+            "ranges are only allowed inside solve()".to_string(),
+        ))
+    }
+
+    fn visit_list(&mut self, _: &[Expression]) -> Result<Node, EvaluatorError> {
+        Err(EvaluatorError::NotCompilable(
+            "lists and matrices are not supported".to_string(),
+        ))
+    }
+
+    fn visit_call(&mut self, name: &str, args: &[Expression]) -> Result<Node, EvaluatorError> {
+        if let Some(call) = LazyCall::decode(name, args) {
+            return self.lazy(call?);
+        }
+
+        let mut args = args
+            .iter()
+            .map(|arg| self.visit_expression(arg))
+            .collect::<Result<Vec<Node>, EvaluatorError>>()?;
+        let name = name.to_string();
+
+        if let (Some(function), 1) = (functions::elementary_function(&name), args.len()) {
+            let arg = args.pop().expect("one argument");
+            return Ok(Box::new(move |inputs, locals| {
+                functions::apply_elementary(&name, function, arg(inputs, locals)?)
+            }));
+        }
+        Ok(Box::new(move |inputs, locals| {
+            let values = args
+                .iter()
+                .map(|arg| arg(inputs, locals).map(Value::Number))
+                .collect::<Result<Vec<Value>, EvaluatorError>>()?;
+            functions::call(&name, &values)?.as_number()
+        }))
+    }
+
+    fn visit_index(&mut self, _: &Expression, _: &[Expression]) -> Result<Node, EvaluatorError> {
+        Err(EvaluatorError::NotCompilable(
+            "indexing is not supported".to_string(),
+        ))
+    }
+
+    fn visit_error(&mut self) -> Result<Node, EvaluatorError> {
+        Err(EvaluatorError::NotCompilable(
+            "the expression contains a parse error".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod closures_tests {
+    use super::*;
+    use crate::parsemath::lazy::compiler_cases;
+    use crate::parsemath::parser::Parser;
+    use crate::parsemath::visitors::Evaluator;
+    use rstest::rstest;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input).unwrap().parse().unwrap()
+    }
+
+    #[rstest]
+    fn runs_like_the_evaluator() {
+        for (input, variables) in compiler_cases::RUNS_LIKE_THE_EVALUATOR {
+            let ast = parse(input);
+            let mut evaluator = Evaluator::new();
+            for (name, value) in *variables {
+                evaluator.set_variable(name, *value);
+            }
+            let expected = evaluator
+                .visit_expression(&ast)
+                .unwrap()
+                .as_number()
+                .unwrap();
+
+            let names: Vec<&str> = variables.iter().map(|(name, _)| *name).collect();
+            let values: Vec<f64> = variables.iter().map(|(_, value)| *value).collect();
+            let actual = compile_with(&ast, &names).unwrap()(&values).unwrap();
+            assert!(
+                (actual - expected).abs() < 1e-9,
+                "{}: {} != {}",
+                input,
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[rstest]
+    fn slots_follow_first_appearance() {
+        let f = compile(&parse("integrate(t * b, t, 0, 1) + a * b")).unwrap();
+        assert_eq!(f(&[4.0, 3.0]).unwrap(), 14.0);
+    }
+
+    #[rstest]
+    fn explicit_slots_may_shadow_constants() {
+        let f = compile_with(&parse("e * x"), &["x", "e"]).unwrap();
+        assert_eq!(f(&[2.0, 5.0]).unwrap(), 10.0);
+        assert_eq!(
+            compile_with(&parse("x * y"), &["x"]).err().unwrap(),
+            EvaluatorError::UnknownVariable("y".to_string())
+        );
+    }
+
+    #[rstest]
+    fn reports_evaluation_errors() {
+        for (input, expected) in compiler_cases::evaluation_errors() {
+            let f = compile_with(&parse(input), &["x"]).unwrap();
+            assert_eq!(f(&[1.0]), Err(expected), "{}", input);
+        }
+    }
+
+    #[rstest]
+    fn checks_the_number_of_inputs() {
+        let f = compile_with(&parse("x + 1"), &["x"]).unwrap();
+        assert_eq!(
+            f(&[]),
+            Err(EvaluatorError::InvalidArgument(
+                "expected 1 input(s), got 0".to_string()
+            ))
+        );
+    }
+
+    #[rstest]
+    fn rejects_unsupported_expressions() {
+        for (input, expected) in compiler_cases::UNSUPPORTED {
+            assert_eq!(compile(&parse(input)).err().unwrap().to_string(), *expected);
+        }
+    }
+
+    #[rstest]
+    fn compiled_expressions_can_be_shared_between_threads() {
+        let f = &compile(&parse("x ^ 2")).unwrap();
+        let total: f64 = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|i| scope.spawn(move || f(&[i as f64]).unwrap()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert_eq!(total, 14.0);
+    }
+}
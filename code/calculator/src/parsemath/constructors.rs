@@ -0,0 +1,182 @@
+use super::parser::Expression;
+use crate::calcmath::functions;
+
+// Smart constructors shared by the tree-rewriting passes. Each one folds trivial
+// constants (`0 + u`, `1 * u`, `u ^ 1`) and inserts `Grouping` nodes wherever
+// operator precedence requires them, so the result prints back unambiguously.
+
+// Precedence of prefix `-`, and of numbers, variables, calls and anything
+// bracketed.
+pub(crate) const UNARY: u8 = 3;
+pub(crate) const PRIMARY: u8 = 5;
+
+// Binding strength used to decide where `Grouping` nodes are needed; mirrors the
+// grammar in parser.rs.
+pub(crate) fn precedence(expr: &Expression) -> u8 {
+    match expr {
+        Expression::Equation(..) | Expression::Range(..) => 0,
+        Expression::Add(..) | Expression::Subtract(..) => 1,
+        Expression::Multiply(..) | Expression::Divide(..) => 2,
+        Expression::Negate(..) => UNARY,
+        Expression::Number(n) if n.is_sign_negative() => UNARY,
+        Expression::Power(..) => 4,
+        _ => PRIMARY,
+    }
+}
+
+// Precedence for renderers that ignore the user's groupings. With stacked fractions
+// a division is drawn with a fraction bar, so it only needs brackets as a power base.
+pub(crate) fn rendered_precedence(expr: &Expression, stacked_fractions: bool) -> u8 {
+    match expr {
+        Expression::Grouping(inner) => rendered_precedence(inner, stacked_fractions),
+        Expression::Divide(..) if stacked_fractions => 4,
+        expr => precedence(expr),
+    }
+}
+
+pub(crate) fn group_below(expr: Expression, min_precedence: u8) -> Box<Expression> {
+    if precedence(&expr) < min_precedence {
+        Box::new(Expression::Grouping(Box::new(expr)))
+    } else {
+        Box::new(expr)
+    }
+}
+
+pub(crate) fn as_number(expr: &Expression) -> Option<f64> {
+    match expr {
+        Expression::Number(n) => Some(*n),
+        _ => None,
+    }
+}
+
+// Whether `expr` evaluates to a number. Free variables are taken to be numbers
+// unless `matrix_variables` is set; literals and calls known to return numbers
+// always are.
+pub(crate) fn is_scalar(expr: &Expression, matrix_variables: bool) -> bool {
+    let scalar = |e: &Expression| is_scalar(e, matrix_variables);
+    match expr {
+        Expression::Number(_) => true,
+        Expression::Variable(_) => !matrix_variables,
+        Expression::Call(name, _) => functions::returns_number(name),
+        Expression::Negate(e) | Expression::Grouping(e) => scalar(e),
+        Expression::Add(a, b)
+        | Expression::Subtract(a, b)
+        | Expression::Multiply(a, b)
+        | Expression::Divide(a, b) => scalar(a) && scalar(b),
+        Expression::Power(base, _) => scalar(base),
+        _ => false,
+    }
+}
+
+pub(crate) fn number(value: f64) -> Expression {
+    Expression::Number(value)
+}
+
+pub(crate) fn call(name: &str, arg: Expression) -> Expression {
+    Expression::Call(name.to_string(), vec![arg])
+}
+
+// Adding the number 0 to a matrix is an error, so `0 + u` only folds to `u` when
+// `u` is a number even if its variables hold matrices; the same goes for the
+// other constructors below.
+pub(crate) fn add(a: Expression, b: Expression) -> Expression {
+    match (as_number(&a), as_number(&b)) {
+        (Some(x), Some(y)) => number(x + y),
+        (Some(0.0), _) if is_scalar(&b, true) => b,
+        (_, Some(0.0)) if is_scalar(&a, true) => a,
+        _ => Expression::Add(group_below(a, 1), group_below(b, 2)),
+    }
+}
+
+pub(crate) fn subtract(a: Expression, b: Expression) -> Expression {
+    match (as_number(&a), as_number(&b)) {
+        (Some(x), Some(y)) => number(x - y),
+        (Some(0.0), _) if is_scalar(&b, true) => negate(b),
+        (_, Some(0.0)) if is_scalar(&a, true) => a,
+        _ => Expression::Subtract(group_below(a, 1), group_below(b, 2)),
+    }
+}
+
+// `add` and `subtract` for passes such as differentiation, whose operands are numbers.
+pub(crate) fn add_numbers(a: Expression, b: Expression) -> Expression {
+    match (as_number(&a), as_number(&b)) {
+        (Some(0.0), _) => b,
+        (_, Some(0.0)) => a,
+        _ => add(a, b),
+    }
+}
+
+pub(crate) fn subtract_numbers(a: Expression, b: Expression) -> Expression {
+    match (as_number(&a), as_number(&b)) {
+        (Some(0.0), _) => negate(b),
+        (_, Some(0.0)) => a,
+        _ => subtract(a, b),
+    }
+}
+
+pub(crate) fn multiply(a: Expression, b: Expression) -> Expression {
+    match (as_number(&a), as_number(&b)) {
+        (Some(x), Some(y)) => number(x * y),
+        // `0 * M` is a zero matrix, not the number 0.
+        (Some(0.0), _) if is_scalar(&b, true) => number(0.0),
+        (_, Some(0.0)) if is_scalar(&a, true) => number(0.0),
+        (Some(1.0), _) => b,
+        (_, Some(1.0)) => a,
+        _ => Expression::Multiply(group_below(a, 2), group_below(b, 3)),
+    }
+}
+
+// `multiply` for passes such as differentiation, whose operands are numbers.
+pub(crate) fn multiply_numbers(a: Expression, b: Expression) -> Expression {
+    match (as_number(&a), as_number(&b)) {
+        (Some(0.0), _) | (_, Some(0.0)) => number(0.0),
+        _ => multiply(a, b),
+    }
+}
+
+// `0 / u` is left alone: `u` may be zero, or a matrix, when it is evaluated.
+pub(crate) fn divide(a: Expression, b: Expression) -> Expression {
+    match (as_number(&a), as_number(&b)) {
+        (_, Some(0.0)) => Expression::Divide(group_below(a, 2), group_below(b, 3)),
+        (Some(x), Some(y)) => number(x / y),
+        (_, Some(1.0)) => a,
+        _ => Expression::Divide(group_below(a, 2), group_below(b, 3)),
+    }
+}
+
+// `M ^ 0` is an identity matrix, and `M ^ 1` fails for a matrix that is not square.
+pub(crate) fn power(a: Expression, b: Expression) -> Expression {
+    match as_number(&b) {
+        Some(0.0) if is_scalar(&a, true) => number(1.0),
+        Some(1.0) if is_scalar(&a, true) => a,
+        _ => Expression::Power(group_below(a, PRIMARY), group_below(b, UNARY)),
+    }
+}
+
+// `power` for passes such as differentiation, whose operands are numbers.
+pub(crate) fn power_numbers(a: Expression, b: Expression) -> Expression {
+    match as_number(&b) {
+        Some(0.0) => number(1.0),
+        Some(1.0) => a,
+        _ => power(a, b),
+    }
+}
+
+// Whether `expr` is a number that cannot be negative, so that `expr ^ a * expr ^ b`
+// is `expr ^ (a + b)` for any real exponents.
+pub(crate) fn is_non_negative(expr: &Expression) -> bool {
+    match expr {
+        Expression::Number(n) => *n >= 0.0,
+        Expression::Call(name, _) => matches!(name.as_str(), "abs" | "sqrt" | "exp"),
+        Expression::Grouping(e) => is_non_negative(e),
+        _ => false,
+    }
+}
+
+pub(crate) fn negate(mut a: Expression) -> Expression {
+    match &mut a {
+        Expression::Number(x) => number(-*x),
+        Expression::Negate(inner) => inner.take(),
+        _ => Expression::Negate(group_below(a, UNARY)),
+    }
+}
@@ -0,0 +1,335 @@
+use super::constructors::{
+    add, add_numbers, call, divide, multiply_numbers, negate, number, power, power_numbers,
+    subtract, subtract_numbers,
+};
+use super::parser::Expression;
+use super::visitors::{EvaluatorError, ExpressionVisitor};
+
+// Differentiates an expression with respect to `variable`, producing a new tree.
+// The result is built with light constant folding (`0 * u`, `1 * u`, `2 - 1`)
+// and with `Grouping` nodes wherever operator precedence requires them.
+pub struct DerivativeVisitor {
+    variable: String,
+}
+
+impl DerivativeVisitor {
+    pub fn new(variable: &str) -> Self {
+        DerivativeVisitor {
+            variable: variable.to_string(),
+        }
+    }
+
+    fn depends_on_variable(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Number(_) | Expression::Error => false,
+            Expression::Variable(name) => *name == self.variable,
+            Expression::Add(a, b)
+            | Expression::Subtract(a, b)
+            | Expression::Multiply(a, b)
+            | Expression::Divide(a, b)
+            | Expression::Power(a, b)
+            | Expression::Equation(a, b)
+            | Expression::Range(a, b) => self.depends_on_variable(a) || self.depends_on_variable(b),
+            Expression::Negate(e) | Expression::Grouping(e) => self.depends_on_variable(e),
+            Expression::List(elements) | Expression::Call(_, elements) => {
+                elements.iter().any(|e| self.depends_on_variable(e))
+            }
+            Expression::Index(target, indices) => {
+                self.depends_on_variable(target)
+                    || indices.iter().any(|e| self.depends_on_variable(e))
+            }
+        }
+    }
+
+    // Derivative of `name(u)` with respect to `u`, to be multiplied by u'.
+    fn outer_derivative(name: &str, u: &Expression) -> Result<Expression, EvaluatorError> {
+        let u = u.clone();
+        let derivative = match name {
+            "sin" => call("cos", u),
+            "cos" => negate(call("sin", u)),
+            "tan" => divide(number(1.0), power(call("cos", u), number(2.0))),
+            "exp" => call("exp", u),
+            "ln" => divide(number(1.0), u),
+            "log" => divide(number(1.0), multiply_numbers(u, call("ln", number(10.0)))),
+            "sqrt" => divide(number(1.0), multiply_numbers(number(2.0), call("sqrt", u))),
+            "abs" => divide(u.clone(), call("abs", u)),
+            "asin" => divide(
+                number(1.0),
+                call("sqrt", subtract(number(1.0), power(u, number(2.0)))),
+            ),
+            "acos" => negate(divide(
+                number(1.0),
+                call("sqrt", subtract(number(1.0), power(u, number(2.0)))),
+            )),
+            "atan" => divide(number(1.0), add(number(1.0), power(u, number(2.0)))),
+            "sinh" => call("cosh", u),
+            "cosh" => call("sinh", u),
+            "tanh" => divide(number(1.0), power(call("cosh", u), number(2.0))),
+            _ => {
+                return Err(EvaluatorError::NotDifferentiable(format!(
+                    "no derivative rule for '{}'",
+                    name
+                )));
+            }
+        };
+        Ok(derivative)
+    }
+}
+
+impl ExpressionVisitor<Expression, EvaluatorError> for DerivativeVisitor {
+    fn visit_number(&mut self, _: f64) -> Result<Expression, EvaluatorError> {
+        Ok(number(0.0))
+    }
+
+    fn visit_add(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Expression, EvaluatorError> {
+        Ok(add_numbers(
+            self.visit_expression(left)?,
+            self.visit_expression(right)?,
+        ))
+    }
+
+    fn visit_subtract(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Expression, EvaluatorError> {
+        Ok(subtract_numbers(
+            self.visit_expression(left)?,
+            self.visit_expression(right)?,
+        ))
+    }
+
+    // (uv)' = u'v + uv'
+    fn visit_multiply(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Expression, EvaluatorError> {
+        Ok(add_numbers(
+            multiply_numbers(self.visit_expression(left)?, right.clone()),
+            multiply_numbers(left.clone(), self.visit_expression(right)?),
+        ))
+    }
+
+    // (u/v)' = (u'v - uv') / v^2
+    fn visit_divide(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<Expression, EvaluatorError> {
+        if !self.depends_on_variable(right) {
+            return Ok(divide(self.visit_expression(left)?, right.clone()));
+        }
+
+        Ok(divide(
+            subtract_numbers(
+                multiply_numbers(self.visit_expression(left)?, right.clone()),
+                multiply_numbers(left.clone(), self.visit_expression(right)?),
+            ),
+            power(right.clone(), number(2.0)),
+        ))
+    }
+
+    // (u^n)' = n u^(n-1) u', (a^v)' = a^v ln(a) v', and in general
+    // (u^v)' = u^v (v' ln(u) + v u' / u).
+    fn visit_power(
+        &mut self,
+        base: &Expression,
+        exponent: &Expression,
+    ) -> Result<Expression, EvaluatorError> {
+        let base_varies = self.depends_on_variable(base);
+        let exponent_varies = self.depends_on_variable(exponent);
+
+        match (base_varies, exponent_varies) {
+            (false, false) => Ok(number(0.0)),
+            (true, false) => Ok(multiply_numbers(
+                multiply_numbers(
+                    exponent.clone(),
+                    power_numbers(base.clone(), subtract(exponent.clone(), number(1.0))),
+                ),
+                self.visit_expression(base)?,
+            )),
+            (false, true) => Ok(multiply_numbers(
+                multiply_numbers(
+                    power(base.clone(), exponent.clone()),
+                    call("ln", base.clone()),
+                ),
+                self.visit_expression(exponent)?,
+            )),
+            (true, true) => Ok(multiply_numbers(
+                power(base.clone(), exponent.clone()),
+                add_numbers(
+                    multiply_numbers(self.visit_expression(exponent)?, call("ln", base.clone())),
+                    divide(
+                        multiply_numbers(exponent.clone(), self.visit_expression(base)?),
+                        base.clone(),
+                    ),
+                ),
+            )),
+        }
+    }
+
+    fn visit_negate(&mut self, expr: &Expression) -> Result<Expression, EvaluatorError> {
+        Ok(negate(self.visit_expression(expr)?))
+    }
+
+    fn visit_grouping(&mut self, expr: &Expression) -> Result<Expression, EvaluatorError> {
+        self.visit_expression(expr)
+    }
+
+    fn visit_variable(&mut self, name: &str) -> Result<Expression, EvaluatorError> {
+        Ok(number(if name == self.variable { 1.0 } else { 0.0 }))
+    }
+
+    fn visit_equation(
+        &mut self,
+        _: &Expression,
+        _: &Expression,
+    ) -> Result<Expression, EvaluatorError> {
+        Err(EvaluatorError::NotDifferentiable(
+            "cannot differentiate an equation".to_string(),
+        ))
+    }
+
+    fn visit_range(
+        &mut self,
+        _: &Expression,
+        _: &Expression,
+    ) -> Result<Expression, EvaluatorError> {
+        Err(EvaluatorError::NotDifferentiable(
+            "cannot differentiate a range".to_string(),
+        ))
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) -> Result<Expression, EvaluatorError> {
+        let derivatives = elements
+            .iter()
+            .map(|e| self.visit_expression(e))
+            .collect::<Result<Vec<Expression>, EvaluatorError>>()?;
+        Ok(Expression::List(derivatives))
+    }
+
+    // Chain rule: f(u)' = f'(u) u'
+    fn visit_call(
+        &mut self,
+        name: &str,
+        args: &[Expression],
+    ) -> Result<Expression, EvaluatorError> {
+        if !args.iter().any(|arg| self.depends_on_variable(arg)) {
+            return Ok(number(0.0));
+        }
+
+        match args {
+            [u] => Ok(multiply_numbers(
+                Self::outer_derivative(name, u)?,
+                self.visit_expression(u)?,
+            )),
+            _ => Err(EvaluatorError::NotDifferentiable(format!(
+                "no derivative rule for '{}'",
+                name
+            ))),
+        }
+    }
+
+    fn visit_index(
+        &mut self,
+        target: &Expression,
+        indices: &[Expression],
+    ) -> Result<Expression, EvaluatorError> {
+        if !self.depends_on_variable(target) && !indices.iter().any(|e| self.depends_on_variable(e))
+        {
+            return Ok(number(0.0));
+        }
+        Err(EvaluatorError::NotDifferentiable(
+            "cannot differentiate an element access".to_string(),
+        ))
+    }
+
+    fn visit_error(&mut self) -> Result<Expression, EvaluatorError> {
+        Err(EvaluatorError::NotDifferentiable(
+            "the expression contains a parse error".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod derivative_tests {
+    use super::*;
+    use crate::parsemath::parser::Parser;
+    use crate::parsemath::visitors::{Evaluator, PrettyPrinterVisitor};
+    use rstest::rstest;
+
+    fn derive(input: &str) -> Result<Expression, EvaluatorError> {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        DerivativeVisitor::new("x").visit_expression(&ast)
+    }
+
+    fn print(expr: &Expression) -> String {
+        let mut printer = PrettyPrinterVisitor::new();
+        printer.visit_expression(expr).unwrap()
+    }
+
+    #[rstest]
+    #[case("5", "0")]
+    #[case("x", "1")]
+    #[case("y", "0")]
+    #[case("3 * x + 2", "3")]
+    #[case("x ^ 2", "2 * x")]
+    #[case("x ^ 2 * sin(x)", "2 * x * sin(x) + x ^ 2 * cos(x)")]
+    #[case("1 / x", "-1 / x ^ 2")]
+    #[case("(x + 1) / (x - 1)", "((x - 1) - (x + 1)) / (x - 1) ^ 2")]
+    #[case("-cos(x)", "sin(x)")]
+    #[case("exp(2 * x)", "exp(2 * x) * 2")]
+    #[case("2 ^ x", "2 ^ x * ln(2)")]
+    #[case("ln(x ^ 2 + 1)", "1 / (x ^ 2 + 1) * (2 * x)")]
+    fn differentiate_to_expression(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(print(&derive(input).unwrap()), expected);
+    }
+
+    #[rstest]
+    #[case("x ^ x", 1.0)]
+    #[case("x ^ x", 2.0)]
+    #[case("sqrt(x) * tan(x)", 0.5)]
+    #[case("atan(x) / (1 + x ^ 2)", 0.7)]
+    #[case("log(x) - asin(x / 2) + acos(x / 3)", 1.2)]
+    #[case("tanh(x) * cosh(x) - sinh(x ^ 2)", 0.3)]
+    #[case("abs(x - 2) ^ 3", 0.5)]
+    fn derivative_matches_finite_differences(#[case] input: &str, #[case] at: f64) {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        let symbolic = derive(input).unwrap();
+
+        let mut evaluator = Evaluator::new().with_variable("x", at);
+        let exact = evaluator
+            .visit_expression(&symbolic)
+            .unwrap()
+            .as_number()
+            .unwrap();
+        let h = 1e-6;
+        let plus = Evaluator::new()
+            .with_variable("x", at + h)
+            .visit_expression(&ast)
+            .unwrap();
+        let minus = Evaluator::new()
+            .with_variable("x", at - h)
+            .visit_expression(&ast)
+            .unwrap();
+        let numeric = (plus.as_number().unwrap() - minus.as_number().unwrap()) / (2.0 * h);
+
+        assert!((exact - numeric).abs() < 1e-6, "{} != {}", exact, numeric);
+    }
+
+    #[rstest]
+    #[case("det(x)")]
+    #[case("x = 1")]
+    #[case("mean(x, 2)")]
+    fn differentiate_unsupported(#[case] input: &str) {
+        assert!(matches!(
+            derive(input),
+            Err(EvaluatorError::NotDifferentiable(_))
+        ));
+    }
+}
@@ -0,0 +1,184 @@
+use super::parser::Expression;
+use super::visitors::ExpressionVisitor;
+
+// Dumps an expression tree as a Graphviz digraph for debugging, one node per
+// `Expression` with ids `n0`, `n1`, ... in pre-order. Unlike the printers it
+// keeps `Grouping` nodes so the tree is shown exactly as parsed. Render the
+// output with e.g. `dot -Tsvg`.
+#[derive(Default)]
+pub struct DotVisitor {
+    lines: Vec<String>,
+    next_id: usize,
+}
+
+impl DotVisitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn render(expr: &Expression) -> String {
+        let mut visitor = DotVisitor::new();
+        visitor
+            .visit_expression(expr)
+            .expect("rendering an expression cannot fail");
+        visitor.finish()
+    }
+
+    // The digraph of every expression visited so far.
+    pub fn finish(self) -> String {
+        let mut output = String::from("digraph Expression {\n");
+        for line in self.lines {
+            output.push_str("    ");
+            output.push_str(&line);
+            output.push('\n');
+        }
+        output.push('}');
+        output
+    }
+
+    fn node(&mut self, label: &str, children: &[&Expression]) -> Result<usize, ()> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.lines
+            .push(format!("n{} [label=\"{}\"];", id, escape(label)));
+        for child in children {
+            let child = self.visit_expression(child)?;
+            self.lines.push(format!("n{} -> n{};", id, child));
+        }
+        Ok(id)
+    }
+
+    fn node_all(
+        &mut self,
+        label: &str,
+        first: Option<&Expression>,
+        rest: &[Expression],
+    ) -> Result<usize, ()> {
+        let children: Vec<&Expression> = first.into_iter().chain(rest).collect();
+        self.node(label, &children)
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl ExpressionVisitor<usize, ()> for DotVisitor {
+    fn visit_number(&mut self, value: f64) -> Result<usize, ()> {
+        self.node(&value.to_string(), &[])
+    }
+
+    fn visit_add(&mut self, left: &Expression, right: &Expression) -> Result<usize, ()> {
+        self.node("+", &[left, right])
+    }
+
+    fn visit_subtract(&mut self, left: &Expression, right: &Expression) -> Result<usize, ()> {
+        self.node("-", &[left, right])
+    }
+
+    fn visit_multiply(&mut self, left: &Expression, right: &Expression) -> Result<usize, ()> {
+        self.node("*", &[left, right])
+    }
+
+    fn visit_divide(&mut self, left: &Expression, right: &Expression) -> Result<usize, ()> {
+        self.node("/", &[left, right])
+    }
+
+    fn visit_power(&mut self, base: &Expression, exponent: &Expression) -> Result<usize, ()> {
+        self.node("^", &[base, exponent])
+    }
+
+    fn visit_negate(&mut self, expr: &Expression) -> Result<usize, ()> {
+        self.node("neg", &[expr])
+    }
+
+    fn visit_grouping(&mut self, expr: &Expression) -> Result<usize, ()> {
+        self.node("( )", &[expr])
+    }
+
+    fn visit_variable(&mut self, name: &str) -> Result<usize, ()> {
+        self.node(name, &[])
+    }
+
+    fn visit_equation(&mut self, left: &Expression, right: &Expression) -> Result<usize, ()> {
+        self.node("=", &[left, right])
+    }
+
+    fn visit_range(&mut self, from: &Expression, to: &Expression) -> Result<usize, ()> {
+        self.node("..", &[from, to])
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) -> Result<usize, ()> {
+        self.node_all("[ ]", None, elements)
+    }
+
+    fn visit_call(&mut self, name: &str, args: &[Expression]) -> Result<usize, ()> {
+        self.node_all(&format!("{}()", name), None, args)
+    }
+
+    fn visit_index(&mut self, target: &Expression, indices: &[Expression]) -> Result<usize, ()> {
+        self.node_all("index", Some(target), indices)
+    }
+
+    fn visit_error(&mut self) -> Result<usize, ()> {
+        self.node("error", &[])
+    }
+}
+
+#[cfg(test)]
+mod dot_tests {
+    use super::*;
+    use crate::parsemath::parser::Parser;
+    use rstest::rstest;
+
+    fn dot(input: &str) -> String {
+        DotVisitor::render(&Parser::new(input).unwrap().parse().unwrap())
+    }
+
+    #[rstest]
+    fn render_digraph() {
+        assert_eq!(
+            dot("(1 + x) * -y"),
+            "digraph Expression {
+    n0 [label=\"*\"];
+    n1 [label=\"( )\"];
+    n2 [label=\"+\"];
+    n3 [label=\"1\"];
+    n2 -> n3;
+    n4 [label=\"x\"];
+    n2 -> n4;
+    n1 -> n2;
+    n0 -> n1;
+    n5 [label=\"neg\"];
+    n6 [label=\"y\"];
+    n5 -> n6;
+    n0 -> n5;
+}"
+        );
+    }
+
+    #[rstest]
+    fn render_calls_lists_and_indices() {
+        assert_eq!(
+            dot("max([a, 2][1])"),
+            "digraph Expression {
+    n0 [label=\"max()\"];
+    n1 [label=\"index\"];
+    n2 [label=\"[ ]\"];
+    n3 [label=\"a\"];
+    n2 -> n3;
+    n4 [label=\"2\"];
+    n2 -> n4;
+    n1 -> n2;
+    n5 [label=\"1\"];
+    n1 -> n5;
+    n0 -> n1;
+}"
+        );
+    }
+
+    #[rstest]
+    fn escape_labels() {
+        assert_eq!(escape("say \"hi\" \\"), "say \\\"hi\\\" \\\\");
+    }
+}
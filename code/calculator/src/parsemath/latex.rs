@@ -0,0 +1,212 @@
+use super::constructors::{PRIMARY, UNARY};
+use super::parser::Expression;
+use super::render::Renderer;
+use super::unicode::greek_letter;
+use super::visitors::ExpressionVisitor;
+
+// Renders an expression as LaTeX math-mode source, e.g. `\frac{1}{x^{2}}`.
+// User groupings are dropped and `\left( \right)` inserted only where operator
+// precedence requires them.
+pub struct LatexVisitor;
+
+// Functions with a dedicated LaTeX operator; everything else uses `\operatorname`.
+const OPERATORS: [(&str, &str); 17] = [
+    ("sin", "\\sin"),
+    ("cos", "\\cos"),
+    ("tan", "\\tan"),
+    ("asin", "\\arcsin"),
+    ("acos", "\\arccos"),
+    ("atan", "\\arctan"),
+    ("sinh", "\\sinh"),
+    ("cosh", "\\cosh"),
+    ("tanh", "\\tanh"),
+    ("exp", "\\exp"),
+    ("ln", "\\ln"),
+    ("log", "\\log"),
+    ("det", "\\det"),
+    ("min", "\\min"),
+    ("max", "\\max"),
+    ("gcd", "\\gcd"),
+    ("lcm", "\\operatorname{lcm}"),
+];
+
+// A fraction counts as a power so that it is bracketed as a base
+// (`\left(\frac{a}{b}\right)^{2}`) but nowhere else.
+impl Renderer<String> for LatexVisitor {
+    const STACKED_FRACTIONS: bool = true;
+
+    fn bracket(text: String) -> String {
+        format!("\\left({}\\right)", text)
+    }
+
+    fn infix(left: String, operator: &str, right: String) -> String {
+        format!("{} {} {}", left, operator, right)
+    }
+
+    fn separated(items: Vec<String>) -> String {
+        items.join(", ")
+    }
+}
+
+impl ExpressionVisitor<String, ()> for LatexVisitor {
+    fn visit_number(&mut self, value: f64) -> Result<String, ()> {
+        Ok(value.to_string())
+    }
+
+    fn visit_add(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, "+", right, 1)
+    }
+
+    fn visit_subtract(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, "-", right, 1)
+    }
+
+    fn visit_multiply(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, "\\cdot", right, 2)
+    }
+
+    // The fraction bar already groups both operands.
+    fn visit_divide(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        Ok(format!(
+            "\\frac{{{}}}{{{}}}",
+            self.visit_expression(left)?,
+            self.visit_expression(right)?
+        ))
+    }
+
+    fn visit_power(&mut self, base: &Expression, exponent: &Expression) -> Result<String, ()> {
+        Ok(format!(
+            "{}^{{{}}}",
+            self.operand(base, PRIMARY)?,
+            self.visit_expression(exponent)?
+        ))
+    }
+
+    fn visit_negate(&mut self, expr: &Expression) -> Result<String, ()> {
+        Ok(format!("-{}", self.operand(expr, UNARY)?))
+    }
+
+    fn visit_grouping(&mut self, expr: &Expression) -> Result<String, ()> {
+        self.visit_expression(expr)
+    }
+
+    fn visit_variable(&mut self, name: &str) -> Result<String, ()> {
+        if greek_letter(name).is_some() {
+            Ok(format!("\\{}", name))
+        } else if name.chars().count() == 1 {
+            Ok(name.to_string())
+        } else {
+            Ok(format!("\\mathrm{{{}}}", name.replace('_', "\\_")))
+        }
+    }
+
+    fn visit_equation(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        Ok(format!(
+            "{} = {}",
+            self.operand(left, 1)?,
+            self.operand(right, 1)?
+        ))
+    }
+
+    fn visit_range(&mut self, from: &Expression, to: &Expression) -> Result<String, ()> {
+        Ok(format!(
+            "{} \\ldots {}",
+            self.operand(from, 1)?,
+            self.operand(to, 1)?
+        ))
+    }
+
+    // A flat list is a row vector; a list of lists is a matrix with one row each.
+    fn visit_list(&mut self, elements: &[Expression]) -> Result<String, ()> {
+        let rows = elements
+            .iter()
+            .map(|element| match element {
+                Expression::List(row) => Ok(row
+                    .iter()
+                    .map(|e| self.visit_expression(e))
+                    .collect::<Result<Vec<String>, ()>>()?
+                    .join(" & ")),
+                element => self.visit_expression(element),
+            })
+            .collect::<Result<Vec<String>, ()>>()?;
+
+        let separator = if elements.iter().all(|e| matches!(e, Expression::List(_))) {
+            " \\\\ "
+        } else {
+            " & "
+        };
+        Ok(format!(
+            "\\begin{{bmatrix}} {} \\end{{bmatrix}}",
+            rows.join(separator)
+        ))
+    }
+
+    fn visit_call(&mut self, name: &str, args: &[Expression]) -> Result<String, ()> {
+        match (name, args) {
+            ("sqrt", [arg]) => Ok(format!("\\sqrt{{{}}}", self.visit_expression(arg)?)),
+            ("abs", [arg]) => Ok(format!("\\left|{}\\right|", self.visit_expression(arg)?)),
+            _ => {
+                let operator = match OPERATORS.iter().find(|(function, _)| *function == name) {
+                    Some((_, operator)) => operator.to_string(),
+                    None => format!("\\operatorname{{{}}}", name.replace('_', "\\_")),
+                };
+                Ok(format!(
+                    "{}\\left({}\\right)",
+                    operator,
+                    self.visit_all(args)?
+                ))
+            }
+        }
+    }
+
+    fn visit_index(&mut self, target: &Expression, indices: &[Expression]) -> Result<String, ()> {
+        Ok(format!(
+            "{}_{{{}}}",
+            self.operand(target, PRIMARY)?,
+            self.visit_all(indices)?
+        ))
+    }
+
+    fn visit_error(&mut self) -> Result<String, ()> {
+        Ok("?".to_string())
+    }
+}
+
+#[cfg(test)]
+mod latex_tests {
+    use super::*;
+    use crate::parsemath::parser::Parser;
+    use rstest::rstest;
+
+    fn latex(input: &str) -> String {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        LatexVisitor.visit_expression(&ast).unwrap()
+    }
+
+    #[rstest]
+    #[case("1 + 2 * x", "1 + 2 \\cdot x")]
+    #[case("(1 + 2) * x", "\\left(1 + 2\\right) \\cdot x")]
+    #[case("a - (b - c)", "a - \\left(b - c\\right)")]
+    #[case("(a - b) - c", "a - b - c")]
+    #[case("(a + b) / (c * d)", "\\frac{a + b}{c \\cdot d}")]
+    #[case("(a / b) ^ 2", "\\left(\\frac{a}{b}\\right)^{2}")]
+    #[case("x ^ (2 * n)", "x^{2 \\cdot n}")]
+    #[case("(x ^ 2) ^ 3", "\\left(x^{2}\\right)^{3}")]
+    #[case("-(x + 1)", "-\\left(x + 1\\right)")]
+    #[case("sqrt(x ^ 2 + 1)", "\\sqrt{x^{2} + 1}")]
+    #[case(
+        "abs(x) * sin(2 * pi * t)",
+        "\\left|x\\right| \\cdot \\sin\\left(2 \\cdot \\pi \\cdot t\\right)"
+    )]
+    #[case("nCr(n, k)", "\\operatorname{nCr}\\left(n, k\\right)")]
+    #[case("rate_2 = 3", "\\mathrm{rate\\_2} = 3")]
+    #[case("[[1, 2], [3, 4]]", "\\begin{bmatrix} 1 & 2 \\\\ 3 & 4 \\end{bmatrix}")]
+    #[case("[1, 2][2]", "\\begin{bmatrix} 1 & 2 \\end{bmatrix}_{2}")]
+    #[case(
+        "solve(x ^ 2 = 2, x, 0..5)",
+        "\\operatorname{solve}\\left(x^{2} = 2, x, 0 \\ldots 5\\right)"
+    )]
+    fn render_latex(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(latex(input), expected);
+    }
+}
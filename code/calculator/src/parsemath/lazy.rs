@@ -0,0 +1,196 @@
+use super::derivative::DerivativeVisitor;
+use super::parser::Expression;
+use super::visitors::{EvaluatorError, ExpressionVisitor};
+use crate::calcmath::functions;
+
+// A call to `integrate`, `diff` or `solve`, whose body is evaluated as a function
+// of one variable rather than up front. The compilers decode their arguments
+// here and only differ in what they emit for each kind.
+pub(crate) enum LazyCall<'e> {
+    // `body` with `variable` bound to `at`, e.g. a symbolic derivative.
+    Bind {
+        body: Expression,
+        variable: &'e str,
+        at: &'e Expression,
+    },
+    Integrate {
+        body: Expression,
+        variable: &'e str,
+        from: &'e Expression,
+        to: &'e Expression,
+    },
+    // Numerical differentiation, for bodies without derivative rules.
+    Differentiate {
+        body: Expression,
+        variable: &'e str,
+        at: &'e Expression,
+    },
+    // A root found with Newton's method from `guess`, or from 1.
+    Newton {
+        body: Expression,
+        variable: &'e str,
+        guess: Option<&'e Expression>,
+    },
+    // The only root in `from..to`.
+    FindRoot {
+        body: Expression,
+        variable: &'e str,
+        from: &'e Expression,
+        to: &'e Expression,
+    },
+}
+
+impl<'e> LazyCall<'e> {
+    // `None` for any other call. Like the evaluator, `diff` prefers the symbolic
+    // derivative and `solve` solves an equation `lhs = rhs` for `lhs - rhs = 0`.
+    pub(crate) fn decode(
+        name: &str,
+        args: &'e [Expression],
+    ) -> Option<Result<Self, EvaluatorError>> {
+        match (name, args) {
+            ("solve", [equation, Expression::Variable(variable)]) => {
+                Some(Ok(Self::solve(equation, variable, None)))
+            }
+            ("solve", [equation, Expression::Variable(variable), search]) => {
+                Some(Ok(Self::solve(equation, variable, Some(search))))
+            }
+            ("integrate", _) => Some(Self::integrate(args)),
+            ("diff", _) => Some(Self::differentiate(args)),
+            _ => None,
+        }
+    }
+
+    fn integrate(args: &'e [Expression]) -> Result<Self, EvaluatorError> {
+        let [body, variable, from, to] = functions::expect_args("integrate", args)?;
+        Ok(LazyCall::Integrate {
+            body: body.clone(),
+            variable: variable_name("integrate", variable)?,
+            from,
+            to,
+        })
+    }
+
+    fn differentiate(args: &'e [Expression]) -> Result<Self, EvaluatorError> {
+        let [body, variable, at] = functions::expect_args("diff", args)?;
+        let variable = variable_name("diff", variable)?;
+        match DerivativeVisitor::new(variable).visit_expression(body) {
+            Ok(derivative) => Ok(LazyCall::Bind {
+                body: derivative,
+                variable,
+                at,
+            }),
+            Err(EvaluatorError::NotDifferentiable(_)) => Ok(LazyCall::Differentiate {
+                body: body.clone(),
+                variable,
+                at,
+            }),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn solve(equation: &'e Expression, variable: &'e str, search: Option<&'e Expression>) -> Self {
+        let body = match equation {
+            Expression::Equation(left, right) => Expression::Subtract(left.clone(), right.clone()),
+            expression => expression.clone(),
+        };
+        match search {
+            Some(Expression::Range(from, to)) => LazyCall::FindRoot {
+                body,
+                variable,
+                from,
+                to,
+            },
+            guess => LazyCall::Newton {
+                body,
+                variable,
+                guess,
+            },
+        }
+    }
+}
+
+fn variable_name<'e>(
+    function: &str,
+    expression: &'e Expression,
+) -> Result<&'e str, EvaluatorError> {
+    match expression {
+        Expression::Variable(name) => Ok(name),
+        _ => Err(EvaluatorError::InvalidArgument(format!(
+            "{} expects a variable name as its second argument",
+            function
+        ))),
+    }
+}
+
+// Cases run by both compilers, which support the same expressions.
+#[cfg(test)]
+pub(crate) mod compiler_cases {
+    use super::*;
+
+    // Expressions and variable values for which a compiled expression must
+    // agree with the evaluator.
+    pub(crate) const RUNS_LIKE_THE_EVALUATOR: &[(&str, &[(&str, f64)])] = &[
+        ("1 + 2 * 3 - 4 / 8", &[]),
+        ("-(x - 1) ^ 2 * y", &[("x", 3.0), ("y", 0.5)]),
+        ("2 * pi * r", &[("r", 2.0)]),
+        ("sqrt(x) + sin(x) ^ 2 + cos(x) ^ 2", &[("x", 2.0)]),
+        (
+            "max(x, 2, y) + nCr(5, 2) + gcd(12, 18)",
+            &[("x", 1.0), ("y", 7.0)],
+        ),
+        ("integrate(t ^ 2 * a, t, 0, b)", &[("a", 3.0), ("b", 2.0)]),
+        ("diff(x ^ 3, x, a) + diff(mean(x, 2), x, 1)", &[("a", 2.0)]),
+        (
+            "solve(x ^ 2 = a, x) + solve(cos(x), x, 1..2)",
+            &[("a", 2.0)],
+        ),
+        ("integrate(integrate(x * y, y, 0, x), x, 0, 1)", &[]),
+    ];
+
+    // Expressions of `x` that fail at x = 1.
+    pub(crate) fn evaluation_errors() -> Vec<(&'static str, EvaluatorError)> {
+        vec![
+            ("1 / (x - x)", EvaluatorError::DivisionByZero),
+            (
+                "sqrt(x - 5)",
+                EvaluatorError::InvalidArgument("sqrt is undefined for -4".to_string()),
+            ),
+            ("foo(x)", EvaluatorError::UnknownFunction("foo".to_string())),
+            (
+                "nCr(x)",
+                EvaluatorError::ArgumentCount {
+                    function: "nCr".to_string(),
+                    expected: 2,
+                    found: 1,
+                },
+            ),
+            (
+                "solve(sin(x), x, 1..7)",
+                EvaluatorError::TypeMismatch("expected a number, got a matrix".to_string()),
+            ),
+            (
+                "det(identity(100000 * x))",
+                EvaluatorError::InvalidArgument(
+                    "identity(100000) would have more than 1048576 elements".to_string(),
+                ),
+            ),
+        ]
+    }
+
+    // Expressions that do not compile, with the error message.
+    pub(crate) const UNSUPPORTED: &[(&str, &str)] = &[
+        (
+            "[1, 2]",
+            "Cannot compile: lists and matrices are not supported",
+        ),
+        ("v[1]", "Cannot compile: indexing is not supported"),
+        (
+            "x = 1",
+            "Unexpected expression: equations are only allowed inside solve()",
+        ),
+        (
+            "integrate(x, 2, 0, 1)",
+            "Invalid argument: integrate expects a variable name as its second argument",
+        ),
+    ];
+}
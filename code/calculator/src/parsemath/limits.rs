@@ -0,0 +1,241 @@
+use super::parser::Expression;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+// Deep enough for hand-written formulas, shallow enough that the parser and the
+// recursive visitors fit in a 2 MiB thread stack even in debug builds.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+// Chains such as `1 + 1 + ... + 1` do not count towards the depth, yet every
+// recursive pass, and dropping the tree, still recurses once per term. This
+// bounds them so that the longest tree the parser accepts by default fits in the
+// same 2 MiB stack; raise it only for trees that are just evaluated.
+pub const DEFAULT_MAX_NODES: usize = 1_000;
+
+// Resource limits for parsing and evaluating untrusted input. The defaults only
+// bound the nesting depth and node count; set the other fields to restrict user
+// submissions:
+//
+//     let limits = EvalLimits {
+//         max_input_length: 4096,
+//         max_steps: 100_000,
+//         timeout: Some(Duration::from_millis(50)),
+//         ..EvalLimits::default()
+//     };
+//
+// `Parser` enforces the input length, token count, depth and node count;
+// `Evaluator` enforces depth and node count again (its input need not come from
+// the parser), the step budget, the value size and the timeout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalLimits {
+    // Bytes of source text.
+    pub max_input_length: usize,
+    pub max_tokens: usize,
+    // Levels of nesting: parentheses, brackets, calls, prefix operators and the
+    // exponent of `^` each add one, while the operands of other binary operators
+    // do not, so `1 + 2 + 3` has depth 0 and `-(1)` 2.
+    pub max_depth: usize,
+    pub max_nodes: usize,
+    // Nodes visited during one evaluation, counting every repetition inside
+    // `solve`, `integrate` and `diff`, plus one per element of the arguments
+    // passed to built-in functions.
+    pub max_steps: u64,
+    // Elements of a single value: 1 for a number, rows * columns for a matrix.
+    pub max_value_size: usize,
+    // Wall-clock time for one evaluation.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for EvalLimits {
+    fn default() -> Self {
+        Self {
+            max_input_length: usize::MAX,
+            max_tokens: usize::MAX,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_nodes: DEFAULT_MAX_NODES,
+            max_steps: u64::MAX,
+            max_value_size: usize::MAX,
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum LimitError {
+    #[error("input is {length} bytes long, the limit is {limit}")]
+    InputTooLong { length: usize, limit: usize },
+    #[error("input has more than {0} tokens")]
+    TooManyTokens(usize),
+    #[error("expression nests deeper than {0} levels")]
+    TooDeeplyNested(usize),
+    #[error("expression has more than {0} nodes")]
+    TooManyNodes(usize),
+    #[error("evaluation took more than {0} steps")]
+    StepBudgetExhausted(u64),
+    #[error("a value of {size} elements exceeds the limit of {limit}")]
+    ValueTooLarge { size: usize, limit: usize },
+    #[error("evaluation took longer than {0:?}")]
+    DeadlineExceeded(Duration),
+}
+
+// Checks the depth and node count of a tree without recursing.
+pub fn check_tree(expr: &Expression, limits: &EvalLimits) -> Result<(), LimitError> {
+    let mut nodes = 0;
+    let mut pending = vec![(expr, 0)];
+    while let Some((expr, depth)) = pending.pop() {
+        nodes += 1;
+        if nodes > limits.max_nodes {
+            return Err(LimitError::TooManyNodes(limits.max_nodes));
+        }
+        if depth > limits.max_depth {
+            return Err(LimitError::TooDeeplyNested(limits.max_depth));
+        }
+        let children = expr.children().into_iter().enumerate();
+        pending.extend(children.map(|(i, child)| (child, depth + nests(expr, i) as usize)));
+    }
+    Ok(())
+}
+
+// Whether the `index`-th child of `expr` is nested a level deeper. Chains of
+// left-associative operators, and of indices as in `m[1][2]`, are not.
+fn nests(expr: &Expression, index: usize) -> bool {
+    match expr {
+        Expression::Add(..)
+        | Expression::Subtract(..)
+        | Expression::Multiply(..)
+        | Expression::Divide(..)
+        | Expression::Equation(..)
+        | Expression::Range(..) => false,
+        Expression::Power(..) | Expression::Index(..) => index > 0,
+        _ => true,
+    }
+}
+
+pub fn check_value_size(size: usize, limits: &EvalLimits) -> Result<(), LimitError> {
+    if size > limits.max_value_size {
+        return Err(LimitError::ValueTooLarge {
+            size,
+            limit: limits.max_value_size,
+        });
+    }
+    Ok(())
+}
+
+// The step budget and deadline of one evaluation in progress.
+#[derive(Debug)]
+pub struct Budget {
+    steps: u64,
+    max_steps: u64,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+}
+
+impl Budget {
+    // Reading the clock costs about as much as a step, so it is only read
+    // every `CLOCK_INTERVAL` steps.
+    const CLOCK_INTERVAL: u64 = 64;
+
+    pub fn start(limits: &EvalLimits) -> Self {
+        Self {
+            steps: 0,
+            max_steps: limits.max_steps,
+            timeout: limits.timeout,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+        }
+    }
+
+    pub fn step(&mut self) -> Result<(), LimitError> {
+        self.charge(1)
+    }
+
+    // Spends `steps` steps at once, for work proportional to the size of a
+    // value, such as a built-in function running over every element of a matrix.
+    pub fn charge(&mut self, steps: u64) -> Result<(), LimitError> {
+        let before = self.steps;
+        self.steps = self.steps.saturating_add(steps);
+        if self.steps > self.max_steps {
+            return Err(LimitError::StepBudgetExhausted(self.max_steps));
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.timeout)
+            && self.steps / Self::CLOCK_INTERVAL != before / Self::CLOCK_INTERVAL
+            && Instant::now() >= deadline
+        {
+            return Err(LimitError::DeadlineExceeded(timeout));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod limits_tests {
+    use super::*;
+    use crate::parsemath::parser::Parser;
+    use rstest::rstest;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input).unwrap().parse().unwrap()
+    }
+
+    #[rstest]
+    #[case("1", 0, 1)]
+    #[case("-(1)", 2, 3)]
+    #[case("1 + 2 * x", 0, 5)]
+    #[case("1 - 2 - 3 - 4", 0, 7)]
+    #[case("2 ^ -x", 2, 4)]
+    #[case("f([1, 2], 3)[1]", 2, 7)]
+    fn measures_depth_and_nodes(#[case] input: &str, #[case] depth: usize, #[case] nodes: usize) {
+        let ast = parse(input);
+        let limits = |max_depth, max_nodes| EvalLimits {
+            max_depth,
+            max_nodes,
+            ..EvalLimits::default()
+        };
+        assert_eq!(check_tree(&ast, &limits(depth, nodes)), Ok(()));
+        assert_eq!(
+            check_tree(&ast, &limits(depth, nodes - 1)),
+            Err(LimitError::TooManyNodes(nodes - 1))
+        );
+        if depth > 0 {
+            assert_eq!(
+                check_tree(&ast, &limits(depth - 1, nodes)),
+                Err(LimitError::TooDeeplyNested(depth - 1))
+            );
+        }
+    }
+
+    #[rstest]
+    fn budget_counts_steps() {
+        let mut budget = Budget::start(&EvalLimits {
+            max_steps: 3,
+            ..EvalLimits::default()
+        });
+        assert!((0..3).all(|_| budget.step().is_ok()));
+        assert_eq!(budget.step(), Err(LimitError::StepBudgetExhausted(3)));
+    }
+
+    #[rstest]
+    fn budget_charges_many_steps() {
+        let mut budget = Budget::start(&EvalLimits {
+            max_steps: 100,
+            timeout: Some(Duration::ZERO),
+            ..EvalLimits::default()
+        });
+        assert_eq!(budget.charge(10), Ok(()));
+        assert_eq!(
+            budget.charge(Budget::CLOCK_INTERVAL),
+            Err(LimitError::DeadlineExceeded(Duration::ZERO))
+        );
+        assert_eq!(budget.charge(100), Err(LimitError::StepBudgetExhausted(100)));
+    }
+
+    #[rstest]
+    fn budget_reads_the_clock() {
+        let timeout = Duration::ZERO;
+        let mut budget = Budget::start(&EvalLimits {
+            timeout: Some(timeout),
+            ..EvalLimits::default()
+        });
+        let result = (0..Budget::CLOCK_INTERVAL).try_for_each(|_| budget.step());
+        assert_eq!(result, Err(LimitError::DeadlineExceeded(timeout)));
+    }
+}
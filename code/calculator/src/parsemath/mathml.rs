@@ -0,0 +1,219 @@
+use super::constructors::{PRIMARY, UNARY};
+use super::parser::Expression;
+use super::render::Renderer;
+use super::unicode::greek_letter;
+use super::visitors::ExpressionVisitor;
+
+// Renders an expression as MathML presentation markup for web pages. Visiting
+// produces the content of a `<math>` element; `render` wraps it in one.
+pub struct MathMLVisitor;
+
+impl MathMLVisitor {
+    pub fn render(expr: &Expression) -> String {
+        let content = MathMLVisitor
+            .visit_expression(expr)
+            .expect("rendering an expression cannot fail");
+        format!(
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">{}</math>",
+            content
+        )
+    }
+}
+
+// Like the LaTeX renderer, a fraction only needs brackets as a power base.
+impl Renderer<String> for MathMLVisitor {
+    const STACKED_FRACTIONS: bool = true;
+
+    fn bracket(markup: String) -> String {
+        fenced(&markup)
+    }
+
+    fn infix(left: String, operator: &str, right: String) -> String {
+        format!("<mrow>{}<mo>{}</mo>{}</mrow>", left, operator, right)
+    }
+
+    fn separated(items: Vec<String>) -> String {
+        items.join("<mo>,</mo>")
+    }
+}
+
+fn fenced(markup: &str) -> String {
+    format!("<mrow><mo>(</mo>{}<mo>)</mo></mrow>", markup)
+}
+
+impl ExpressionVisitor<String, ()> for MathMLVisitor {
+    fn visit_number(&mut self, value: f64) -> Result<String, ()> {
+        if value.is_sign_negative() {
+            Ok(format!("<mrow><mo>-</mo><mn>{}</mn></mrow>", -value))
+        } else {
+            Ok(format!("<mn>{}</mn>", value))
+        }
+    }
+
+    fn visit_add(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, "+", right, 1)
+    }
+
+    fn visit_subtract(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, "-", right, 1)
+    }
+
+    fn visit_multiply(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, "⋅", right, 2)
+    }
+
+    fn visit_divide(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        Ok(format!(
+            "<mfrac>{}{}</mfrac>",
+            self.visit_expression(left)?,
+            self.visit_expression(right)?
+        ))
+    }
+
+    fn visit_power(&mut self, base: &Expression, exponent: &Expression) -> Result<String, ()> {
+        Ok(format!(
+            "<msup>{}{}</msup>",
+            self.operand(base, PRIMARY)?,
+            self.visit_expression(exponent)?
+        ))
+    }
+
+    fn visit_negate(&mut self, expr: &Expression) -> Result<String, ()> {
+        Ok(format!(
+            "<mrow><mo>-</mo>{}</mrow>",
+            self.operand(expr, UNARY)?
+        ))
+    }
+
+    fn visit_grouping(&mut self, expr: &Expression) -> Result<String, ()> {
+        self.visit_expression(expr)
+    }
+
+    fn visit_variable(&mut self, name: &str) -> Result<String, ()> {
+        match greek_letter(name) {
+            Some(letter) => Ok(format!("<mi>{}</mi>", letter)),
+            None => Ok(format!("<mi>{}</mi>", name)),
+        }
+    }
+
+    fn visit_equation(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        Ok(format!(
+            "<mrow>{}<mo>=</mo>{}</mrow>",
+            self.operand(left, 1)?,
+            self.operand(right, 1)?
+        ))
+    }
+
+    fn visit_range(&mut self, from: &Expression, to: &Expression) -> Result<String, ()> {
+        Ok(format!(
+            "<mrow>{}<mo>…</mo>{}</mrow>",
+            self.operand(from, 1)?,
+            self.operand(to, 1)?
+        ))
+    }
+
+    // A flat list is a single-row table; a list of lists has one row each.
+    fn visit_list(&mut self, elements: &[Expression]) -> Result<String, ()> {
+        let mut cell = |e: &Expression| -> Result<String, ()> {
+            Ok(format!("<mtd>{}</mtd>", self.visit_expression(e)?))
+        };
+
+        let rows = if elements.iter().all(|e| matches!(e, Expression::List(_))) {
+            elements
+                .iter()
+                .map(|row| match row {
+                    Expression::List(cells) => cells.iter().map(&mut cell).collect(),
+                    _ => unreachable!(),
+                })
+                .collect::<Result<Vec<String>, ()>>()?
+        } else {
+            vec![elements.iter().map(cell).collect::<Result<String, ()>>()?]
+        };
+
+        let table: String = rows
+            .iter()
+            .map(|row| format!("<mtr>{}</mtr>", row))
+            .collect();
+        Ok(format!(
+            "<mrow><mo>[</mo><mtable>{}</mtable><mo>]</mo></mrow>",
+            table
+        ))
+    }
+
+    fn visit_call(&mut self, name: &str, args: &[Expression]) -> Result<String, ()> {
+        match (name, args) {
+            ("sqrt", [arg]) => Ok(format!("<msqrt>{}</msqrt>", self.visit_expression(arg)?)),
+            ("abs", [arg]) => Ok(format!(
+                "<mrow><mo>|</mo>{}<mo>|</mo></mrow>",
+                self.visit_expression(arg)?
+            )),
+            _ => Ok(format!(
+                "<mrow><mi>{}</mi><mo>&#x2061;</mo>{}</mrow>",
+                name,
+                fenced(&self.visit_all(args)?)
+            )),
+        }
+    }
+
+    fn visit_index(&mut self, target: &Expression, indices: &[Expression]) -> Result<String, ()> {
+        Ok(format!(
+            "<msub>{}<mrow>{}</mrow></msub>",
+            self.operand(target, PRIMARY)?,
+            self.visit_all(indices)?
+        ))
+    }
+
+    fn visit_error(&mut self) -> Result<String, ()> {
+        Ok("<merror><mtext>?</mtext></merror>".to_string())
+    }
+}
+
+#[cfg(test)]
+mod mathml_tests {
+    use super::*;
+    use crate::parsemath::parser::Parser;
+    use rstest::rstest;
+
+    fn mathml(input: &str) -> String {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        MathMLVisitor.visit_expression(&ast).unwrap()
+    }
+
+    #[rstest]
+    #[case("x", "<mi>x</mi>")]
+    #[case("-2.5", "<mrow><mo>-</mo><mn>2.5</mn></mrow>")]
+    #[case(
+        "a - (b - c)",
+        "<mrow><mi>a</mi><mo>-</mo><mrow><mo>(</mo><mrow><mi>b</mi><mo>-</mo><mi>c</mi></mrow><mo>)</mo></mrow></mrow>"
+    )]
+    #[case(
+        "(1 + x) / 2",
+        "<mfrac><mrow><mn>1</mn><mo>+</mo><mi>x</mi></mrow><mn>2</mn></mfrac>"
+    )]
+    #[case(
+        "x ^ 2 * pi",
+        "<mrow><msup><mi>x</mi><mn>2</mn></msup><mo>⋅</mo><mi>π</mi></mrow>"
+    )]
+    #[case("sqrt(y)", "<msqrt><mi>y</mi></msqrt>")]
+    #[case(
+        "max(a, 1)",
+        "<mrow><mi>max</mi><mo>&#x2061;</mo><mrow><mo>(</mo><mi>a</mi><mo>,</mo><mn>1</mn><mo>)</mo></mrow></mrow>"
+    )]
+    #[case(
+        "[[1, 2], [3, 4]]",
+        "<mrow><mo>[</mo><mtable><mtr><mtd><mn>1</mn></mtd><mtd><mn>2</mn></mtd></mtr><mtr><mtd><mn>3</mn></mtd><mtd><mn>4</mn></mtd></mtr></mtable><mo>]</mo></mrow>"
+    )]
+    #[case("v[2]", "<msub><mi>v</mi><mrow><mn>2</mn></mrow></msub>")]
+    fn render_mathml(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(mathml(input), expected);
+    }
+
+    #[rstest]
+    fn render_wraps_in_math_element() {
+        let ast = Parser::new("1").unwrap().parse().unwrap();
+        assert_eq!(
+            MathMLVisitor::render(&ast),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mn>1</mn></math>"
+        );
+    }
+}
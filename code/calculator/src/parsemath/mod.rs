@@ -1,3 +1,25 @@
+pub mod arena;
+pub mod ascii_art;
+pub mod batch;
+pub mod bytecode;
+pub mod closures;
+mod constructors;
+pub mod derivative;
+pub mod dot;
+pub mod latex;
+mod lazy;
+pub mod limits;
+pub mod mathml;
+pub mod operators;
 pub mod tokenizer;
+pub mod transform;
+pub mod unicode;
 pub mod parser;
+mod render;
+pub mod rpn;
+#[cfg(feature = "serde")]
+pub mod serialization;
+pub mod sexpr;
+pub mod simplifier;
+pub mod substitution;
 pub mod visitors;
\ No newline at end of file
@@ -0,0 +1,195 @@
+use super::parser::Expression;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+    // `a = b = c` is rejected rather than grouped either way.
+    NonAssociative,
+}
+
+// Where an operator stands relative to its operands, and how it builds the tree.
+#[derive(Debug, Clone, Copy)]
+pub enum Fixity {
+    Prefix(fn(Expression) -> Expression),
+    Infix(Associativity, fn(Expression, Expression) -> Expression),
+    Postfix(fn(Expression) -> Expression),
+}
+
+// An operator known to the parser. Operators with a higher precedence bind
+// tighter. A prefix operator's operand extends over operators of at least its
+// own precedence, so with the standard table `-2 ^ 2` is `-(2 ^ 2)` while
+// `-2 * 3` is `(-2) * 3`. Indexing with `[...]` binds tighter than any operator.
+#[derive(Debug, Clone)]
+pub struct Operator {
+    pub symbol: String,
+    pub precedence: u8,
+    pub fixity: Fixity,
+}
+
+impl Operator {
+    pub fn prefix(symbol: &str, precedence: u8, build: fn(Expression) -> Expression) -> Self {
+        Self::new(symbol, precedence, Fixity::Prefix(build))
+    }
+
+    pub fn infix(
+        symbol: &str,
+        precedence: u8,
+        associativity: Associativity,
+        build: fn(Expression, Expression) -> Expression,
+    ) -> Self {
+        Self::new(symbol, precedence, Fixity::Infix(associativity, build))
+    }
+
+    pub fn postfix(symbol: &str, precedence: u8, build: fn(Expression) -> Expression) -> Self {
+        Self::new(symbol, precedence, Fixity::Postfix(build))
+    }
+
+    fn new(symbol: &str, precedence: u8, fixity: Fixity) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            precedence,
+            fixity,
+        }
+    }
+
+    fn is_prefix(&self) -> bool {
+        matches!(self.fixity, Fixity::Prefix(_))
+    }
+}
+
+// The operators a `Parser` recognizes. A symbol may be both a prefix and an
+// infix operator, like `-`; when it is both infix and postfix the postfix
+// reading wins. Symbols other than the built-in `+ - * / ^ = ..` are passed to
+// the tokenizer, so hosts can add operators such as `±` or `→`:
+//
+//     let operators = OperatorTable::standard().with(Operator::infix(
+//         "→",
+//         0,
+//         Associativity::Right,
+//         |from, to| Expression::Call("convert".to_string(), vec![from, to]),
+//     ));
+//     let ast = Parser::with_operators("3 * ft → m", operators)?.parse()?;
+#[derive(Debug, Clone)]
+pub struct OperatorTable {
+    operators: Vec<Operator>,
+}
+
+impl Default for OperatorTable {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl OperatorTable {
+    // No operators at all: only numbers, variables, calls, lists and groupings.
+    pub fn empty() -> Self {
+        Self {
+            operators: Vec::new(),
+        }
+    }
+
+    // The calculator's own syntax.
+    pub fn standard() -> Self {
+        use Associativity::{Left, NonAssociative, Right};
+        use Expression as E;
+
+        Self::empty()
+            .with(Operator::infix("=", 0, NonAssociative, |a, b| {
+                E::Equation(Box::new(a), Box::new(b))
+            }))
+            .with(Operator::infix("..", 0, NonAssociative, |a, b| {
+                E::Range(Box::new(a), Box::new(b))
+            }))
+            .with(Operator::infix("+", 1, Left, |a, b| {
+                E::Add(Box::new(a), Box::new(b))
+            }))
+            .with(Operator::infix("-", 1, Left, |a, b| {
+                E::Subtract(Box::new(a), Box::new(b))
+            }))
+            .with(Operator::infix("*", 2, Left, |a, b| {
+                E::Multiply(Box::new(a), Box::new(b))
+            }))
+            .with(Operator::infix("/", 2, Left, |a, b| {
+                E::Divide(Box::new(a), Box::new(b))
+            }))
+            .with(Operator::prefix("-", 3, |a| E::Negate(Box::new(a))))
+            .with(Operator::infix("^", 4, Right, |a, b| {
+                E::Power(Box::new(a), Box::new(b))
+            }))
+    }
+
+    // Adds `operator`, replacing one with the same symbol in the same position
+    // (prefix, or infix and postfix, which compete for the same place).
+    pub fn with(mut self, operator: Operator) -> Self {
+        self.operators.retain(|existing| {
+            existing.symbol != operator.symbol || existing.is_prefix() != operator.is_prefix()
+        });
+        self.operators.push(operator);
+        self
+    }
+
+    pub fn without(mut self, symbol: &str) -> Self {
+        self.operators.retain(|existing| existing.symbol != symbol);
+        self
+    }
+
+    pub fn prefix(&self, symbol: &str) -> Option<&Operator> {
+        self.find(symbol, |fixity| matches!(fixity, Fixity::Prefix(_)))
+    }
+
+    pub fn infix(&self, symbol: &str) -> Option<&Operator> {
+        self.find(symbol, |fixity| matches!(fixity, Fixity::Infix(..)))
+    }
+
+    pub fn postfix(&self, symbol: &str) -> Option<&Operator> {
+        self.find(symbol, |fixity| matches!(fixity, Fixity::Postfix(_)))
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.operators
+            .iter()
+            .map(|operator| operator.symbol.as_str())
+    }
+
+    fn find(&self, symbol: &str, fixity: impl Fn(&Fixity) -> bool) -> Option<&Operator> {
+        self.operators
+            .iter()
+            .find(|operator| operator.symbol == symbol && fixity(&operator.fixity))
+    }
+}
+
+#[cfg(test)]
+mod operators_tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    fn standard_table() {
+        let table = OperatorTable::standard();
+        assert_eq!(table.symbols().count(), 8);
+        assert_eq!(table.prefix("-").map(|op| op.precedence), Some(3));
+        assert_eq!(table.infix("-").map(|op| op.precedence), Some(1));
+        assert!(table.prefix("+").is_none());
+        assert!(matches!(
+            table.infix("^").unwrap().fixity,
+            Fixity::Infix(Associativity::Right, _)
+        ));
+    }
+
+    #[rstest]
+    fn with_replaces_and_without_removes() {
+        let table = OperatorTable::standard()
+            .with(Operator::infix("^", 4, Associativity::Left, |a, _| a))
+            .with(Operator::postfix("-", 5, |a| a))
+            .without("*");
+        assert!(matches!(
+            table.infix("^").unwrap().fixity,
+            Fixity::Infix(Associativity::Left, _)
+        ));
+        assert!(table.infix("-").is_none());
+        assert!(table.postfix("-").is_some());
+        assert!(table.prefix("-").is_some());
+        assert!(table.infix("*").is_none());
+    }
+}
@@ -1,18 +1,136 @@
-use crate::parsemath::tokenizer::{self, Token, Tokenizer, TokenizingError};
+use crate::parsemath::limits::{self, EvalLimits, LimitError};
+use crate::parsemath::operators::{Associativity, Fixity, Operator, OperatorTable};
+use crate::parsemath::tokenizer::{Token, Tokenizer, TokenizingError};
 use thiserror::Error;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Expression {
     Number(f64),
     Add(Box<Expression>, Box<Expression>),
     Subtract(Box<Expression>, Box<Expression>),
     Multiply(Box<Expression>, Box<Expression>),
     Divide(Box<Expression>, Box<Expression>),
+    Power(Box<Expression>, Box<Expression>),
     Negate(Box<Expression>),
     Grouping(Box<Expression>),
+    Variable(String),
+    Equation(Box<Expression>, Box<Expression>),
+    Range(Box<Expression>, Box<Expression>),
+    List(Vec<Expression>),
+    Call(String, Vec<Expression>),
+    Index(Box<Expression>, Vec<Expression>),
+    // Stands in for input that could not be parsed; only produced by
+    // `Parser::parse_partial`.
+    Error,
 }
 
+impl Expression {
+    // The direct sub-expressions, left to right.
+    pub fn children(&self) -> Vec<&Expression> {
+        match self {
+            Expression::Number(_) | Expression::Variable(_) | Expression::Error => vec![],
+            Expression::Add(a, b)
+            | Expression::Subtract(a, b)
+            | Expression::Multiply(a, b)
+            | Expression::Divide(a, b)
+            | Expression::Power(a, b)
+            | Expression::Equation(a, b)
+            | Expression::Range(a, b) => vec![a, b],
+            Expression::Negate(e) | Expression::Grouping(e) => vec![e],
+            Expression::List(elements) | Expression::Call(_, elements) => elements.iter().collect(),
+            Expression::Index(target, indices) => {
+                std::iter::once(target.as_ref()).chain(indices).collect()
+            }
+        }
+    }
+
+    // Moves the expression out, leaving `Error` in its place, so that a tree can
+    // be taken apart through a mutable reference.
+    pub fn take(&mut self) -> Expression {
+        std::mem::replace(self, Expression::Error)
+    }
+
+    // Whether the variable `name` occurs anywhere in the tree.
+    pub fn mentions(&self, name: &str) -> bool {
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            match expr {
+                Expression::Variable(variable) if variable == name => return true,
+                expr => pending.extend(expr.children()),
+            }
+        }
+        false
+    }
+}
+
+// Owns an expression and frees it with an explicit stack when dropped. Dropping
+// an `Expression` recurses once per level, which is fine for any tree within the
+// default limits, but overflows the stack for, say, a sum of a million terms
+// built by code or parsed with a raised `max_nodes`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DeepExpression(Expression);
+
+impl DeepExpression {
+    pub fn new(expression: Expression) -> Self {
+        DeepExpression(expression)
+    }
+
+    pub fn into_inner(mut self) -> Expression {
+        self.0.take()
+    }
+}
+
+impl From<Expression> for DeepExpression {
+    fn from(expression: Expression) -> Self {
+        DeepExpression::new(expression)
+    }
+}
+
+impl std::ops::Deref for DeepExpression {
+    type Target = Expression;
+
+    fn deref(&self) -> &Expression {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for DeepExpression {
+    fn deref_mut(&mut self) -> &mut Expression {
+        &mut self.0
+    }
+}
+
+impl Drop for DeepExpression {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        take_children(&mut self.0, &mut pending);
+        while let Some(mut expr) = pending.pop() {
+            take_children(&mut expr, &mut pending);
+        }
+    }
+}
 
+// Moves the children of `expr` onto `pending`, leaving leaves in their place.
+fn take_children(expr: &mut Expression, pending: &mut Vec<Expression>) {
+    match expr {
+        Expression::Number(_) | Expression::Variable(_) | Expression::Error => {}
+        Expression::Add(a, b)
+        | Expression::Subtract(a, b)
+        | Expression::Multiply(a, b)
+        | Expression::Divide(a, b)
+        | Expression::Power(a, b)
+        | Expression::Equation(a, b)
+        | Expression::Range(a, b) => pending.extend([a.take(), b.take()]),
+        Expression::Negate(e) | Expression::Grouping(e) => pending.push(e.take()),
+        Expression::List(elements) | Expression::Call(_, elements) => pending.append(elements),
+        Expression::Index(target, indices) => {
+            pending.push(target.take());
+            pending.append(indices);
+        }
+    }
+}
 
 #[derive(Error, Debug, PartialEq)]
 pub enum ParserError {
@@ -20,91 +138,288 @@ pub enum ParserError {
     UnexpectedToken(#[from] TokenizingError),
     #[error("Syntax error: {0}")]
     SyntaxError(String),
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(#[from] LimitError),
+    // Input left over after a complete expression, at the character offset of
+    // its first token.
+    #[error("Syntax error: unexpected token after expression at position {0}")]
+    TrailingInput(usize),
+}
+
+// An error found by `Parser::parse_partial`, at a character offset into the
+// input counting from 0.
+#[derive(Error, Debug, PartialEq)]
+#[error("{error} at position {position}")]
+pub struct Diagnostic {
+    pub position: usize,
+    pub error: ParserError,
 }
 
-// A simple recursive descent parser for mathematical expressions.
-// Write grammar in EBNF:
-// expression = term;
-// term       = factor ( ( "-" | "+" ) factor )* ;
-// factor     = unary ( "/" | "*" ) unary )* ;
-// unary      = ( "-" )* unary | primary ;
-// primary    = NUMBER | "(" expression ")" ;
+// A Pratt (operator-precedence) parser for mathematical expressions. Operators,
+// their precedences and the nodes they build come from an `OperatorTable`; the
+// rest of the grammar is fixed. In EBNF, with `operand(p)` extending over
+// operators of precedence p and above:
+// expression = operand(0) ;
+// operand(p) = PREFIX operand(q) | primary, then repeatedly
+//              POSTFIX | "[" arguments "]" | INFIX operand(q') while their precedence >= p ;
+// primary    = NUMBER | IDENTIFIER ( "(" arguments? ")" )? | "[" arguments "]" | "(" expression ")" ;
+// arguments  = expression ( "," expression )* ;
+// where q is the prefix operator's precedence and q' the infix operator's,
+// plus one unless it is right-associative.
+
+// The precedence `expression` starts from, below every operator. Minimum
+// precedences are wider than `Operator::precedence` so that q' exists for an
+// operator of precedence 255.
+const LOWEST: u16 = 0;
 
 #[derive(Debug)]
 pub struct Parser {
     tokens: Vec<Token>,
+    // Character offset of each token, and of the end of the input.
+    positions: Vec<usize>,
+    end: usize,
     current_token_index: usize,
     bracket_count: usize,
+    depth: usize,
+    // Nodes built so far, checked as they are built so that over-long input is
+    // rejected before its tree grows too deep to drop.
+    nodes: usize,
+    limits: EvalLimits,
+    operators: OperatorTable,
+    // Set by `parse_partial`: errors are collected instead of returned.
+    recovering: bool,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Parser {
     pub fn new(expression: &str) -> Result<Self, ParserError> {
-        let tokenizer = Tokenizer::new(expression);
-        let tokens = tokenizer.collect::<Result<Vec<Token>, TokenizingError>>()?;
+        Self::with_operators_and_limits(expression, OperatorTable::standard(), EvalLimits::default())
+    }
 
-        Ok(Parser {
-            tokens,
-            current_token_index: 0,
-            bracket_count: 0,
-        })
+    // Rejects over-long input before tokenizing it and stops tokenizing once
+    // there are too many tokens; the depth and node limits apply in `parse`.
+    pub fn with_limits(expression: &str, limits: EvalLimits) -> Result<Self, ParserError> {
+        Self::with_operators_and_limits(expression, OperatorTable::standard(), limits)
     }
 
-    pub fn parse(&mut self) -> Result<Expression, ParserError> {
-        let expression = self.expression();
-        expression
+    pub fn with_operators(expression: &str, operators: OperatorTable) -> Result<Self, ParserError> {
+        Self::with_operators_and_limits(expression, operators, EvalLimits::default())
     }
 
-    fn expression(&mut self) -> Result<Expression, ParserError> {
-        let expression = self.term();
+    pub fn with_operators_and_limits(
+        expression: &str,
+        operators: OperatorTable,
+        limits: EvalLimits,
+    ) -> Result<Self, ParserError> {
+        let mut parser = Self::recovering_with(expression, operators, limits);
+        if !parser.diagnostics.is_empty() {
+            return Err(parser.diagnostics.swap_remove(0).error);
+        }
+        Ok(parser)
+    }
 
-        expression
+    // A parser for `parse_partial`. Invalid characters and numbers are skipped
+    // and reported along with the syntax errors instead of failing here.
+    pub fn recovering(expression: &str) -> Self {
+        Self::recovering_with(expression, OperatorTable::standard(), EvalLimits::default())
     }
 
-    fn term(&mut self) -> Result<Expression, ParserError> {
-        let mut expression = self.factor()?;
+    pub fn recovering_with(expression: &str, operators: OperatorTable, limits: EvalLimits) -> Self {
+        let mut parser = Parser {
+            tokens: Vec::new(),
+            positions: Vec::new(),
+            end: expression.chars().count(),
+            current_token_index: 0,
+            bracket_count: 0,
+            depth: 0,
+            nodes: 0,
+            limits,
+            operators,
+            recovering: false,
+            diagnostics: Vec::new(),
+        };
+
+        if expression.len() > parser.limits.max_input_length {
+            let error = LimitError::InputTooLong {
+                length: expression.len(),
+                limit: parser.limits.max_input_length,
+            };
+            parser.diagnostics.push(Diagnostic {
+                position: 0,
+                error: error.into(),
+            });
+            return parser;
+        }
 
-        loop {
-            match self.peek() {
-                Some(Token::Plus) => {
-                    self.consume();
-                    let right = self.factor()?;
-                    expression = Expression::Add(Box::new(expression), Box::new(right));
-                }
-                Some(Token::Minus) => {
-                    self.consume();
-                    let right = self.factor()?;
-                    expression = Expression::Subtract(Box::new(expression), Box::new(right));
-                }
-                Some(Token::RightParen) if self.bracket_count == 0 => {
-                    return Err(ParserError::SyntaxError(r#"Too many ')'."#.to_string()));
+        let mut tokenizer = Tokenizer::new(expression).with_operators(parser.operators.symbols());
+        while let Some(token) = tokenizer.next() {
+            let position = tokenizer.position();
+            match token {
+                Ok(_) if parser.tokens.len() == parser.limits.max_tokens => {
+                    let error = LimitError::TooManyTokens(parser.limits.max_tokens);
+                    parser.diagnostics.push(Diagnostic {
+                        position,
+                        error: error.into(),
+                    });
+                    break;
                 }
-                Some(Token::LeftParen) if self.bracket_count == 0 => {
-                    return Err(ParserError::SyntaxError(r#"Unexpected '('."#.to_string()));
+                Ok(token) => {
+                    parser.tokens.push(token);
+                    parser.positions.push(position);
                 }
-                _ => break,
+                Err(error) => parser.diagnostics.push(Diagnostic {
+                    position,
+                    error: error.into(),
+                }),
             }
         }
 
+        parser
+    }
+
+    // Limits how deeply the input may nest. Parentheses, brackets, call
+    // arguments, prefix operators and the right operand of a right-associative
+    // operator count as one level; a chain such as `1 + 2 + 3` does not. Deeper
+    // input fails with `LimitError::TooDeeplyNested` instead of overflowing the
+    // stack.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.limits.max_depth = max_depth;
+        self
+    }
+
+    // Parses the whole input as one expression. Fails with the first error the
+    // tokenizer reported to a `recovering` parser, if any.
+    pub fn parse(&mut self) -> Result<Expression, ParserError> {
+        if !self.diagnostics.is_empty() {
+            self.diagnostics.sort_by_key(|diagnostic| diagnostic.position);
+            return Err(self.diagnostics.remove(0).error);
+        }
+        let expression = self.expression(LOWEST)?;
+        if !self.is_at_end() {
+            return Err(ParserError::TrailingInput(self.position()));
+        }
+        limits::check_tree(&expression, &self.limits)?;
+        Ok(expression)
+    }
+
+    // Parses as much as possible and reports every error, in order of position,
+    // instead of stopping at the first. A missing operand becomes
+    // `Expression::Error`, a stray `)` or `(` is skipped, an unclosed group skips
+    // ahead to its closing bracket and anything after a complete expression is
+    // reported and checked, then dropped. Exceeding a limit still ends parsing,
+    // with an `Error` root.
+    //
+    //     let (ast, diagnostics) = Parser::recovering("(1 + ) * 2)").parse_partial();
+    //     // ast: (1 + ?) * 2, diagnostics: "Expected number or '('." at 5
+    //     // and "Too many ')'." at 10
+    pub fn parse_partial(&mut self) -> (Expression, Vec<Diagnostic>) {
+        self.recovering = true;
+        let fatal = self
+            .diagnostics
+            .iter()
+            .any(|diagnostic| matches!(diagnostic.error, ParserError::LimitExceeded(_)));
+        let expression = if fatal {
+            Expression::Error
+        } else {
+            match self.recover() {
+                Ok(expression) => expression,
+                Err(error) => {
+                    let position = self.position();
+                    self.diagnostics.push(Diagnostic { position, error });
+                    Expression::Error
+                }
+            }
+        };
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        diagnostics.sort_by_key(|diagnostic| diagnostic.position);
+        (expression, diagnostics)
+    }
+
+    fn recover(&mut self) -> Result<Expression, ParserError> {
+        let expression = self.expression(LOWEST)?;
+        if !self.is_at_end() {
+            self.report(ParserError::TrailingInput(self.position()))?;
+        }
+        while let Some(token) = self.peek() {
+            let start = self.current_token_index;
+            if !matches!(token, Token::RightBracket | Token::Comma) {
+                self.expression(LOWEST)?;
+            }
+            if self.current_token_index == start {
+                self.consume();
+            }
+        }
+        limits::check_tree(&expression, &self.limits)?;
         Ok(expression)
     }
 
-    fn factor(&mut self) -> Result<Expression, ParserError> {
-        let mut expression = self.unary()?;
+    fn expression(&mut self, min_precedence: u16) -> Result<Expression, ParserError> {
+        let mut expression = self.prefix()?;
+        // Set after a non-associative operator, which must not be followed by
+        // another of the same precedence.
+        let mut closed_precedence = None;
 
         loop {
-            match self.peek() {
-                Some(Token::Star) => {
+            let token = self.peek();
+            let symbol = token.as_ref().and_then(Token::symbol);
+            let postfix = symbol.and_then(|symbol| self.operators.postfix(symbol));
+            let infix = symbol.and_then(|symbol| self.operators.infix(symbol));
+
+            match (token.as_ref(), postfix, infix) {
+                (Some(Token::LeftBracket), _, _) => {
+                    self.consume();
+                    self.descend()?;
+                    let indices = self.arguments()?;
+                    self.ascend();
+                    self.consume_right_bracket()?;
+                    self.count_node()?;
+                    expression = Expression::Index(Box::new(expression), indices);
+                }
+                (_, Some(operator), _) if u16::from(operator.precedence) >= min_precedence => {
+                    let Fixity::Postfix(build) = operator.fixity else {
+                        unreachable!("looked up as postfix")
+                    };
                     self.consume();
-                    let right = self.unary()?;
-                    expression = Expression::Multiply(Box::new(expression), Box::new(right));
+                    self.count_node()?;
+                    expression = build(expression);
                 }
-                Some(Token::Slash) => {
+                (_, _, Some(operator))
+                    if u16::from(operator.precedence) >= min_precedence
+                        && closed_precedence != Some(operator.precedence) =>
+                {
+                    let precedence = operator.precedence;
+                    let operand_precedence = u16::from(precedence);
+                    let Fixity::Infix(associativity, build) = operator.fixity else {
+                        unreachable!("looked up as infix")
+                    };
                     self.consume();
-                    let right = self.unary()?;
-                    expression = Expression::Divide(Box::new(expression), Box::new(right));
+                    // Only a right operand that can chain nests deeper; the
+                    // loop builds left-associative chains without recursing.
+                    let right = match associativity {
+                        Associativity::Right => {
+                            self.descend()?;
+                            let right = self.expression(operand_precedence)?;
+                            self.ascend();
+                            right
+                        }
+                        _ => self.expression(operand_precedence + 1)?,
+                    };
+                    if associativity == Associativity::NonAssociative {
+                        closed_precedence = Some(precedence);
+                    }
+                    self.count_node()?;
+                    expression = build(expression, right);
                 }
-                Some(Token::RightParen) if self.bracket_count == 0 => {
-                    return Err(ParserError::SyntaxError(r#"Too many ')'."#.to_string()));
+                (Some(Token::RightParen), _, _) if self.bracket_count == 0 => {
+                    self.report(ParserError::SyntaxError(r#"Too many ')'."#.to_string()))?;
+                    self.consume();
+                }
+                // Calls are parsed with their name, so a `(` after an operand
+                // is never valid, whether or not it is inside parentheses.
+                (Some(Token::LeftParen), _, _) => {
+                    self.report(ParserError::SyntaxError(r#"Unexpected '('."#.to_string()))?;
+                    // Still checked for errors of its own.
+                    self.primary()?;
                 }
                 _ => break,
             }
@@ -113,36 +428,113 @@ impl Parser {
         Ok(expression)
     }
 
-    fn unary(&mut self) -> Result<Expression, ParserError> {
-        if let Some(Token::Minus) = self.peek() {
-            self.consume();
+    fn prefix(&mut self) -> Result<Expression, ParserError> {
+        let token = self.peek();
+        let operator = token
+            .as_ref()
+            .and_then(Token::symbol)
+            .and_then(|symbol| self.operators.prefix(symbol));
 
-            let right = self.unary()?;
-            return Ok(Expression::Negate(Box::new(right)));
+        if let Some(&Operator {
+            precedence,
+            fixity: Fixity::Prefix(build),
+            ..
+        }) = operator
+        {
+            self.consume();
+            self.descend()?;
+            let operand = self.expression(precedence.into())?;
+            self.ascend();
+            self.count_node()?;
+            return Ok(build(operand));
         }
 
         self.primary()
     }
 
     fn primary(&mut self) -> Result<Expression, ParserError> {
-        let expression = match self.next() {
-            Some(Token::Number(n)) => Expression::Number(n),
+        self.count_node()?;
+        let expression = match self.peek() {
+            Some(Token::Number(n)) => {
+                self.consume();
+                Expression::Number(n)
+            }
             Some(Token::LeftParen) => {
+                self.consume();
                 self.bracket_count += 1;
-                let expression = self.expression()?;
+                self.descend()?;
+                let expression = self.expression(LOWEST)?;
+                self.ascend();
                 self.consume_right_paren()?;
                 Expression::Grouping(Box::new(expression))
             }
+            Some(Token::LeftBracket) => {
+                self.consume();
+                self.descend()?;
+                let elements = self.arguments()?;
+                self.ascend();
+                self.consume_right_bracket()?;
+                Expression::List(elements)
+            }
+            Some(Token::Identifier(name)) => {
+                self.consume();
+                if self.peek() != Some(Token::LeftParen) {
+                    return Ok(Expression::Variable(name));
+                }
+                self.consume();
+                self.bracket_count += 1;
+                self.descend()?;
+                let arguments = if let Some(Token::RightParen) = self.peek() {
+                    Vec::new()
+                } else {
+                    self.arguments()?
+                };
+                self.ascend();
+                self.consume_right_paren()?;
+                Expression::Call(name, arguments)
+            }
             _ => {
-                return Err(ParserError::SyntaxError(
+                self.report(ParserError::SyntaxError(
                     "Expected number or '('.".to_string(),
-                ));
+                ))?;
+                Expression::Error
             }
         };
 
         Ok(expression)
     }
 
+    fn arguments(&mut self) -> Result<Vec<Expression>, ParserError> {
+        let mut arguments = vec![self.expression(LOWEST)?];
+
+        while let Some(Token::Comma) = self.peek() {
+            self.consume();
+            arguments.push(self.expression(LOWEST)?);
+        }
+
+        Ok(arguments)
+    }
+
+    fn descend(&mut self) -> Result<(), ParserError> {
+        if self.depth >= self.limits.max_depth {
+            return Err(LimitError::TooDeeplyNested(self.limits.max_depth).into());
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn ascend(&mut self) {
+        self.depth -= 1;
+    }
+
+    fn count_node(&mut self) -> Result<(), ParserError> {
+        self.nodes += 1;
+        if self.nodes > self.limits.max_nodes {
+            return Err(LimitError::TooManyNodes(self.limits.max_nodes).into());
+        }
+        Ok(())
+    }
+
     fn consume(&mut self) {
         if !self.is_at_end() {
             self.current_token_index += 1;
@@ -150,14 +542,66 @@ impl Parser {
     }
 
     fn consume_right_paren(&mut self) -> Result<(), ParserError> {
-        if let Some(Token::RightParen) = self.next() {
-            self.bracket_count -= 1;
+        self.bracket_count -= 1;
+        if let Some(Token::RightParen) = self.peek() {
+            self.consume();
+            return Ok(());
+        }
+        self.report(ParserError::SyntaxError(
+            "Expect ')' after expression.".to_string(),
+        ))?;
+        self.skip_to(Token::RightParen);
+        Ok(())
+    }
+
+    fn consume_right_bracket(&mut self) -> Result<(), ParserError> {
+        if let Some(Token::RightBracket) = self.peek() {
+            self.consume();
             return Ok(());
-        } else {
-            Err(ParserError::SyntaxError(
-                "Expect ')' after expression.".to_string(),
-            ))
         }
+        self.report(ParserError::SyntaxError(
+            "Expect ']' after elements.".to_string(),
+        ))?;
+        self.skip_to(Token::RightBracket);
+        Ok(())
+    }
+
+    // Skips past the `closer` of the group being parsed, or up to a closing
+    // bracket of an enclosing group when this one is never closed.
+    fn skip_to(&mut self, closer: Token) {
+        let mut nesting = 0;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::LeftParen | Token::LeftBracket => nesting += 1,
+                Token::RightParen | Token::RightBracket if nesting == 0 => {
+                    if token == closer {
+                        self.consume();
+                    }
+                    return;
+                }
+                Token::RightParen | Token::RightBracket => nesting -= 1,
+                _ => {}
+            }
+            self.consume();
+        }
+    }
+
+    // In recovering mode records `error` at the current token and lets the
+    // caller carry on; otherwise fails with it.
+    fn report(&mut self, error: ParserError) -> Result<(), ParserError> {
+        if !self.recovering {
+            return Err(error);
+        }
+        let position = self.position();
+        self.diagnostics.push(Diagnostic { position, error });
+        Ok(())
+    }
+
+    fn position(&self) -> usize {
+        self.positions
+            .get(self.current_token_index)
+            .copied()
+            .unwrap_or(self.end)
     }
 
     fn is_at_end(&self) -> bool {
@@ -170,17 +614,13 @@ impl Parser {
         }
         Some(self.tokens[self.current_token_index].clone())
     }
-
-    fn next(&mut self) -> Option<Token> {
-        let token = self.peek();
-        self.current_token_index += 1;
-        return token;
-    }
 }
 
 #[cfg(test)]
 mod parser_tests {
     use super::*;
+    use crate::parsemath::limits::{DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES};
+    use crate::parsemath::visitors::{Evaluator, EvaluatorError, ExpressionVisitor, PrettyPrinterVisitor};
     use rstest::*;
 
     #[test]
@@ -326,6 +766,96 @@ mod parser_tests {
         assert_eq!(ast, expected_ast);
     }
 
+    #[rstest]
+    #[case::row("[1, 2]", Expression::List(vec![Expression::Number(1.0), Expression::Number(2.0)]))]
+    #[case::matrix(
+        "[[1, 2], [3, 4]]",
+        Expression::List(vec![
+            Expression::List(vec![Expression::Number(1.0), Expression::Number(2.0)]),
+            Expression::List(vec![Expression::Number(3.0), Expression::Number(4.0)]),
+        ])
+    )]
+    #[case::element_access(
+        "[1, 2][1]",
+        Expression::Index(
+            Box::new(Expression::List(vec![Expression::Number(1.0), Expression::Number(2.0)])),
+            vec![Expression::Number(1.0)]
+        )
+    )]
+    fn parse_matrix_literal(#[case] expression: &str, #[case] expected_ast: Expression) {
+        let mut parser = Parser::new(expression).unwrap();
+
+        let ast = parser.parse().unwrap();
+        assert_eq!(ast, expected_ast);
+    }
+
+    #[rstest]
+    #[case::no_arguments("f()", Expression::Call("f".to_string(), vec![]))]
+    #[case::two_arguments(
+        "solve(1, 2 + 3)",
+        Expression::Call(
+            "solve".to_string(),
+            vec![
+                Expression::Number(1.0),
+                Expression::Add(Box::new(Expression::Number(2.0)), Box::new(Expression::Number(3.0)))
+            ]
+        )
+    )]
+    fn parse_function_call(#[case] expression: &str, #[case] expected_ast: Expression) {
+        let mut parser = Parser::new(expression).unwrap();
+
+        let ast = parser.parse().unwrap();
+        assert_eq!(ast, expected_ast);
+    }
+
+    #[rstest]
+    #[case::unclosed_bracket("[1, 2", "Expect ']' after elements.")]
+    #[case::unclosed_call("det([1]", "Expect ')' after expression.")]
+    fn parse_invalid_matrix_syntax(#[case] expression: &str, #[case] expected_message: &str) {
+        let mut parser = Parser::new(expression).unwrap();
+        let parser_error = parser.parse().unwrap_err();
+
+        assert_eq!(parser_error, ParserError::SyntaxError(expected_message.to_string()));
+    }
+
+    #[rstest]
+    #[case::variable("x", Expression::Variable("x".to_string()))]
+    #[case::power_is_right_associative(
+        "2 ^ 3 ^ 2",
+        Expression::Power(
+            Box::new(Expression::Number(2.0)),
+            Box::new(Expression::Power(Box::new(Expression::Number(3.0)), Box::new(Expression::Number(2.0))))
+        )
+    )]
+    #[case::power_binds_tighter_than_negation(
+        "-x ^ 2",
+        Expression::Negate(Box::new(Expression::Power(
+            Box::new(Expression::Variable("x".to_string())),
+            Box::new(Expression::Number(2.0))
+        )))
+    )]
+    #[case::negative_exponent(
+        "2 ^ -1",
+        Expression::Power(
+            Box::new(Expression::Number(2.0)),
+            Box::new(Expression::Negate(Box::new(Expression::Number(1.0))))
+        )
+    )]
+    #[case::equation(
+        "x = 2",
+        Expression::Equation(Box::new(Expression::Variable("x".to_string())), Box::new(Expression::Number(2.0)))
+    )]
+    #[case::range(
+        "0..5",
+        Expression::Range(Box::new(Expression::Number(0.0)), Box::new(Expression::Number(5.0)))
+    )]
+    fn parse_variables_powers_and_relations(#[case] expression: &str, #[case] expected_ast: Expression) {
+        let mut parser = Parser::new(expression).unwrap();
+
+        let ast = parser.parse().unwrap();
+        assert_eq!(ast, expected_ast);
+    }
+
     #[rstest]
     #[case::lb_1("(1")]
     #[case::lb_lb_lb_1_rb_2_rb("(((1)2)")]
@@ -346,6 +876,60 @@ mod parser_tests {
         assert_eq!(parser_error, ParserError::SyntaxError(r#"Too many ')'."#.to_string()));
     }
 
+    #[rstest]
+    #[case::numbers("1 2", 2)]
+    #[case::number_after_group("(1) 3", 4)]
+    #[case::after_call("f(x) y", 5)]
+    #[case::after_list("[1] 2", 4)]
+    #[case::after_index("v[1] 2", 5)]
+    #[case::identifiers("x y", 2)]
+    #[case::after_operator_chain("1 + 2 * 3 4", 10)]
+    #[case::comma("1, 2", 1)]
+    #[case::right_bracket("1 + 2]", 5)]
+    #[case::list_closed_twice("[1]]", 3)]
+    #[case::chained_equations("x = 1 = 2", 6)]
+    #[case::equation_then_range("x = 1..2", 5)]
+    #[case::range_then_equation("0..1 = x", 5)]
+    fn parse_trailing_input(#[case] expression: &str, #[case] position: usize) {
+        let mut parser = Parser::new(expression).unwrap();
+        let parser_error = parser.parse().unwrap_err();
+
+        assert_eq!(parser_error, ParserError::TrailingInput(position));
+    }
+
+    #[rstest]
+    fn trailing_input_reports_its_position() {
+        let error = Parser::new("(1) 3").unwrap().parse().unwrap_err();
+        assert_eq!(error.to_string(), "Syntax error: unexpected token after expression at position 4");
+
+        // Positions count characters, not bytes.
+        let error = Parser::with_operators("1 ± 2 3", custom_operators()).unwrap().parse().unwrap_err();
+        assert_eq!(error, ParserError::TrailingInput(6));
+        let error = Parser::with_operators("2 √4", custom_operators()).unwrap().parse().unwrap_err();
+        assert_eq!(error, ParserError::TrailingInput(2));
+    }
+
+    #[rstest]
+    #[case::after_number("2 (3)")]
+    #[case::inside_group("(1 (2))")]
+    #[case::after_group("(1) (2)")]
+    #[case::inside_call("f(1 (y))")]
+    #[case::inside_list("[1 (2)]")]
+    fn parse_unexpected_left_paren(#[case] expression: &str) {
+        let mut parser = Parser::new(expression).unwrap();
+        let parser_error = parser.parse().unwrap_err();
+
+        assert_eq!(parser_error, ParserError::SyntaxError(r#"Unexpected '('."#.to_string()));
+    }
+
+    #[rstest]
+    #[case::trailing_whitespace("1 + 2   ")]
+    #[case::surrounding_whitespace("  (1)  ")]
+    #[case::nested_closers("f([1, (2)])")]
+    fn parse_consumes_all_input(#[case] expression: &str) {
+        assert!(Parser::new(expression).unwrap().parse().is_ok());
+    }
+
     #[rstest]
     #[case::expr_plus_plus("++")]
     #[case::expr_1_minus("1-")]
@@ -363,4 +947,191 @@ mod parser_tests {
         assert_eq!(parser_error, ParserError::UnexpectedToken(TokenizingError::InvalidCharacter('#')));
         assert_eq!(format!("{}", parser_error), "Syntax error: Unexpected token \'#\'");
     }
+
+    #[rstest]
+    #[case::parentheses(format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000)))]
+    #[case::negations(format!("{}1", "-".repeat(100_000)))]
+    #[case::lists(format!("{}1{}", "[".repeat(100_000), "]".repeat(100_000)))]
+    #[case::calls(format!("{}1{}", "f(".repeat(100_000), ")".repeat(100_000)))]
+    #[case::exponents(format!("2{}", "^2".repeat(100_000)))]
+    #[case::indices(format!("v{}", "[[1".repeat(100_000)))]
+    fn parse_deeply_nested_input(#[case] expression: String) {
+        let mut parser = Parser::new(&expression).unwrap();
+        let parser_error = parser.parse().unwrap_err();
+        assert_eq!(parser_error, LimitError::TooDeeplyNested(DEFAULT_MAX_DEPTH).into());
+        assert_eq!(format!("{}", parser_error), "Limit exceeded: expression nests deeper than 128 levels");
+    }
+
+    #[rstest]
+    #[case::parentheses("((1))", 2)]
+    #[case::negations("--1", 2)]
+    #[case::exponents("2 ^ 3 ^ 4", 2)]
+    #[case::mixed("-(1 + 2) * 3", 2)]
+    #[case::call("f([1], 2)", 2)]
+    fn parse_at_max_depth(#[case] expression: &str, #[case] depth: usize) {
+        assert!(Parser::new(expression).unwrap().with_max_depth(depth).parse().is_ok());
+        assert_eq!(
+            Parser::new(expression).unwrap().with_max_depth(depth - 1).parse(),
+            Err(LimitError::TooDeeplyNested(depth - 1).into())
+        );
+    }
+
+    #[rstest]
+    #[case::sums(format!("1{}", " + 1".repeat(100_000)))]
+    #[case::products(format!("1{}", " * 1 / 1".repeat(100_000)))]
+    #[case::indices(format!("v{}", "[1]".repeat(100_000)))]
+    #[case::mixed(format!("-(1{})", " - 2 * 3 ^ 4".repeat(100_000)))]
+    fn parse_long_chains(#[case] expression: String) {
+        assert_eq!(
+            Parser::new(&expression).unwrap().parse(),
+            Err(LimitError::TooManyNodes(DEFAULT_MAX_NODES).into())
+        );
+        let limits = EvalLimits { max_nodes: usize::MAX, ..EvalLimits::default() };
+        let ast = Parser::with_limits(&expression, limits).unwrap().parse().map(DeepExpression::new);
+        assert!(ast.is_ok());
+    }
+
+    #[rstest]
+    fn depth_resets_between_siblings() {
+        let expression = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+        let siblings = [expression.as_str(); 5].join(", ");
+        let mut parser = Parser::new(&format!("f({})", siblings)).unwrap();
+        assert!(parser.parse().is_ok());
+    }
+
+    #[rstest]
+    #[case::input_length(EvalLimits { max_input_length: 8, ..EvalLimits::default() }, LimitError::InputTooLong { length: 9, limit: 8 })]
+    #[case::tokens(EvalLimits { max_tokens: 4, ..EvalLimits::default() }, LimitError::TooManyTokens(4))]
+    #[case::nodes(EvalLimits { max_nodes: 3, ..EvalLimits::default() }, LimitError::TooManyNodes(3))]
+    #[case::depth(EvalLimits { max_depth: 0, ..EvalLimits::default() }, LimitError::TooDeeplyNested(0))]
+    fn parse_with_limits(#[case] limits: EvalLimits, #[case] expected: LimitError) {
+        let expression = "f(1 + 2)";
+        assert!(Parser::with_limits(expression, EvalLimits::default()).unwrap().parse().is_ok());
+        let result = Parser::with_limits(" f(1 + 2)", limits).and_then(|mut parser| parser.parse());
+        assert_eq!(result, Err(ParserError::LimitExceeded(expected)));
+    }
+
+    #[rstest]
+    fn token_limit_stops_tokenizing() {
+        // The invalid character is never reached.
+        let expression = format!("{}#", "1 + ".repeat(100));
+        let limits = EvalLimits { max_tokens: 10, ..EvalLimits::default() };
+        assert_eq!(Parser::with_limits(&expression, limits).unwrap_err(), LimitError::TooManyTokens(10).into());
+    }
+
+    fn custom_operators() -> OperatorTable {
+        OperatorTable::standard()
+            .with(Operator::infix("±", 1, Associativity::Left, |a, b| {
+                Expression::List(vec![
+                    Expression::Subtract(Box::new(a.clone()), Box::new(b.clone())),
+                    Expression::Add(Box::new(a), Box::new(b)),
+                ])
+            }))
+            .with(Operator::infix("→", 0, Associativity::Right, |a, b| Expression::Call("convert".to_string(), vec![a, b])))
+            .with(Operator::prefix("√", 4, |a| Expression::Call("sqrt".to_string(), vec![a])))
+            .with(Operator::postfix("!", 5, |a| Expression::Call("factorial".to_string(), vec![a])))
+    }
+
+    #[rstest]
+    #[case::plus_minus("1 ± 2 * x", "[1 - 2 * x, 1 + 2 * x]")]
+    #[case::arrow_is_right_associative("a → b → c", "convert(a, convert(b, c))")]
+    #[case::arrow_binds_loosest("1 + 2 → km", "convert(1 + 2, km)")]
+    #[case::prefix_binds_tighter_than_product("√4 * 2", "sqrt(4) * 2")]
+    #[case::prefix_takes_powers("√2 ^ 2", "sqrt(2 ^ 2)")]
+    #[case::postfix("-3! ^ 2", "-factorial(3) ^ 2")]
+    #[case::postfix_chain("x!! [1]", "factorial(factorial(x))[1]")]
+    #[case::standard_operators_unchanged("-2 ^ -x - 1 .. 3", "-2 ^ -x - 1..3")]
+    fn parse_with_custom_operators(#[case] expression: &str, #[case] expected: &str) {
+        let ast = Parser::with_operators(expression, custom_operators()).unwrap().parse().unwrap();
+        let printed = PrettyPrinterVisitor::new().visit_expression(&ast).unwrap();
+        assert_eq!(printed, expected);
+    }
+
+    #[rstest]
+    fn parse_with_replaced_operators() {
+        let operators = OperatorTable::standard()
+            .with(Operator::infix("^", 4, Associativity::Left, |a, b| Expression::Power(Box::new(a), Box::new(b))))
+            .without("..");
+        let ast = Parser::with_operators("2 ^ 3 ^ 2", operators.clone()).unwrap().parse().unwrap();
+        assert_eq!(PrettyPrinterVisitor::new().visit_expression(&ast).unwrap(), "(2 ^ 3) ^ 2");
+
+        let error = Parser::with_operators("1 + ..", operators).unwrap().parse().unwrap_err();
+        assert_eq!(error, ParserError::SyntaxError("Expected number or '('.".to_string()));
+    }
+
+    #[rstest]
+    #[case::left("1 ## 2 ## 3", "l(l(1, 2), 3)")]
+    #[case::right("1 #> 2 #> 3", "r(1, r(2, 3))")]
+    #[case::prefix("~1 ## ~2", "-l(1, -2)")]
+    fn parse_operators_of_the_highest_precedence(#[case] expression: &str, #[case] expected: &str) {
+        let operators = OperatorTable::empty()
+            .with(Operator::infix("##", u8::MAX, Associativity::Left, |a, b| Expression::Call("l".to_string(), vec![a, b])))
+            .with(Operator::infix("#>", u8::MAX, Associativity::Right, |a, b| Expression::Call("r".to_string(), vec![a, b])))
+            .with(Operator::prefix("~", u8::MAX, |a| Expression::Negate(Box::new(a))));
+        let ast = Parser::with_operators(expression, operators).unwrap().parse().unwrap();
+        let printed = PrettyPrinterVisitor::new().visit_expression(&ast).unwrap();
+        assert_eq!(printed, expected);
+    }
+
+    #[rstest]
+    fn unregistered_symbols_are_invalid() {
+        let error = Parser::new("1 ± 2").unwrap_err();
+        assert_eq!(error, ParserError::UnexpectedToken(TokenizingError::InvalidCharacter('±')));
+    }
+
+    #[rstest]
+    #[case::valid("1 + 2 * x", "1 + 2 * x", vec![])]
+    #[case::missing_operand("(1 + ) * 2", "(1 + ?) * 2", vec![(5, "Expected number or '('.")])]
+    #[case::missing_operands("* 2 - ", "? * 2 - ?", vec![(0, "Expected number or '('."), (6, "Expected number or '('.")])]
+    #[case::stray_right_paren("1 + 2) * 3", "1 + 2 * 3", vec![(5, "Too many ')'.")])]
+    #[case::stray_left_paren("2 (3 +) + 1", "2 + 1", vec![(2, "Unexpected '('."), (6, "Expected number or '('.")])]
+    #[case::unclosed_paren("f(1, 2", "f(1, 2)", vec![(6, "Expect ')' after expression.")])]
+    #[case::skips_to_closer("(1 2 3) + 4", "(1) + 4", vec![(3, "Expect ')' after expression.")])]
+    #[case::unclosed_bracket("[1, (2] + 3", "[1, (2)] + 3", vec![(6, "Expect ')' after expression.")])]
+    #[case::trailing_tokens("1 + 2 3 *", "1 + 2", vec![(6, "unexpected token after expression at position 6"), (9, "Expected number or '('.")])]
+    #[case::invalid_characters("1 $ 2 + # ", "1", vec![(2, "Unexpected token '$'"), (4, "unexpected token after expression at position 4"), (8, "Unexpected token '#'"), (10, "Expected number or '('.")])]
+    #[case::empty("", "?", vec![(0, "Expected number or '('.")])]
+    fn parse_partial_reports_every_error(#[case] expression: &str, #[case] expected: &str, #[case] diagnostics: Vec<(usize, &str)>) {
+        let (ast, found) = Parser::recovering(expression).parse_partial();
+        assert_eq!(PrettyPrinterVisitor::new().visit_expression(&ast).unwrap(), expected);
+        let found: Vec<(usize, String)> = found.into_iter().map(|d| (d.position, d.error.to_string())).collect();
+        let diagnostics: Vec<(usize, String)> = diagnostics.into_iter().map(|(position, message)| (position, format!("Syntax error: {}", message))).collect();
+        assert_eq!(found, diagnostics);
+    }
+
+    #[rstest]
+    #[case("(1) 3", 4)]
+    #[case("1 + 2 3 *", 6)]
+    fn parse_partial_reports_trailing_input_like_parse(#[case] expression: &str, #[case] position: usize) {
+        let error = Parser::new(expression).unwrap().parse().unwrap_err();
+        assert_eq!(error, ParserError::TrailingInput(position));
+        let (_, diagnostics) = Parser::recovering(expression).parse_partial();
+        assert!(diagnostics.contains(&Diagnostic { position, error }));
+    }
+
+    #[rstest]
+    fn parse_partial_stops_at_limits() {
+        let limits = EvalLimits { max_depth: 3, ..EvalLimits::default() };
+        let (ast, diagnostics) = Parser::recovering_with("((((1)))) + )", OperatorTable::standard(), limits).parse_partial();
+        assert_eq!(ast, Expression::Error);
+        assert_eq!(diagnostics, vec![Diagnostic { position: 4, error: LimitError::TooDeeplyNested(3).into() }]);
+
+        let limits = EvalLimits { max_input_length: 3, ..EvalLimits::default() };
+        let (ast, diagnostics) = Parser::recovering_with("1 + 2", OperatorTable::standard(), limits).parse_partial();
+        assert_eq!(ast, Expression::Error);
+        assert_eq!(diagnostics[0].to_string(), "Limit exceeded: input is 5 bytes long, the limit is 3 at position 0");
+    }
+
+    #[rstest]
+    fn parse_reports_tokenizer_errors_of_a_recovering_parser() {
+        let result = Parser::recovering("1 # 2 $").parse();
+        assert_eq!(result, Err(TokenizingError::InvalidCharacter('#').into()));
+        assert_eq!(Parser::recovering("1 + 2").parse(), Ok(Parser::new("1 + 2").unwrap().parse().unwrap()));
+    }
+
+    #[rstest]
+    fn error_nodes_are_rejected_by_the_evaluator() {
+        let (ast, _) = Parser::recovering("1 + * 2").parse_partial();
+        assert!(matches!(Evaluator::new().visit_expression(&ast), Err(EvaluatorError::UnexpectedExpression(_))));
+    }
 }
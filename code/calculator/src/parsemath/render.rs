@@ -0,0 +1,53 @@
+use super::constructors::rendered_precedence;
+use super::parser::Expression;
+use super::visitors::ExpressionVisitor;
+
+// Helpers shared by the printers and renderers. Each one says how it brackets an
+// operand, writes a binary operator and separates a list; operands are bracketed
+// wherever `precedence` says they bind too loosely.
+pub(crate) trait Renderer<T>: ExpressionVisitor<T, ()> {
+    // Whether divisions are drawn as fractions; see `rendered_precedence`.
+    const STACKED_FRACTIONS: bool = false;
+
+    fn bracket(output: T) -> T;
+
+    fn infix(left: T, operator: &str, right: T) -> T;
+
+    fn separated(items: Vec<T>) -> T;
+
+    fn precedence(&self, expr: &Expression) -> u8 {
+        rendered_precedence(expr, Self::STACKED_FRACTIONS)
+    }
+
+    // Renders `expr`, bracketed if it binds more loosely than `min_precedence`.
+    fn operand(&mut self, expr: &Expression, min_precedence: u8) -> Result<T, ()> {
+        let output = self.visit_expression(expr)?;
+        if self.precedence(expr) < min_precedence {
+            Ok(Self::bracket(output))
+        } else {
+            Ok(output)
+        }
+    }
+
+    // Left-associative binary operator: only the right operand is bracketed at
+    // equal precedence, e.g. `1 - (2 - 3)` but `1 - 2 - 3`.
+    fn binary(
+        &mut self,
+        left: &Expression,
+        operator: &str,
+        right: &Expression,
+        precedence: u8,
+    ) -> Result<T, ()> {
+        let left = self.operand(left, precedence)?;
+        let right = self.operand(right, precedence + 1)?;
+        Ok(Self::infix(left, operator, right))
+    }
+
+    fn visit_all(&mut self, exprs: &[Expression]) -> Result<T, ()> {
+        let items = exprs
+            .iter()
+            .map(|e| self.visit_expression(e))
+            .collect::<Result<Vec<T>, ()>>()?;
+        Ok(Self::separated(items))
+    }
+}
@@ -0,0 +1,518 @@
+use super::constructors::group_below;
+use super::parser::{Expression, ParserError};
+use super::tokenizer::{Token, Tokenizer, TokenizingError};
+use super::visitors::ExpressionVisitor;
+use thiserror::Error;
+
+// Parses Reverse Polish Notation such as `3 4 + 2 *` into the tree the infix
+// parser builds for `(3 + 4) * 2`, including the `Grouping` nodes infix needs.
+pub fn parse_rpn(input: &str) -> Result<Expression, ParserError> {
+    let mut stack = RpnStack::new();
+    stack.push_input(input)?;
+    match stack.items.len() {
+        1 => Ok(stack.items.remove(0)),
+        0 => Err(ParserError::SyntaxError("Expected an expression.".into())),
+        n => Err(ParserError::SyntaxError(format!(
+            "Expected a single expression, found {} on the stack.",
+            n
+        ))),
+    }
+}
+
+// Number of arguments a function takes from the stack. Functions over a data set
+// take a single list, e.g. `[ 1 2 3 ] mean`. Any other count is written after the
+// name, as in `1 2 3 max:3` or `a b f:2`.
+fn arity(name: &str, stack: &[Expression]) -> Option<usize> {
+    let arity = match name {
+        "sqrt" | "exp" | "ln" | "log" | "abs" | "sin" | "cos" | "tan" | "asin" | "acos"
+        | "atan" | "sinh" | "cosh" | "tanh" | "transpose" | "det" | "inv" | "identity"
+        | "isprime" | "nextprime" | "factor" | "mean" | "median" | "mode" | "variance"
+        | "pvariance" | "stdev" | "pstdev" | "min" | "max" | "range" => 1,
+        "percentile" | "correlation" | "linreg" | "nCr" | "nPr" | "gcd" | "lcm" | "mod_inv" => 2,
+        "mod_pow" | "diff" => 3,
+        "integrate" => 4,
+        // `eq x guess solve` finds a root, `A b solve` solves a linear system.
+        "solve" => match stack.iter().rev().nth(1) {
+            Some(Expression::Variable(_)) => 3,
+            _ => 2,
+        },
+        _ => return None,
+    };
+    Some(arity)
+}
+
+// Words that manipulate the stack rather than naming a value.
+const STACK_COMMANDS: [&str; 6] = ["neg", "index", "swap", "dup", "drop", "clear"];
+
+// An HP-style operand stack. Each input line is applied atomically: if any token
+// fails, the stack is left as it was.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RpnStack {
+    items: Vec<Expression>,
+}
+
+impl RpnStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Bottom of the stack first.
+    pub fn items(&self) -> &[Expression] {
+        &self.items
+    }
+
+    pub fn push_input(&mut self, input: &str) -> Result<(), ParserError> {
+        let tokens = Tokenizer::new(input)
+            .with_operators([":"])
+            .collect::<Result<Vec<Token>, TokenizingError>>()?;
+
+        let mut items = self.items.clone();
+        // Stack heights at each open `[`.
+        let mut marks: Vec<usize> = Vec::new();
+        let mut tokens = tokens.into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            let count = match token {
+                Token::Identifier(_) if tokens.next_if(is_colon).is_some() => match tokens.next() {
+                    Some(Token::Number(n)) if n.fract() == 0.0 && n >= 0.0 => Some(n as usize),
+                    _ => {
+                        return Err(ParserError::SyntaxError(
+                            "Expected an argument count after ':'.".into(),
+                        ));
+                    }
+                },
+                _ => None,
+            };
+            apply(&mut items, &mut marks, token, count)?;
+        }
+        if !marks.is_empty() {
+            return Err(ParserError::SyntaxError(
+                "Expect ']' after elements.".into(),
+            ));
+        }
+
+        self.items = items;
+        Ok(())
+    }
+}
+
+fn is_colon(token: &Token) -> bool {
+    *token == Token::Operator(":".to_string())
+}
+
+// Applies `token` to the stack; `count` is the argument count written after a
+// function name.
+fn apply(
+    items: &mut Vec<Expression>,
+    marks: &mut Vec<usize>,
+    token: Token,
+    count: Option<usize>,
+) -> Result<(), ParserError> {
+    let floor = marks.last().copied().unwrap_or(0);
+    let pop = |items: &mut Vec<Expression>, n: usize, what: &str| {
+        if items.len() < floor + n {
+            return Err(ParserError::SyntaxError(format!(
+                "Not enough operands for '{}'.",
+                what
+            )));
+        }
+        Ok(items.split_off(items.len() - n))
+    };
+
+    let binary = |a: Expression, b: Expression, token: &Token| match token {
+        Token::Plus => Expression::Add(group_below(a, 1), group_below(b, 2)),
+        Token::Minus => Expression::Subtract(group_below(a, 1), group_below(b, 2)),
+        Token::Star => Expression::Multiply(group_below(a, 2), group_below(b, 3)),
+        Token::Slash => Expression::Divide(group_below(a, 2), group_below(b, 3)),
+        Token::Caret => Expression::Power(group_below(a, 5), group_below(b, 3)),
+        Token::Equal => Expression::Equation(group_below(a, 1), group_below(b, 1)),
+        _ => Expression::Range(group_below(a, 1), group_below(b, 1)),
+    };
+
+    match token {
+        Token::Number(n) => items.push(Expression::Number(n)),
+        Token::Plus
+        | Token::Minus
+        | Token::Star
+        | Token::Slash
+        | Token::Caret
+        | Token::Equal
+        | Token::DotDot => {
+            let symbol = match token {
+                Token::Plus => "+",
+                Token::Minus => "-",
+                Token::Star => "*",
+                Token::Slash => "/",
+                Token::Caret => "^",
+                Token::Equal => "=",
+                _ => "..",
+            };
+            let mut operands = pop(items, 2, symbol)?;
+            let b = operands.pop().unwrap();
+            let a = operands.pop().unwrap();
+            items.push(binary(a, b, &token));
+        }
+        Token::LeftBracket => marks.push(items.len()),
+        Token::RightBracket => {
+            let mark = marks
+                .pop()
+                .ok_or_else(|| ParserError::SyntaxError("Unexpected ']'.".into()))?;
+            let elements = items.split_off(mark);
+            items.push(Expression::List(elements));
+        }
+        Token::Identifier(name) if let Some(n) = count => {
+            let args = pop(items, n, &name)?;
+            items.push(Expression::Call(name, args));
+        }
+        Token::Identifier(name) => match name.as_str() {
+            "neg" => {
+                let a = pop(items, 1, "neg")?.remove(0);
+                items.push(Expression::Negate(group_below(a, 3)));
+            }
+            "index" => {
+                let mut operands = pop(items, 2, "index")?;
+                let indices = match &mut operands.pop().unwrap() {
+                    Expression::List(indices) => std::mem::take(indices),
+                    _ => {
+                        return Err(ParserError::SyntaxError(
+                            "'index' expects a list of indices.".into(),
+                        ));
+                    }
+                };
+                let target = operands.pop().unwrap();
+                items.push(Expression::Index(group_below(target, 5), indices));
+            }
+            "swap" => {
+                let mut operands = pop(items, 2, "swap")?;
+                operands.reverse();
+                items.extend(operands);
+            }
+            "dup" => {
+                let top = pop(items, 1, "dup")?.remove(0);
+                items.push(top.clone());
+                items.push(top);
+            }
+            "drop" => {
+                pop(items, 1, "drop")?;
+            }
+            "clear" => items.truncate(floor),
+            _ => match arity(&name, &items[floor..]) {
+                Some(n) => {
+                    let args = pop(items, n, &name)?;
+                    items.push(Expression::Call(name, args));
+                }
+                None => items.push(Expression::Variable(name)),
+            },
+        },
+        Token::LeftParen | Token::RightParen | Token::Comma => {
+            return Err(ParserError::SyntaxError(
+                "Parentheses and commas are not used in RPN.".into(),
+            ));
+        }
+        Token::Operator(symbol) => {
+            return Err(ParserError::SyntaxError(format!(
+                "Unknown operator '{}'.",
+                symbol
+            )));
+        }
+    }
+    Ok(())
+}
+
+// Trees that `parse_rpn` could not read back from their RPN text.
+#[derive(Error, Debug, PartialEq)]
+pub enum RpnPrintError {
+    #[error("Variable '{0}' would read back as a function or stack command")]
+    ReservedName(String),
+    #[error("{0} has no RPN literal")]
+    NonFiniteNumber(f64),
+}
+
+// Prints any expression in postfix order, e.g. `3 4 + 2 *`. Negation is written
+// `neg`, also for negative numbers, and element access `target [ indices ] index`.
+// A call is followed by its argument count unless the reader's arity table gives
+// the same one, e.g. `x sin` but `a 1 max:2`. Variables named like a function or
+// stack command, and infinite or NaN numbers, are rejected, so the output always
+// reads back to the same tree.
+pub struct RpnPrinterVisitor;
+
+impl RpnPrinterVisitor {
+    fn postfix(
+        &mut self,
+        operands: &[&Expression],
+        operator: &str,
+    ) -> Result<String, RpnPrintError> {
+        let mut parts = operands
+            .iter()
+            .map(|e| self.visit_expression(e))
+            .collect::<Result<Vec<String>, RpnPrintError>>()?;
+        parts.push(operator.to_string());
+        Ok(parts.join(" "))
+    }
+
+    fn list(&mut self, elements: &[Expression]) -> Result<String, RpnPrintError> {
+        let operands: Vec<&Expression> = elements.iter().collect();
+        let mut text = self.postfix(&operands, "]")?;
+        text.insert_str(0, "[ ");
+        Ok(text)
+    }
+}
+
+impl ExpressionVisitor<String, RpnPrintError> for RpnPrinterVisitor {
+    fn visit_number(&mut self, value: f64) -> Result<String, RpnPrintError> {
+        if !value.is_finite() {
+            return Err(RpnPrintError::NonFiniteNumber(value));
+        }
+        if value.is_sign_negative() {
+            return Ok(format!("{} neg", -value));
+        }
+        Ok(value.to_string())
+    }
+
+    fn visit_add(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<String, RpnPrintError> {
+        self.postfix(&[left, right], "+")
+    }
+
+    fn visit_subtract(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<String, RpnPrintError> {
+        self.postfix(&[left, right], "-")
+    }
+
+    fn visit_multiply(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<String, RpnPrintError> {
+        self.postfix(&[left, right], "*")
+    }
+
+    fn visit_divide(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<String, RpnPrintError> {
+        self.postfix(&[left, right], "/")
+    }
+
+    fn visit_power(
+        &mut self,
+        base: &Expression,
+        exponent: &Expression,
+    ) -> Result<String, RpnPrintError> {
+        self.postfix(&[base, exponent], "^")
+    }
+
+    fn visit_negate(&mut self, expr: &Expression) -> Result<String, RpnPrintError> {
+        self.postfix(&[expr], "neg")
+    }
+
+    fn visit_grouping(&mut self, expr: &Expression) -> Result<String, RpnPrintError> {
+        self.visit_expression(expr)
+    }
+
+    fn visit_variable(&mut self, name: &str) -> Result<String, RpnPrintError> {
+        if STACK_COMMANDS.contains(&name) || arity(name, &[]).is_some() {
+            return Err(RpnPrintError::ReservedName(name.to_string()));
+        }
+        Ok(name.to_string())
+    }
+
+    fn visit_equation(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<String, RpnPrintError> {
+        self.postfix(&[left, right], "=")
+    }
+
+    fn visit_range(&mut self, from: &Expression, to: &Expression) -> Result<String, RpnPrintError> {
+        self.postfix(&[from, to], "..")
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) -> Result<String, RpnPrintError> {
+        self.list(elements)
+    }
+
+    fn visit_call(&mut self, name: &str, args: &[Expression]) -> Result<String, RpnPrintError> {
+        let operands: Vec<&Expression> = args.iter().collect();
+        if arity(name, args) == Some(args.len()) {
+            return self.postfix(&operands, name);
+        }
+        self.postfix(&operands, &format!("{}:{}", name, args.len()))
+    }
+
+    fn visit_index(
+        &mut self,
+        target: &Expression,
+        indices: &[Expression],
+    ) -> Result<String, RpnPrintError> {
+        Ok(format!(
+            "{} {} index",
+            self.visit_expression(target)?,
+            self.list(indices)?
+        ))
+    }
+
+    fn visit_error(&mut self) -> Result<String, RpnPrintError> {
+        Ok("?".to_string())
+    }
+}
+
+#[cfg(test)]
+mod rpn_tests {
+    use super::*;
+    use crate::parsemath::parser::Parser;
+    use rstest::rstest;
+
+    fn infix(input: &str) -> Expression {
+        Parser::new(input).unwrap().parse().unwrap()
+    }
+
+    fn elements(input: &str) -> Vec<Expression> {
+        match &mut infix(input) {
+            Expression::List(elements) => std::mem::take(elements),
+            _ => panic!("not a list"),
+        }
+    }
+
+    #[rstest]
+    #[case("3 4 + 2 *", "(3 + 4) * 2")]
+    #[case("1 2 3 - -", "1 - (2 - 3)")]
+    #[case("1 2 - 3 -", "1 - 2 - 3")]
+    #[case("2 3 2 ^ ^", "2 ^ 3 ^ 2")]
+    #[case("2 3 ^ 2 ^", "(2 ^ 3) ^ 2")]
+    #[case("x neg 2 ^", "(-x) ^ 2")]
+    #[case("x 2 ^ neg", "-x ^ 2")]
+    #[case("x sin 2 x * cos +", "sin(x) + cos(2 * x)")]
+    #[case("x 2 ^ 2 = x 1 solve", "solve(x ^ 2 = 2, x, 1)")]
+    #[case("[ [ 1 2 ] [ 3 4 ] ] [ 1 2 ] solve", "solve([[1, 2], [3, 4]], [1, 2])")]
+    #[case("v [ 1 2 ] index", "v[1, 2]")]
+    #[case("0 5 ..", "0..5")]
+    #[case("a 1 max:2", "max(a, 1)")]
+    #[case("x sin:1 f:0 +", "sin(x) + f()")]
+    #[case("[ 1 2 ] 3 4 mean:3 neg:1", "neg(mean([1, 2], 3, 4))")]
+    fn rpn_matches_infix_tree(#[case] rpn: &str, #[case] expected: &str) {
+        assert_eq!(parse_rpn(rpn).unwrap(), infix(expected));
+    }
+
+    #[rstest]
+    #[case("1 +", "Syntax error: Not enough operands for '+'.")]
+    #[case(
+        "1 2",
+        "Syntax error: Expected a single expression, found 2 on the stack."
+    )]
+    #[case("", "Syntax error: Expected an expression.")]
+    #[case("[ 1 + ]", "Syntax error: Not enough operands for '+'.")]
+    #[case("[ 1 2", "Syntax error: Expect ']' after elements.")]
+    #[case("1 ]", "Syntax error: Unexpected ']'.")]
+    #[case("(1 2 +)", "Syntax error: Parentheses and commas are not used in RPN.")]
+    #[case("1 2 #", "Syntax error: Unexpected token '#'")]
+    #[case("1 2 max:", "Syntax error: Expected an argument count after ':'.")]
+    #[case("1 2 max:1.5", "Syntax error: Expected an argument count after ':'.")]
+    #[case("1 2 max:3", "Syntax error: Not enough operands for 'max'.")]
+    #[case("1 2 :", "Syntax error: Unknown operator ':'.")]
+    fn rpn_errors(#[case] rpn: &str, #[case] expected: &str) {
+        assert_eq!(parse_rpn(rpn).unwrap_err().to_string(), expected);
+    }
+
+    #[rstest]
+    fn stack_commands() {
+        let mut stack = RpnStack::new();
+        stack.push_input("1 2 3").unwrap();
+        stack.push_input("swap").unwrap();
+        assert_eq!(stack.items(), &elements("[1, 3, 2]"));
+        stack.push_input("dup drop drop").unwrap();
+        assert_eq!(stack.items(), &elements("[1, 3]"));
+        stack.push_input("clear").unwrap();
+        assert!(stack.items().is_empty());
+    }
+
+    #[rstest]
+    fn failed_input_leaves_stack_unchanged() {
+        let mut stack = RpnStack::new();
+        stack.push_input("1 2").unwrap();
+        assert!(stack.push_input("+ + +").is_err());
+        assert_eq!(stack.items().len(), 2);
+    }
+
+    #[rstest]
+    #[case("(3 + 4) * 2", "3 4 + 2 *")]
+    #[case("-x ^ 2 / f(a, b)", "x 2 ^ neg a b f:2 /")]
+    #[case("max(a, 1) + mean([1, 2, 3])", "a 1 max:2 [ 1 2 3 ] mean +")]
+    #[case("solve(A, b) + solve(x = 1, x, 0)", "A b solve:2 x 1 = x 0 solve +")]
+    #[case("m[2, 1] + [1, 2]", "m [ 2 1 ] index [ 1 2 ] +")]
+    fn print_rpn(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(
+            RpnPrinterVisitor.visit_expression(&infix(input)).unwrap(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case("1 - (2 - 3) * 4 ^ 2 ^ -x")]
+    #[case("sqrt(x) / (ln(y) + 1) = (2 ^ 3) ^ 2")]
+    #[case("integrate(t ^ 2, t, 0, 1) + mod_pow(3, 4, 5)")]
+    #[case("max(a, 1) * mean(1, 2, 3) - min([1, 2])")]
+    #[case("-x ^ 2 / f(a, b) + g() * sin(h(x, y, z))")]
+    #[case("solve(A, b) + solve(x ^ 2, x, 1) + solve(x ^ 2 = 2, x)")]
+    fn rpn_round_trip(#[case] input: &str) {
+        let ast = infix(input);
+        let rpn = RpnPrinterVisitor.visit_expression(&ast).unwrap();
+        assert_eq!(parse_rpn(&rpn).unwrap(), ast, "{}", rpn);
+    }
+
+    #[rstest]
+    #[case("sin + 1", RpnPrintError::ReservedName("sin".to_string()))]
+    #[case("f(mean)", RpnPrintError::ReservedName("mean".to_string()))]
+    #[case("[dup, 2]", RpnPrintError::ReservedName("dup".to_string()))]
+    #[case("neg * index", RpnPrintError::ReservedName("neg".to_string()))]
+    #[case("x - clear", RpnPrintError::ReservedName("clear".to_string()))]
+    fn reserved_variable_names_are_rejected(#[case] input: &str, #[case] expected: RpnPrintError) {
+        assert_eq!(
+            RpnPrinterVisitor.visit_expression(&infix(input)),
+            Err(expected)
+        );
+    }
+
+    #[rstest]
+    #[case(f64::INFINITY)]
+    #[case(f64::NEG_INFINITY)]
+    #[case(f64::NAN)]
+    fn non_finite_numbers_are_rejected(#[case] value: f64) {
+        let ast = Expression::Add(
+            Box::new(Expression::Variable("x".to_string())),
+            Box::new(Expression::Number(value)),
+        );
+        let error = RpnPrinterVisitor.visit_expression(&ast).unwrap_err();
+        assert!(
+            matches!(error, RpnPrintError::NonFiniteNumber(n) if n.to_bits() == value.to_bits())
+        );
+    }
+
+    #[rstest]
+    #[case("sine + mean_x * dupe")]
+    #[case("f(swapped, x_neg) + clear_1")]
+    #[case("0.5 * x - 1234.5")]
+    fn names_near_reserved_words_round_trip(#[case] input: &str) {
+        let ast = infix(input);
+        let rpn = RpnPrinterVisitor.visit_expression(&ast).unwrap();
+        assert_eq!(parse_rpn(&rpn).unwrap(), ast, "{}", rpn);
+    }
+
+    #[rstest]
+    fn negative_numbers_print_as_negations() {
+        let ast = Expression::Multiply(
+            Box::new(Expression::Number(-2.0)),
+            Box::new(Expression::Variable("x".to_string())),
+        );
+        let rpn = RpnPrinterVisitor.visit_expression(&ast).unwrap();
+        assert_eq!(rpn, "2 neg x *");
+        assert_eq!(parse_rpn(&rpn).unwrap(), infix("-2 * x"));
+    }
+}
@@ -0,0 +1,415 @@
+use super::limits::{self, EvalLimits, LimitError};
+use super::parser::Expression;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use thiserror::Error;
+
+// Storage formats for expressions, available with the `serde` feature.
+//
+// JSON documents look like `{"version":1,"expression":{"add":[{"number":1.0},
+// {"variable":"x"}]}}`: every node is an object with a single snake_case key
+// naming the `Expression` variant, holding its fields (an array when there are
+// several). The binary encoding is one version byte followed by the bincode
+// varint encoding of the same tree. Both formats reproduce a tree exactly, so a
+// stored formula evaluates the same as the freshly parsed one. Changes to the
+// schema bump `FORMAT_VERSION`; documents from other versions are rejected.
+//
+// Decoded trees are held to the depth and node limits of `EvalLimits`, like
+// parsed ones, so anything the parser accepts loads back.
+pub const FORMAT_VERSION: u8 = 1;
+
+#[derive(Error, Debug)]
+pub enum SerializationError {
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid binary encoding: {0}")]
+    Binary(#[from] bincode::Error),
+    #[error("Unsupported format version {0}")]
+    UnsupportedVersion(u64),
+    #[error("JSON cannot represent the number {0}")]
+    NonFiniteNumber(f64),
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(#[from] LimitError),
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    version: u8,
+    expression: &'a Expression,
+}
+
+// Read in two steps so the version is checked before the tree is decoded.
+#[derive(Deserialize)]
+struct StoredDocument<'a> {
+    version: u64,
+    #[serde(borrow)]
+    expression: &'a RawValue,
+}
+
+pub fn to_json(expr: &Expression) -> Result<String, SerializationError> {
+    check_finite(expr)?;
+    Ok(serde_json::to_string(&Document {
+        version: FORMAT_VERSION,
+        expression: expr,
+    })?)
+}
+
+pub fn from_json(json: &str) -> Result<Expression, SerializationError> {
+    from_json_with_limits(json, &EvalLimits::default())
+}
+
+// serde_json stops at 128 nested values by default, well short of the trees the
+// parser accepts, so the tree is decoded without that limit once its nesting is
+// known to be within `limits`.
+pub fn from_json_with_limits(
+    json: &str,
+    limits: &EvalLimits,
+) -> Result<Expression, SerializationError> {
+    let document: StoredDocument = decode_json(json)?;
+    if document.version != u64::from(FORMAT_VERSION) {
+        return Err(SerializationError::UnsupportedVersion(document.version));
+    }
+    let json = document.expression.get();
+    check_json_nesting(json, limits)?;
+    let expr = decode_json(json)?;
+    limits::check_tree(&expr, limits)?;
+    Ok(expr)
+}
+
+fn decode_json<'a, T: Deserialize<'a>>(json: &'a str) -> Result<T, serde_json::Error> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    deserializer.disable_recursion_limit();
+    let value = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+// Decoding recurses once per nested array or object. Each node opens at most
+// three of them (`{"call":["f",[...]]}`), so deeper nesting means more than
+// `max_nodes` nodes, and is rejected before it can overflow the stack.
+fn check_json_nesting(json: &str, limits: &EvalLimits) -> Result<(), LimitError> {
+    let max_nesting = limits.max_nodes.saturating_mul(3);
+    let (mut nesting, mut in_string, mut escaped) = (0usize, false, false);
+    for byte in json.bytes() {
+        match byte {
+            _ if escaped => escaped = false,
+            b'\\' if in_string => escaped = true,
+            b'"' => in_string = !in_string,
+            _ if in_string => {}
+            b'[' | b'{' => {
+                nesting += 1;
+                if nesting > max_nesting {
+                    return Err(LimitError::TooManyNodes(limits.max_nodes));
+                }
+            }
+            b']' | b'}' => nesting = nesting.saturating_sub(1),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+pub fn to_bytes(expr: &Expression) -> Result<Vec<u8>, SerializationError> {
+    let mut bytes = vec![FORMAT_VERSION];
+    bincode_options().serialize_into(&mut bytes, expr)?;
+    Ok(bytes)
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Result<Expression, SerializationError> {
+    from_bytes_with_limits(bytes, &EvalLimits::default())
+}
+
+pub fn from_bytes_with_limits(
+    bytes: &[u8],
+    limits: &EvalLimits,
+) -> Result<Expression, SerializationError> {
+    match bytes.split_first() {
+        Some((&FORMAT_VERSION, encoded)) => {
+            count_encoded_nodes(encoded, limits)?;
+            let expr = bincode_options().deserialize(encoded)?;
+            limits::check_tree(&expr, limits)?;
+            Ok(expr)
+        }
+        Some((&version, _)) => Err(SerializationError::UnsupportedVersion(u64::from(version))),
+        None => Err(SerializationError::Binary(Box::new(
+            bincode::ErrorKind::Custom("empty input".to_string()),
+        ))),
+    }
+}
+
+// bincode decodes recursively and has no depth limit of its own, so the nodes
+// of the encoded tree are counted first, without decoding it. Malformed input
+// is left for bincode to report.
+fn count_encoded_nodes(mut bytes: &[u8], limits: &EvalLimits) -> Result<(), LimitError> {
+    let (mut nodes, mut pending) = (0usize, 1u64);
+    while pending > 0 {
+        pending -= 1;
+        nodes += 1;
+        if nodes > limits.max_nodes {
+            return Err(LimitError::TooManyNodes(limits.max_nodes));
+        }
+        // The variant indices follow the declaration order of `Expression`.
+        let children = match read_varint(&mut bytes) {
+            Some(0) => skip(&mut bytes, 8).map(|_| 0),
+            Some(1..=5 | 9 | 10) => Some(2),
+            Some(6 | 7) => Some(1),
+            Some(8) => read_varint(&mut bytes)
+                .and_then(|len| skip(&mut bytes, len))
+                .map(|_| 0),
+            Some(11) => read_varint(&mut bytes),
+            Some(12) => read_varint(&mut bytes)
+                .and_then(|len| skip(&mut bytes, len))
+                .and_then(|_| read_varint(&mut bytes)),
+            Some(13) => read_varint(&mut bytes).map(|len| len.saturating_add(1)),
+            Some(14) => Some(0),
+            _ => None,
+        };
+        match children {
+            Some(children) => pending = pending.saturating_add(children),
+            None => return Ok(()),
+        }
+    }
+    Ok(())
+}
+
+fn skip(bytes: &mut &[u8], count: u64) -> Option<()> {
+    *bytes = bytes.get(usize::try_from(count).ok()?..)?;
+    Some(())
+}
+
+// bincode's varint encoding: values below 251 are a single byte, larger ones a
+// marker byte followed by a little-endian u16, u32 or u64.
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let (&first, rest) = bytes.split_first()?;
+    let width = match first {
+        0..=250 => {
+            *bytes = rest;
+            return Some(u64::from(first));
+        }
+        251 => 2,
+        252 => 4,
+        253 => 8,
+        _ => return None,
+    };
+    let value = rest.get(..width)?;
+    *bytes = &rest[width..];
+    let mut le = [0; 8];
+    le[..width].copy_from_slice(value);
+    Some(u64::from_le_bytes(le))
+}
+
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new().reject_trailing_bytes()
+}
+
+// JSON has no NaN or infinity; serde_json would silently write `null`.
+fn check_finite(expr: &Expression) -> Result<(), SerializationError> {
+    let mut pending = vec![expr];
+    while let Some(expr) = pending.pop() {
+        if let Expression::Number(n) = expr
+            && !n.is_finite()
+        {
+            return Err(SerializationError::NonFiniteNumber(*n));
+        }
+        pending.extend(expr.children().into_iter().rev());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod serialization_tests {
+    use super::*;
+    use crate::parsemath::limits::{DEFAULT_MAX_DEPTH, DEFAULT_MAX_NODES};
+    use crate::parsemath::parser::Parser;
+    use crate::parsemath::tokenizer::{Token, Tokenizer};
+    use crate::parsemath::visitors::{Evaluator, ExpressionVisitor};
+    use rstest::rstest;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input).unwrap().parse().unwrap()
+    }
+
+    #[rstest]
+    fn json_schema() {
+        assert_eq!(
+            to_json(&parse("-(1 + x) ^ 0.5")).unwrap(),
+            "{\"version\":1,\"expression\":{\"negate\":{\"power\":[{\"grouping\":\
+             {\"add\":[{\"number\":1.0},{\"variable\":\"x\"}]}},{\"number\":0.5}]}}}"
+        );
+        assert_eq!(
+            to_json(&parse("f(v[1], [2])")).unwrap(),
+            "{\"version\":1,\"expression\":{\"call\":[\"f\",[{\"index\":[{\"variable\":\"v\"},\
+             [{\"number\":1.0}]]},{\"list\":[{\"number\":2.0}]}]]}}"
+        );
+    }
+
+    #[rstest]
+    #[case("2 * pi * r ^ 2 / 3")]
+    #[case("0.1 + 0.2 - 0.000000000000001 * 123456.789")]
+    #[case("solve(x ^ 2 = 2, x, 0..5)")]
+    #[case("det([[1, 2], [3, 4]])[1] + mean([0.3, 1 / 3])")]
+    fn round_trips_evaluate_identically(#[case] input: &str) {
+        let ast = parse(input);
+        let from_json = from_json(&to_json(&ast).unwrap()).unwrap();
+        let from_bytes = from_bytes(&to_bytes(&ast).unwrap()).unwrap();
+        assert_eq!(from_json, ast);
+        assert_eq!(from_bytes, ast);
+
+        let expected = Evaluator::new().visit_expression(&ast);
+        assert_eq!(Evaluator::new().visit_expression(&from_json), expected);
+        assert_eq!(Evaluator::new().visit_expression(&from_bytes), expected);
+    }
+
+    #[rstest]
+    fn binary_encoding_is_compact() {
+        let ast = parse("x + 1");
+        let bytes = to_bytes(&ast).unwrap();
+        assert_eq!(bytes.len(), 14);
+        assert!(bytes.len() < to_json(&ast).unwrap().len() / 4);
+    }
+
+    #[rstest]
+    fn non_finite_numbers_round_trip_in_binary_only() {
+        let ast = Expression::Add(
+            Box::new(Expression::Number(f64::INFINITY)),
+            Box::new(Expression::Number(1.0)),
+        );
+        assert_eq!(
+            to_json(&ast).unwrap_err().to_string(),
+            "JSON cannot represent the number inf"
+        );
+        assert_eq!(from_bytes(&to_bytes(&ast).unwrap()).unwrap(), ast);
+    }
+
+    #[rstest]
+    #[case("{\"version\":2,\"expression\":{\"number\":1.0}}")]
+    #[case("{\"version\":0,\"expression\":{\"number\":1.0}}")]
+    fn rejects_other_json_versions(#[case] json: &str) {
+        assert!(matches!(
+            from_json(json),
+            Err(SerializationError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[rstest]
+    #[case("{\"expression\":{\"number\":1.0}}")]
+    #[case("{\"version\":1,\"expression\":{\"modulo\":[]}}")]
+    #[case("{\"version\":1,\"expression\":{\"add\":[{\"number\":1.0}]}}")]
+    fn rejects_malformed_json(#[case] json: &str) {
+        assert!(matches!(from_json(json), Err(SerializationError::Json(_))));
+    }
+
+    #[rstest]
+    fn rejects_malformed_bytes() {
+        let mut bytes = to_bytes(&parse("1 + 2")).unwrap();
+        assert!(matches!(
+            from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SerializationError::Binary(_))
+        ));
+        bytes.push(0);
+        assert!(matches!(
+            from_bytes(&bytes),
+            Err(SerializationError::Binary(_))
+        ));
+        bytes[0] = 9;
+        assert_eq!(
+            from_bytes(&bytes).unwrap_err().to_string(),
+            "Unsupported format version 9"
+        );
+        assert!(from_bytes(&[]).is_err());
+    }
+
+    fn limit_error(result: Result<Expression, SerializationError>) -> Option<LimitError> {
+        match result {
+            Err(SerializationError::LimitExceeded(error)) => Some(error),
+            _ => None,
+        }
+    }
+
+    // A sum with `extra` nodes more than the default limit allows.
+    fn sum_of_nodes(extra: usize) -> Expression {
+        let mut expr = Expression::Variable("x".to_string());
+        for _ in 0..(DEFAULT_MAX_NODES + extra - 2) / 2 {
+            expr = Expression::Add(Box::new(expr), Box::new(Expression::Number(1.0)));
+        }
+        Expression::Negate(Box::new(expr))
+    }
+
+    #[rstest]
+    fn largest_trees_round_trip() {
+        let ast = sum_of_nodes(0);
+        assert_eq!(limits::check_tree(&ast, &EvalLimits::default()), Ok(()));
+        assert_eq!(from_json(&to_json(&ast).unwrap()).unwrap(), ast);
+        assert_eq!(from_bytes(&to_bytes(&ast).unwrap()).unwrap(), ast);
+
+        let ast = sum_of_nodes(2);
+        assert_eq!(
+            limit_error(from_json(&to_json(&ast).unwrap())),
+            Some(LimitError::TooManyNodes(DEFAULT_MAX_NODES))
+        );
+        assert_eq!(
+            limit_error(from_bytes(&to_bytes(&ast).unwrap())),
+            Some(LimitError::TooManyNodes(DEFAULT_MAX_NODES))
+        );
+
+        let limits = EvalLimits {
+            max_nodes: DEFAULT_MAX_NODES + 2,
+            ..EvalLimits::default()
+        };
+        let from_json = from_json_with_limits(&to_json(&ast).unwrap(), &limits);
+        let from_bytes = from_bytes_with_limits(&to_bytes(&ast).unwrap(), &limits);
+        assert_eq!(from_json.unwrap(), ast);
+        assert_eq!(from_bytes.unwrap(), ast);
+    }
+
+    #[rstest]
+    fn deep_documents_are_rejected_before_decoding() {
+        let depth = 1_000_000;
+        let json = format!(
+            "{{\"version\":1,\"expression\":{}{{\"number\":1.0}}{}}}",
+            "{\"negate\":".repeat(depth),
+            "}".repeat(depth)
+        );
+        assert_eq!(
+            limit_error(from_json(&json)),
+            Some(LimitError::TooManyNodes(DEFAULT_MAX_NODES))
+        );
+
+        // `Negate` is variant 6 and `Error` variant 14.
+        let mut bytes = vec![FORMAT_VERSION];
+        bytes.extend(std::iter::repeat_n(6, depth));
+        bytes.push(14);
+        assert_eq!(
+            limit_error(from_bytes(&bytes)),
+            Some(LimitError::TooManyNodes(DEFAULT_MAX_NODES))
+        );
+    }
+
+    #[rstest]
+    fn deep_trees_are_rejected_after_decoding() {
+        let mut ast = Expression::Number(1.0);
+        for _ in 0..=DEFAULT_MAX_DEPTH {
+            ast = Expression::Negate(Box::new(ast));
+        }
+        assert_eq!(
+            limit_error(from_json(&to_json(&ast).unwrap())),
+            Some(LimitError::TooDeeplyNested(DEFAULT_MAX_DEPTH))
+        );
+        assert_eq!(
+            limit_error(from_bytes(&to_bytes(&ast).unwrap())),
+            Some(LimitError::TooDeeplyNested(DEFAULT_MAX_DEPTH))
+        );
+    }
+
+    #[rstest]
+    fn tokens_serialize() {
+        let tokens: Vec<Token> = Tokenizer::new("a .. 2").map(Result::unwrap).collect();
+        let json = serde_json::to_string(&tokens).unwrap();
+        assert_eq!(
+            json,
+            "[{\"identifier\":\"a\"},\"dot_dot\",{\"number\":2.0}]"
+        );
+        assert_eq!(serde_json::from_str::<Vec<Token>>(&json).unwrap(), tokens);
+    }
+}
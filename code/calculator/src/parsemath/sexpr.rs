@@ -0,0 +1,114 @@
+use super::parser::Expression;
+use super::visitors::ExpressionVisitor;
+
+// Dumps an expression as a Lisp-style S-expression for debugging, e.g.
+// `(1 + 2) * (3 - 4)` becomes `(* (+ 1 2) (- 3 4))`. The nesting already shows
+// the structure, so groupings are left out.
+pub struct SExprVisitor;
+
+impl SExprVisitor {
+    fn form(&mut self, head: &str, operands: &[&Expression]) -> Result<String, ()> {
+        let mut parts = vec![head.to_string()];
+        for operand in operands {
+            parts.push(self.visit_expression(operand)?);
+        }
+        Ok(format!("({})", parts.join(" ")))
+    }
+
+    fn form_all(
+        &mut self,
+        head: &str,
+        first: Option<&Expression>,
+        rest: &[Expression],
+    ) -> Result<String, ()> {
+        let operands: Vec<&Expression> = first.into_iter().chain(rest).collect();
+        self.form(head, &operands)
+    }
+}
+
+impl ExpressionVisitor<String, ()> for SExprVisitor {
+    fn visit_number(&mut self, value: f64) -> Result<String, ()> {
+        Ok(value.to_string())
+    }
+
+    fn visit_add(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.form("+", &[left, right])
+    }
+
+    fn visit_subtract(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.form("-", &[left, right])
+    }
+
+    fn visit_multiply(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.form("*", &[left, right])
+    }
+
+    fn visit_divide(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.form("/", &[left, right])
+    }
+
+    fn visit_power(&mut self, base: &Expression, exponent: &Expression) -> Result<String, ()> {
+        self.form("^", &[base, exponent])
+    }
+
+    fn visit_negate(&mut self, expr: &Expression) -> Result<String, ()> {
+        self.form("-", &[expr])
+    }
+
+    fn visit_grouping(&mut self, expr: &Expression) -> Result<String, ()> {
+        self.visit_expression(expr)
+    }
+
+    fn visit_variable(&mut self, name: &str) -> Result<String, ()> {
+        Ok(name.to_string())
+    }
+
+    fn visit_equation(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.form("=", &[left, right])
+    }
+
+    fn visit_range(&mut self, from: &Expression, to: &Expression) -> Result<String, ()> {
+        self.form("..", &[from, to])
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) -> Result<String, ()> {
+        self.form_all("list", None, elements)
+    }
+
+    fn visit_call(&mut self, name: &str, args: &[Expression]) -> Result<String, ()> {
+        self.form_all(name, None, args)
+    }
+
+    fn visit_index(&mut self, target: &Expression, indices: &[Expression]) -> Result<String, ()> {
+        self.form_all("index", Some(target), indices)
+    }
+
+    fn visit_error(&mut self) -> Result<String, ()> {
+        Ok("?".to_string())
+    }
+}
+
+#[cfg(test)]
+mod sexpr_tests {
+    use super::*;
+    use crate::parsemath::parser::Parser;
+    use rstest::rstest;
+
+    fn sexpr(input: &str) -> String {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        SExprVisitor.visit_expression(&ast).unwrap()
+    }
+
+    #[rstest]
+    #[case("(1 + 2) * (3 - 4)", "(* (+ 1 2) (- 3 4))")]
+    #[case("1 - 2 - 3", "(- (- 1 2) 3)")]
+    #[case("-x ^ 2.5", "(- (^ x 2.5))")]
+    #[case("-2 / y", "(/ (- 2) y)")]
+    #[case("0..5", "(.. 0 5)")]
+    #[case("max(a, sin(b))", "(max a (sin b))")]
+    #[case("[[1, 2], [3]]", "(list (list 1 2) (list 3))")]
+    #[case("m[1, 2]", "(index m 1 2)")]
+    fn dump_sexpr(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(sexpr(input), expected);
+    }
+}
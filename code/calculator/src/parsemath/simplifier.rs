@@ -0,0 +1,540 @@
+use super::constructors::{
+    add, as_number, divide, is_non_negative, is_scalar, multiply, negate, number, power,
+    power_numbers, subtract,
+};
+use super::parser::Expression;
+use super::transform::ExpressionTransformer;
+use crate::calcmath::{functions, value::Value};
+
+// Rewrites an expression into an equivalent, simpler tree: constant sub-trees are
+// folded, redundant groupings dropped, like terms collected (`x + 2 * x` -> `3 * x`)
+// and powers of a common base combined (`x * x ^ 2` -> `x ^ 3`).
+//
+// Free variables are taken to be numbers, so `x - x` is `0`. With
+// `with_matrix_variables(true)` they may stand for matrices instead: products of
+// them are never reordered and nothing that would turn a matrix into a number,
+// such as `A - A` -> `0`, is applied to them.
+#[derive(Default)]
+pub struct Simplifier {
+    matrix_variables: bool,
+}
+
+impl Simplifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_matrix_variables(mut self, matrix_variables: bool) -> Self {
+        self.matrix_variables = matrix_variables;
+        self
+    }
+
+    pub fn simplify(expr: &Expression) -> Expression {
+        Simplifier::new().transform_expression(expr.clone())
+    }
+}
+
+// A sum as `constant + coefficient * term + ...`, with each distinct term stored
+// once. Terms are kept as products so that `x * y` and `y * x` are recognised as alike.
+//
+// Terms that may be matrices are kept even when their coefficient is 0, as
+// `A - A` is a zero matrix rather than the number 0; so is a constant of 0 next
+// to them, since `A + 0` fails to evaluate.
+struct Sum {
+    constant: Option<f64>,
+    terms: Vec<Product>,
+    matrix_variables: bool,
+}
+
+impl Sum {
+    fn new(matrix_variables: bool) -> Self {
+        Sum {
+            constant: None,
+            terms: Vec::new(),
+            matrix_variables,
+        }
+    }
+
+    fn collect(&mut self, mut expr: Expression, sign: f64) {
+        match &mut expr {
+            Expression::Number(n) => *self.constant.get_or_insert(0.0) += sign * *n,
+            Expression::Add(a, b) => {
+                self.collect(a.take(), sign);
+                self.collect(b.take(), sign);
+            }
+            Expression::Subtract(a, b) => {
+                self.collect(a.take(), sign);
+                self.collect(b.take(), -sign);
+            }
+            Expression::Negate(e) => self.collect(e.take(), -sign),
+            Expression::Grouping(e) => self.collect(e.take(), sign),
+            _ => {
+                let mut term = Product::new(self.matrix_variables);
+                term.coefficient = sign;
+                term.collect(expr, 1.0);
+                match self.terms.iter_mut().find(|t| t.is_like(&term)) {
+                    Some(t) => t.coefficient += term.coefficient,
+                    None => self.terms.push(term),
+                }
+            }
+        }
+    }
+
+    fn build(self) -> Expression {
+        let scalar = self.terms.iter().all(Product::is_scalar);
+        let constant = self.constant.map(|coefficient| Product {
+            coefficient,
+            factors: Vec::new(),
+            matrix_variables: self.matrix_variables,
+        });
+        let terms = self
+            .terms
+            .into_iter()
+            .filter(|t| t.coefficient != 0.0 || !t.is_scalar())
+            .chain(constant.filter(|c| c.coefficient != 0.0 || !scalar));
+
+        let mut result: Option<Expression> = None;
+        for mut term in terms {
+            result = Some(match result {
+                None => term.build(),
+                Some(sum) if term.coefficient < 0.0 => {
+                    term.coefficient = -term.coefficient;
+                    subtract(sum, term.build())
+                }
+                Some(sum) => add(sum, term.build()),
+            });
+        }
+        result.unwrap_or(number(0.0))
+    }
+}
+
+// A product as `coefficient * base ^ exponent * ...`. Negative exponents end up
+// in the denominator. Simplifying must not change what an expression means, so:
+//
+// - factors that may be matrices keep their order and are only merged with the
+//   previous one (`A * A * B` is `A ^ 2 * B`, but `A * B * A` stays as it is);
+// - factors known to be numbers commute and merge wherever they appear;
+// - numerator and denominator never cancel, so `x / x` still fails for x = 0.
+//
+// A divisor is always a number (dividing by a matrix is an error), so
+// denominator factors merge wherever they appear too.
+struct Product {
+    coefficient: f64,
+    factors: Vec<(Expression, f64)>,
+    matrix_variables: bool,
+}
+
+impl Product {
+    fn new(matrix_variables: bool) -> Self {
+        Product {
+            coefficient: 1.0,
+            factors: Vec::new(),
+            matrix_variables,
+        }
+    }
+
+    fn is_scalar_factor(&self, base: &Expression) -> bool {
+        is_scalar(base, self.matrix_variables)
+    }
+
+    fn collect(&mut self, mut expr: Expression, exponent: f64) {
+        match &mut expr {
+            // Division by a literal zero is left in place for the evaluator to report.
+            Expression::Number(n) if exponent > 0.0 || *n != 0.0 => {
+                self.coefficient *= n.powf(exponent)
+            }
+            Expression::Multiply(a, b) => {
+                self.collect(a.take(), exponent);
+                self.collect(b.take(), exponent);
+            }
+            Expression::Divide(a, b) => {
+                self.collect(a.take(), exponent);
+                self.collect(b.take(), -exponent);
+            }
+            Expression::Negate(e) => {
+                self.coefficient = -self.coefficient;
+                self.collect(e.take(), exponent);
+            }
+            Expression::Grouping(e) => self.collect(e.take(), exponent),
+            // A matrix is only a factor of its positive integer powers from `A ^ 2`
+            // on: `A ^ 0` and `A ^ -1` are matrices of their own.
+            Expression::Power(base, e)
+                if as_number(e).is_some_and(|e| {
+                    self.is_scalar_factor(base) || (e.fract() == 0.0 && e >= 2.0)
+                }) =>
+            {
+                self.push(base.take(), exponent * as_number(e).unwrap())
+            }
+            _ => self.push(expr, exponent),
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        self.factors
+            .iter()
+            .all(|(base, e)| *e > 0.0 && self.is_scalar_factor(base))
+    }
+
+    // Whether both products have the same factors: in the same order for the
+    // factors that may be matrices, in any order for the rest.
+    fn is_like(&self, other: &Product) -> bool {
+        let ordered = |p: &Product| {
+            p.factors
+                .iter()
+                .filter(|(base, e)| *e > 0.0 && !p.is_scalar_factor(base))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let commuting = |p: &Product| {
+            p.factors
+                .iter()
+                .filter(|(base, e)| *e < 0.0 || (*e > 0.0 && p.is_scalar_factor(base)))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let (mine, theirs) = (commuting(self), commuting(other));
+        ordered(self) == ordered(other)
+            && mine.len() == theirs.len()
+            && mine.iter().all(|factor| theirs.contains(factor))
+    }
+
+    // `u ^ a * u ^ b` is `u ^ (a + b)` for integer exponents, but for other real
+    // ones only when `u` is not negative: `x ^ 0.5 * x ^ 0.5` is NaN for x = -4.
+    fn push(&mut self, base: Expression, exponent: f64) {
+        let same_side = |e: f64| (e > 0.0) == (exponent > 0.0);
+        let mergeable =
+            |e: f64| (e.fract() == 0.0 && exponent.fract() == 0.0) || is_non_negative(&base);
+        let matrix_variables = self.matrix_variables;
+        let existing = if exponent < 0.0 || is_scalar(&base, matrix_variables) {
+            self.factors
+                .iter_mut()
+                .find(|(b, e)| same_side(*e) && *b == base)
+        } else {
+            // Only the closest factor that may be a matrix is a neighbour.
+            self.factors
+                .iter_mut()
+                .rfind(|(b, e)| *e > 0.0 && !is_scalar(b, matrix_variables))
+                .filter(|(b, _)| *b == base)
+        }
+        .filter(|(_, e)| mergeable(*e));
+        match existing {
+            Some((_, e)) => *e += exponent,
+            None => self.factors.push((base, exponent)),
+        }
+    }
+
+    fn build(self) -> Expression {
+        if self.coefficient == 0.0 && self.is_scalar() {
+            return number(0.0);
+        }
+
+        let mut numerator = (self.coefficient.abs() != 1.0).then(|| number(self.coefficient));
+        let mut denominator: Option<Expression> = None;
+        for (base, exponent) in self.factors {
+            let (side, exponent) = match exponent {
+                e if e > 0.0 => (&mut numerator, e),
+                e if e < 0.0 => (&mut denominator, -e),
+                _ => continue,
+            };
+            let factor = if exponent == 1.0 {
+                base
+            } else {
+                power(base, number(exponent))
+            };
+            *side = Some(match side.take() {
+                None => factor,
+                Some(product) => multiply(product, factor),
+            });
+        }
+
+        let result = match (numerator, denominator) {
+            (None, None) => number(1.0),
+            (Some(n), None) => n,
+            (None, Some(d)) => divide(number(1.0), d),
+            (Some(n), Some(d)) => divide(n, d),
+        };
+        if self.coefficient == -1.0 {
+            negate(result)
+        } else {
+            result
+        }
+    }
+}
+
+impl Simplifier {
+    // `u ^ 0` is 1 and `u ^ 1` is `u` for any number `u`.
+    fn power(&self, base: Expression, exponent: Expression) -> Expression {
+        if is_scalar(&base, self.matrix_variables) {
+            power_numbers(base, exponent)
+        } else {
+            power(base, exponent)
+        }
+    }
+}
+
+impl ExpressionTransformer for Simplifier {
+    fn transform_add(&mut self, left: Expression, right: Expression) -> Expression {
+        let mut sum = Sum::new(self.matrix_variables);
+        sum.collect(self.transform_expression(left), 1.0);
+        sum.collect(self.transform_expression(right), 1.0);
+        sum.build()
+    }
+
+    fn transform_subtract(&mut self, left: Expression, right: Expression) -> Expression {
+        let mut sum = Sum::new(self.matrix_variables);
+        sum.collect(self.transform_expression(left), 1.0);
+        sum.collect(self.transform_expression(right), -1.0);
+        sum.build()
+    }
+
+    fn transform_multiply(&mut self, left: Expression, right: Expression) -> Expression {
+        let mut product = Product::new(self.matrix_variables);
+        product.collect(self.transform_expression(left), 1.0);
+        product.collect(self.transform_expression(right), 1.0);
+        product.build()
+    }
+
+    fn transform_divide(&mut self, left: Expression, right: Expression) -> Expression {
+        let mut product = Product::new(self.matrix_variables);
+        product.collect(self.transform_expression(left), 1.0);
+        product.collect(self.transform_expression(right), -1.0);
+        product.build()
+    }
+
+    fn transform_power(&mut self, base: Expression, exponent: Expression) -> Expression {
+        let mut base = self.transform_expression(base);
+        let exponent = self.transform_expression(exponent);
+
+        match (&mut base, as_number(&exponent)) {
+            (Expression::Number(b), Some(e)) if b.powf(e).is_finite() => number(b.powf(e)),
+            (Expression::Number(1.0), _) => number(1.0),
+            // (u ^ m) ^ n = u ^ (m * n) holds for integers m and n, and for any real
+            // ones when u is not negative: (x ^ 0.5) ^ 2 is NaN for x = -4.
+            (Expression::Power(inner, m), Some(n))
+                if as_number(m).is_some_and(|m| {
+                    (m.fract() == 0.0 && n.fract() == 0.0) || is_non_negative(inner)
+                }) =>
+            {
+                let m = as_number(m).unwrap();
+                self.power(inner.take(), number(m * n))
+            }
+            _ => self.power(base, exponent),
+        }
+    }
+
+    fn transform_negate(&mut self, expr: Expression) -> Expression {
+        let mut sum = Sum::new(self.matrix_variables);
+        sum.collect(self.transform_expression(expr), -1.0);
+        sum.build()
+    }
+
+    fn transform_grouping(&mut self, expr: Expression) -> Expression {
+        self.transform_expression(expr)
+    }
+
+    // Calls on constant arguments are folded only when the result is an integer,
+    // so `sqrt(16)` becomes `4` while `ln(2)` keeps its exact symbolic form.
+    fn transform_call(&mut self, name: String, args: Vec<Expression>) -> Expression {
+        let args = self.transform_all(args);
+
+        let constants: Option<Vec<Value>> = args
+            .iter()
+            .map(|arg| as_number(arg).map(Value::Number))
+            .collect();
+        if let Some(Ok(Value::Number(n))) = constants.map(|values| functions::call(&name, &values))
+            && n.is_finite()
+            && n.fract() == 0.0
+        {
+            return number(n);
+        }
+        Expression::Call(name, args)
+    }
+}
+
+#[cfg(test)]
+mod simplifier_tests {
+    use super::*;
+    use crate::parsemath::parser::Parser;
+    use crate::parsemath::visitors::{Evaluator, ExpressionVisitor, PrettyPrinterVisitor};
+    use rstest::rstest;
+
+    fn simplify(input: &str) -> String {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        let mut printer = PrettyPrinterVisitor::new();
+        printer
+            .visit_expression(&Simplifier::simplify(&ast))
+            .unwrap()
+    }
+
+    fn simplify_matrices(expr: &Expression) -> Expression {
+        Simplifier::new()
+            .with_matrix_variables(true)
+            .transform_expression(expr.clone())
+    }
+
+    #[rstest]
+    #[case("2 * (3 + 4)", "14")]
+    #[case("((x))", "x")]
+    #[case("x * 1", "x")]
+    #[case("x + 0", "x")]
+    #[case("x * 0", "0")]
+    #[case("sin(x) + 0", "sin(x)")]
+    #[case("0 * x + y", "y")]
+    #[case("0 * sin(x) + y", "y")]
+    #[case("--x", "x")]
+    #[case("x - x", "0")]
+    #[case("sin(x) - sin(x)", "0")]
+    #[case("0 / x", "0 / x")]
+    #[case("x ^ 0", "1")]
+    #[case("x ^ 1", "x")]
+    #[case("sin(x) ^ 0", "1")]
+    #[case("x ^ 0.5 * x ^ 0.5", "x ^ 0.5 * x ^ 0.5")]
+    #[case("sin(x) ^ 0.5 * sin(x) ^ 0.5", "sin(x) ^ 0.5 * sin(x) ^ 0.5")]
+    #[case("abs(x) ^ 0.5 * abs(x) ^ 0.5", "abs(x)")]
+    #[case("(x ^ 0.5) ^ 2", "(x ^ 0.5) ^ 2")]
+    #[case("(x ^ 3) ^ 2", "x ^ 6")]
+    #[case("(x + 1) * 2", "2 * (x + 1)")]
+    #[case("x + 2 * x - y + 3", "3 * x - y + 3")]
+    #[case("2 * x * 3 * y", "6 * x * y")]
+    #[case("x * x ^ 2 * x", "x ^ 4")]
+    #[case("(x ^ 2) ^ 3", "x ^ 6")]
+    #[case("x ^ 3 / x ^ 2 / x", "x ^ 3 / x ^ 3")]
+    #[case("x / x", "x / x")]
+    #[case("2 / x * x ^ 3", "2 * x ^ 3 / x")]
+    #[case("-(x - y)", "-x + y")]
+    #[case("6 * x * y - x * y * 5", "x * y")]
+    #[case("6 * x * y - y * x * 5", "x * y")]
+    #[case("6 * sin(x) * det(y) - det(y) * sin(x) * 5", "sin(x) * det(y)")]
+    #[case("sin(x) * A * 2 * sin(x)", "2 * sin(x) ^ 2 * A")]
+    #[case("A * B * A", "A ^ 2 * B")]
+    #[case("0 * A", "0")]
+    #[case("0 * sin(x)", "0")]
+    #[case("-3 * x", "-3 * x")]
+    #[case("sqrt(16) + ln(2)", "ln(2) + 4")]
+    #[case("x = 1 + 2", "x = 3")]
+    fn simplify_expression(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(simplify(input), expected);
+    }
+
+    #[rstest]
+    #[case("x + 0", "x + 0")]
+    #[case("0 * x + y", "0 * x + y")]
+    #[case("0 * sin(x) + y", "y + 0")]
+    #[case("x - x", "0 * x")]
+    #[case("x ^ 0", "x ^ 0")]
+    #[case("sin(x) ^ 0", "1")]
+    #[case("x ^ 0.5 * x ^ 0.5", "(x ^ 0.5) ^ 2")]
+    #[case("6 * x * y - y * x * 5", "6 * x * y - 5 * y * x")]
+    #[case("sin(x) * A * 2 * sin(x)", "2 * sin(x) ^ 2 * A")]
+    #[case("A * B * A", "A * B * A")]
+    #[case("A * A * B", "A ^ 2 * B")]
+    #[case("0 * A", "0 * A")]
+    fn simplify_with_matrix_variables(#[case] input: &str, #[case] expected: &str) {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        let mut printer = PrettyPrinterVisitor::new();
+        assert_eq!(
+            printer.visit_expression(&simplify_matrices(&ast)).unwrap(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case("[[1, 2], [3, 4]] * [[0, 1], [1, 0]] * [[1, 2], [3, 4]]")]
+    #[case("[[1, 2], [3, 4]] * [[1, 2], [3, 4]] * [[0, 1], [1, 0]] - 2 * [[1, 2], [3, 4]]")]
+    #[case("0 * [[1, 2], [3, 4]] + [[1, 0], [0, 1]]")]
+    fn matrix_products_evaluate_the_same(#[case] input: &str) {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        let simplified = Simplifier::simplify(&ast);
+        assert_eq!(
+            Evaluator::new().visit_expression(&simplified),
+            Evaluator::new().visit_expression(&ast)
+        );
+    }
+
+    #[rstest]
+    #[case("A - A")]
+    #[case("A ^ 0")]
+    #[case("A + 0")]
+    #[case("0 - A")]
+    #[case("0 / A")]
+    #[case("A ^ 1")]
+    #[case("A * A ^ -1")]
+    #[case("A ^ 2 * A - A ^ 3")]
+    fn matrix_variables_evaluate_the_same(#[case] input: &str) {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        let simplified = simplify_matrices(&ast);
+        for matrix in [
+            "[[1, 2], [3, 4]]",
+            "[[1, 2, 3], [4, 5, 6]]",
+            "[[1, 1], [1, 1]]",
+        ] {
+            let value = Parser::new(matrix).unwrap().parse().unwrap();
+            let value = Evaluator::new().visit_expression(&value).unwrap();
+            let evaluate = |expr: &Expression| {
+                Evaluator::new()
+                    .with_variable("A", value.clone())
+                    .visit_expression(expr)
+            };
+            // Errors may be worded differently, e.g. `0 - A` fails as `-A + 0`.
+            match (evaluate(&simplified), evaluate(&ast)) {
+                (Err(actual), Err(expected)) => assert_eq!(
+                    std::mem::discriminant(&actual),
+                    std::mem::discriminant(&expected),
+                    "{} with A = {}",
+                    input,
+                    matrix
+                ),
+                (actual, expected) => {
+                    assert_eq!(actual, expected, "{} with A = {}", input, matrix)
+                }
+            }
+        }
+    }
+
+    #[rstest]
+    #[case("0 / x", 0.0)]
+    #[case("x ^ 0.5 * x ^ 0.5", -4.0)]
+    #[case("(x ^ 0.5) ^ 2", -4.0)]
+    #[case("x ^ 2 * x ^ -0.5", -4.0)]
+    fn numbers_evaluate_the_same(#[case] input: &str, #[case] x: f64) {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        let simplified = Simplifier::simplify(&ast);
+        let evaluate = |expr: &Expression| {
+            Evaluator::new()
+                .with_variable("x", x)
+                .visit_expression(expr)
+                .map(|value| value.to_string())
+        };
+        assert_eq!(evaluate(&simplified), evaluate(&ast));
+    }
+
+    #[rstest]
+    fn division_by_zero_is_left_for_the_evaluator() {
+        assert_eq!(simplify("1 / 0"), "1 / 0");
+    }
+
+    #[rstest]
+    #[case("x ^ 2 * sin(x) / x - 3 * x + x * 2")]
+    #[case("(x + 1) ^ 2 - (x + 1) * (x + 1) + 7 / (x - 2)")]
+    #[case("-(2 * x) ^ 2 / (4 * x) + exp(x - x)")]
+    fn simplified_expression_evaluates_the_same(#[case] input: &str) {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        let simplified = Simplifier::simplify(&ast);
+
+        for x in [0.5, 1.5, 3.0] {
+            let expected = Evaluator::new()
+                .with_variable("x", x)
+                .visit_expression(&ast);
+            let actual = Evaluator::new()
+                .with_variable("x", x)
+                .visit_expression(&simplified);
+            let (expected, actual) = (expected.unwrap(), actual.unwrap());
+            assert!(
+                (expected.as_number().unwrap() - actual.as_number().unwrap()).abs() < 1e-9,
+                "{} != {}",
+                expected,
+                actual
+            );
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use super::constructors::group_below;
+use super::parser::Expression;
+use super::transform::{ExpressionTransformer, walk};
+use super::visitors::{Evaluator, ExpressionVisitor, solves_for};
+use crate::calcmath::value::Value;
+
+// Replaces every free occurrence of a bound variable with its expression.
+pub fn substitute(expr: &Expression, bindings: &HashMap<String, Expression>) -> Expression {
+    Substitution::new(bindings, false).transform_expression(expr.clone())
+}
+
+// Binds variables to numbers and evaluates every sub-tree that no longer depends
+// on an unbound variable, leaving a residual expression in the remaining ones:
+// `a * x + b` with `{a: 2, b: 3}` gives `2 * x + 3`. Sub-trees whose evaluation
+// fails (e.g. a division by zero) are kept so the error surfaces on final evaluation.
+pub fn partially_evaluate(expr: &Expression, bindings: &HashMap<String, f64>) -> Expression {
+    let bindings = bindings
+        .iter()
+        .map(|(name, value)| (name.clone(), Expression::Number(*value)))
+        .collect();
+    Substitution::new(&bindings, true).transform_expression(expr.clone())
+}
+
+struct Substitution<'a> {
+    bindings: &'a HashMap<String, Expression>,
+    // Variables bound by an enclosing `solve`, `integrate` or `diff` call.
+    shadowed: Vec<String>,
+    fold_constants: bool,
+}
+
+impl<'a> Substitution<'a> {
+    fn new(bindings: &'a HashMap<String, Expression>, fold_constants: bool) -> Self {
+        Substitution {
+            bindings,
+            shadowed: Vec::new(),
+            fold_constants,
+        }
+    }
+
+    fn is_bound(&self, name: &str) -> bool {
+        self.bindings.contains_key(name) && !self.shadowed.iter().any(|local| local == name)
+    }
+
+    // Evaluates `expr` if all of its operands are constants.
+    fn fold(expr: Expression) -> Expression {
+        let closed = match &expr {
+            Expression::Number(_) | Expression::Variable(_) | Expression::Error => false,
+            Expression::Add(a, b)
+            | Expression::Subtract(a, b)
+            | Expression::Multiply(a, b)
+            | Expression::Divide(a, b)
+            | Expression::Power(a, b)
+            | Expression::Equation(a, b)
+            | Expression::Range(a, b) => is_constant(a) && is_constant(b),
+            Expression::Negate(e) | Expression::Grouping(e) => is_constant(e),
+            Expression::List(elements) | Expression::Call(_, elements) => {
+                elements.iter().all(is_constant)
+            }
+            Expression::Index(target, indices) => {
+                is_constant(target) && indices.iter().all(is_constant)
+            }
+        };
+        if !closed {
+            return expr;
+        }
+
+        match Evaluator::new().visit_expression(&expr) {
+            // A negative result keeps its parentheses so `(-2) ^ x` prints back correctly.
+            Ok(Value::Number(n)) if n < 0.0 && matches!(expr, Expression::Grouping(_)) => {
+                Expression::Grouping(Box::new(Expression::Number(n)))
+            }
+            Ok(Value::Number(n)) => Expression::Number(n),
+            _ => expr,
+        }
+    }
+}
+
+fn is_constant(expr: &Expression) -> bool {
+    match expr {
+        Expression::Number(_) => true,
+        Expression::Grouping(e) => is_constant(e),
+        _ => false,
+    }
+}
+
+impl ExpressionTransformer for Substitution<'_> {
+    fn transform_expression(&mut self, expr: Expression) -> Expression {
+        let expr = walk(self, expr);
+        if self.fold_constants {
+            Self::fold(expr)
+        } else {
+            expr
+        }
+    }
+
+    fn transform_variable(&mut self, name: String) -> Expression {
+        match self.bindings.get(&name) {
+            Some(value) if !self.shadowed.contains(&name) => *group_below(value.clone(), 5),
+            _ => Expression::Variable(name),
+        }
+    }
+
+    // The second argument of `solve`, `integrate` and `diff` names a variable local
+    // to the first, so it is neither replaced itself nor inside the first argument.
+    // The remaining arguments (bounds, starting point, search range) are evaluated
+    // outside the call and substituted as usual.
+    fn transform_call(&mut self, name: String, args: Vec<Expression>) -> Expression {
+        let local = match (name.as_str(), &args[..]) {
+            ("solve", [function, Expression::Variable(local)])
+                if !solves_for(function, local, self.is_bound(local)) =>
+            {
+                return Expression::Call(name, self.transform_all(args));
+            }
+            ("solve" | "integrate" | "diff", [_, Expression::Variable(local), ..]) => local.clone(),
+            _ => return Expression::Call(name, self.transform_all(args)),
+        };
+
+        let args = args
+            .into_iter()
+            .enumerate()
+            .map(|(i, arg)| match i {
+                0 => {
+                    self.shadowed.push(local.clone());
+                    let arg = self.transform_expression(arg);
+                    self.shadowed.pop();
+                    arg
+                }
+                1 => arg,
+                _ => self.transform_expression(arg),
+            })
+            .collect();
+        Expression::Call(name, args)
+    }
+}
+
+#[cfg(test)]
+mod substitution_tests {
+    use super::*;
+    use crate::parsemath::parser::Parser;
+    use crate::parsemath::visitors::PrettyPrinterVisitor;
+    use rstest::rstest;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input).unwrap().parse().unwrap()
+    }
+
+    fn print(expr: &Expression) -> String {
+        let mut printer = PrettyPrinterVisitor::new();
+        printer.visit_expression(expr).unwrap()
+    }
+
+    fn partial(input: &str, bindings: &[(&str, f64)]) -> String {
+        let bindings = bindings
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect();
+        print(&partially_evaluate(&parse(input), &bindings))
+    }
+
+    #[rstest]
+    #[case("a * x + b", &[("a", 2.0), ("b", 3.0)], "2 * x + 3")]
+    #[case("(a + b) * x ^ (a - 1)", &[("a", 2.0), ("b", 3.0)], "5 * x ^ 1")]
+    #[case("sqrt(a) * max(x, a)", &[("a", 16.0)], "4 * max(x, 16)")]
+    #[case("(a) ^ x", &[("a", -2.0)], "(-2) ^ x")]
+    #[case("x / a", &[("a", 0.0)], "x / 0")]
+    #[case("1 / a + x", &[("a", 0.0)], "1 / 0 + x")]
+    #[case("a * x", &[("x", 1.0), ("a", 4.0)], "4")]
+    fn partial_evaluation_leaves_residual(
+        #[case] input: &str,
+        #[case] bindings: &[(&str, f64)],
+        #[case] expected: &str,
+    ) {
+        assert_eq!(partial(input, bindings), expected);
+    }
+
+    #[rstest]
+    fn local_variables_are_not_substituted() {
+        assert_eq!(
+            partial("integrate(t * k, t, 0, x) + t", &[("t", 5.0), ("k", 2.0)]),
+            "integrate(t * 2, t, 0, x) + 5"
+        );
+    }
+
+    // Only the first argument sees the local variable; the others are evaluated
+    // in the enclosing scope.
+    #[rstest]
+    #[case("diff(x ^ 2, x, x)", &[("x", 3.0)], "diff(x ^ 2, x, 3)")]
+    #[case("integrate(t, t, 0, t)", &[("t", 2.0)], "integrate(t, t, 0, 2)")]
+    #[case("solve(x ^ 2 = x0, x, x0)", &[("x", 5.0), ("x0", 4.0)], "solve(x ^ 2 = 4, x, 4)")]
+    #[case("diff(x ^ 2 * k, x, k)", &[("k", 2.0)], "diff(x ^ 2 * 2, x, 2)")]
+    fn bounds_are_substituted_in_the_outer_scope(
+        #[case] input: &str,
+        #[case] bindings: &[(&str, f64)],
+        #[case] expected: &str,
+    ) {
+        let ast = parse(input);
+        let residual = partial(input, bindings);
+        assert_eq!(residual, expected);
+
+        let mut direct = Evaluator::new();
+        for (name, value) in bindings {
+            direct = direct.with_variable(name, *value);
+        }
+        let direct = direct.visit_expression(&ast).unwrap().as_number().unwrap();
+        let residual = Evaluator::new()
+            .visit_expression(&parse(&residual))
+            .unwrap()
+            .as_number()
+            .unwrap();
+        assert!(
+            (direct - residual).abs() < 1e-6,
+            "{} != {}",
+            direct,
+            residual
+        );
+    }
+
+    #[rstest]
+    #[case("solve(A, b)", "solve(A, [2, 8])")]
+    #[case("solve(b - 2, b)", "solve(b - 2, b)")]
+    #[case("solve(A = 1, b)", "solve(A = 1, b)")]
+    fn substitute_into_linear_solve(#[case] input: &str, #[case] expected: &str) {
+        let bindings = HashMap::from([("b".to_string(), parse("[2, 8]"))]);
+        assert_eq!(print(&substitute(&parse(input), &bindings)), expected);
+    }
+
+    #[rstest]
+    fn substitute_expressions_with_parentheses() {
+        let bindings = HashMap::from([("x".to_string(), parse("y + 1"))]);
+        let result = substitute(&parse("2 * x ^ 2"), &bindings);
+        assert_eq!(print(&result), "2 * (y + 1) ^ 2");
+    }
+
+    #[rstest]
+    fn residual_evaluates_like_the_original() {
+        let ast = parse("price * qty * (1 - discount) + fee / qty");
+        let bindings = HashMap::from([("discount".to_string(), 0.25), ("fee".to_string(), 12.0)]);
+        let residual = partially_evaluate(&ast, &bindings);
+        assert_eq!(print(&residual), "price * qty * 0.75 + 12 / qty");
+
+        for (price, qty) in [(10.0, 3.0), (2.5, 8.0)] {
+            let mut full = Evaluator::new()
+                .with_variable("price", price)
+                .with_variable("qty", qty)
+                .with_variable("discount", 0.25)
+                .with_variable("fee", 12.0);
+            let mut partial = Evaluator::new()
+                .with_variable("price", price)
+                .with_variable("qty", qty);
+            assert_eq!(
+                full.visit_expression(&ast).unwrap(),
+                partial.visit_expression(&residual).unwrap()
+            );
+        }
+    }
+}
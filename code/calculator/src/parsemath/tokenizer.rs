@@ -3,14 +3,42 @@ use std::str::Chars;
 use thiserror::Error;
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Token {
     Plus,
     Minus,
     Star,
     Slash,
+    Caret,
+    Equal,
+    DotDot,
     LeftParen,
     RightParen,
+    LeftBracket,
+    RightBracket,
+    Comma,
     Number(f64),
+    Identifier(String),
+    // A symbol registered with `Tokenizer::with_operators`.
+    Operator(String),
+}
+
+impl Token {
+    // The operator symbol this token stands for.
+    pub fn symbol(&self) -> Option<&str> {
+        match self {
+            Token::Plus => Some("+"),
+            Token::Minus => Some("-"),
+            Token::Star => Some("*"),
+            Token::Slash => Some("/"),
+            Token::Caret => Some("^"),
+            Token::Equal => Some("="),
+            Token::DotDot => Some(".."),
+            Token::Operator(symbol) => Some(symbol),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Error, Debug, PartialEq)]
@@ -23,33 +51,110 @@ pub enum TokenizingError {
 
 pub struct Tokenizer<'a> {
     expr: Peekable<Chars<'a>>,
+    // Longest first, so that `**` wins over `*`.
+    operators: Vec<String>,
+    // Characters consumed so far, and where the latest token started.
+    offset: usize,
+    start: usize,
 }
 
 impl<'a> Tokenizer<'a> {
     pub fn new(expr: &str) -> Tokenizer<'_> {
         Tokenizer {
             expr: expr.chars().peekable(),
+            operators: Vec::new(),
+            offset: 0,
+            start: 0,
+        }
+    }
+
+    // Character offset of the token (or invalid input) most recently returned
+    // by `next`, counting from 0.
+    pub fn position(&self) -> usize {
+        self.start
+    }
+
+    // Character offset just past the input read so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    // Additional operator symbols, tokenized as `Token::Operator`. Built-in
+    // symbols keep their own tokens. A symbol ending in a letter or digit, such
+    // as `mod`, only matches when no identifier character follows it.
+    pub fn with_operators<'s>(mut self, symbols: impl IntoIterator<Item = &'s str>) -> Self {
+        for symbol in symbols {
+            let builtin = matches!(symbol, "+" | "-" | "*" | "/" | "^" | "=" | "..");
+            if !builtin && !symbol.is_empty() && !self.operators.iter().any(|s| s == symbol) {
+                self.operators.push(symbol.to_string());
+            }
         }
+        self.operators
+            .sort_by_key(|symbol| std::cmp::Reverse(symbol.chars().count()));
+        self
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.expr.next()?;
+        self.offset += 1;
+        Some(c)
     }
 
     fn skip_whitespace(&mut self) {
         while let Some(' ') = self.expr.peek() {
-            self.expr.next();
+            self.advance();
         }
     }
 
     fn tokenize_number(&mut self, c: char) -> Result<Token, TokenizingError> {
         let mut number_str = c.to_string();
         while let Some('0'..='9') | Some('.') = self.expr.peek() {
-            number_str.push(self.expr.next().unwrap());
+            if self.at_range_operator() {
+                break;
+            }
+            number_str.push(self.advance().unwrap());
         }
 
-        let number = number_str
+        number_str
             .parse::<f64>()
-            .map(|n| Token::Number(n))
-            .map_err(|_| TokenizingError::InvalidNumber);
+            .map(Token::Number)
+            .map_err(|_| TokenizingError::InvalidNumber)
+    }
+
+    // Exactly two dots form the `..` range operator, so `0..5` splits into
+    // `0`, `..` and `5` while `1...` is still a malformed number.
+    fn at_range_operator(&self) -> bool {
+        let mut lookahead = self.expr.clone();
+        lookahead.next() == Some('.') && lookahead.next() == Some('.') && lookahead.next() != Some('.')
+    }
+
+    fn tokenize_operator(&mut self) -> Option<Token> {
+        let symbol = self.operators.iter().find(|symbol| {
+            let mut lookahead = self.expr.clone();
+            let matches = symbol.chars().all(|c| lookahead.next() == Some(c));
+            let ends_word = symbol.ends_with(|c: char| c.is_ascii_alphanumeric());
+            let continues_word =
+                matches!(lookahead.next(), Some(c) if c.is_ascii_alphanumeric() || c == '_');
+            matches && !(ends_word && continues_word)
+        })?;
+        let token = Token::Operator(symbol.clone());
+        for _ in 0..symbol.chars().count() {
+            self.advance();
+        }
+        Some(token)
+    }
+
+    fn tokenize_identifier(&mut self, c: char) -> Token {
+        let mut identifier = c.to_string();
+        while let Some(&c) = self.expr.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_') {
+                break;
+            }
+            identifier.push(c);
+            self.advance();
+        }
 
-        number
+        Token::Identifier(identifier)
     }
 }
 
@@ -58,16 +163,31 @@ impl<'a> Iterator for Tokenizer<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         self.skip_whitespace();
+        self.start = self.offset;
+
+        if let Some(token) = self.tokenize_operator() {
+            return Some(Ok(token));
+        }
 
-        if let Some(c) = self.expr.next() {
+        if let Some(c) = self.advance() {
             match c {
                 '+' => Some(Ok(Token::Plus)),
                 '-' => Some(Ok(Token::Minus)),
                 '*' => Some(Ok(Token::Star)),
                 '/' => Some(Ok(Token::Slash)),
+                '^' => Some(Ok(Token::Caret)),
+                '=' => Some(Ok(Token::Equal)),
+                '.' if self.expr.peek() == Some(&'.') => {
+                    self.advance();
+                    Some(Ok(Token::DotDot))
+                }
                 '(' => Some(Ok(Token::LeftParen)),
                 ')' => Some(Ok(Token::RightParen)),
+                '[' => Some(Ok(Token::LeftBracket)),
+                ']' => Some(Ok(Token::RightBracket)),
+                ',' => Some(Ok(Token::Comma)),
                 '0'..='9' => Some(self.tokenize_number(c)),
+                'a'..='z' | 'A'..='Z' | '_' => Some(Ok(self.tokenize_identifier(c))),
                 invalid => Some(Err(TokenizingError::InvalidCharacter(invalid))),
             }
         } else {
@@ -86,6 +206,9 @@ mod tokenizer_tests {
     #[case("-", Token::Minus)]
     #[case("/", Token::Slash)]
     #[case("*", Token::Star)]
+    #[case("^", Token::Caret)]
+    #[case("=", Token::Equal)]
+    #[case("..", Token::DotDot)]
     fn tokenize_operator(#[case] expr: &str, #[case] expected_token: Token) {
         let mut tokenizer = Tokenizer::new(expr);
 
@@ -97,6 +220,9 @@ mod tokenizer_tests {
     #[rstest]
     #[case("(", vec![Token::LeftParen])]
     #[case(")", vec![Token::RightParen])]
+    #[case("[", vec![Token::LeftBracket])]
+    #[case("]", vec![Token::RightBracket])]
+    #[case(",", vec![Token::Comma])]
     fn tokenizer_parens(#[case] expr: &str, #[case] expected_tokens: Vec<Token>) {
         let tokenizer = Tokenizer::new(expr);
 
@@ -110,6 +236,7 @@ mod tokenizer_tests {
     #[case("3", vec![Token::Number(3.0)])]
     #[case("3.14", vec![Token::Number(3.14)])]
     #[case("93.14", vec![Token::Number(93.14)])]
+    #[allow(clippy::approx_constant)]
     fn tokenizer_numbers(#[case] expr: &str, #[case] expected_tokens: Vec<Token>) {
         let tokenizer = Tokenizer::new(expr);
 
@@ -120,7 +247,7 @@ mod tokenizer_tests {
     }
 
     #[rstest]
-    #[case("1a", 'a')]
+    #[case("1$", '$')]
     #[case("2#", '#')]
     fn tokenizer_invalid_characters(#[case] expr: &str, #[case] expected: char) {
         let tokenizer = Tokenizer::new(expr);
@@ -132,6 +259,19 @@ mod tokenizer_tests {
         assert_eq!(result, TokenizingError::InvalidCharacter(expected));
     }
 
+    #[rstest]
+    #[case("det", vec![Token::Identifier("det".to_string())])]
+    #[case("x_1", vec![Token::Identifier("x_1".to_string())])]
+    #[case("2a", vec![Token::Number(2.0), Token::Identifier("a".to_string())])]
+    fn tokenizer_identifiers(#[case] expr: &str, #[case] expected_tokens: Vec<Token>) {
+        let tokenizer = Tokenizer::new(expr);
+
+        let tokens: Vec<Token> = tokenizer
+            .collect::<Result<Vec<Token>, TokenizingError>>()
+            .unwrap();
+        assert_eq!(tokens, expected_tokens);
+    }
+
     #[rstest]
     #[case("1.324.3")]
     #[case("1....")]
@@ -151,6 +291,10 @@ mod tokenizer_tests {
     #[rstest]
     #[case("1+2", vec![Token::Number(1.0), Token::Plus, Token::Number(2.0)])]
     #[case("1 + 2", vec![Token::Number(1.0), Token::Plus, Token::Number(2.0)])]
+    #[case("0..5", vec![Token::Number(0.0), Token::DotDot, Token::Number(5.0)])]
+    #[case("0.5..1.5", vec![Token::Number(0.5), Token::DotDot, Token::Number(1.5)])]
+    #[case("x^2 = 2", vec![Token::Identifier("x".to_string()), Token::Caret, Token::Number(2.0), Token::Equal, Token::Number(2.0)])]
+    #[case("[1, 2]", vec![Token::LeftBracket, Token::Number(1.0), Token::Comma, Token::Number(2.0), Token::RightBracket])]
     fn tokenizer_expressions(#[case] expr: &str, #[case] expected_tokens: Vec<Token>) {
         let tokenizer = Tokenizer::new(expr);
 
@@ -159,4 +303,31 @@ mod tokenizer_tests {
             .unwrap();
         assert_eq!(tokens, expected_tokens);
     }
+
+    #[rstest]
+    #[case("a ± b", vec![Token::Identifier("a".to_string()), Token::Operator("±".to_string()), Token::Identifier("b".to_string())])]
+    #[case("1**2*3", vec![Token::Number(1.0), Token::Operator("**".to_string()), Token::Number(2.0), Token::Star, Token::Number(3.0)])]
+    #[case("7 mod 2", vec![Token::Number(7.0), Token::Operator("mod".to_string()), Token::Number(2.0)])]
+    #[case("model", vec![Token::Identifier("model".to_string())])]
+    #[case("1+-2", vec![Token::Number(1.0), Token::Plus, Token::Minus, Token::Number(2.0)])]
+    fn tokenizer_custom_operators(#[case] expr: &str, #[case] expected_tokens: Vec<Token>) {
+        let tokenizer = Tokenizer::new(expr).with_operators(["±", "*", "**", "mod", "+"]);
+
+        let tokens = tokenizer
+            .collect::<Result<Vec<Token>, TokenizingError>>()
+            .unwrap();
+        assert_eq!(tokens, expected_tokens);
+    }
+
+    #[rstest]
+    fn tokenizer_positions() {
+        let mut tokenizer = Tokenizer::new("  ab + √2 $");
+        let positions: Vec<usize> = std::iter::from_fn(|| {
+            let _token = tokenizer.next()?;
+            Some(tokenizer.position())
+        })
+        .collect();
+        assert_eq!(positions, vec![2, 5, 7, 8, 10]);
+        assert_eq!(tokenizer.offset(), 11);
+    }
 }
@@ -0,0 +1,213 @@
+use super::parser::Expression;
+
+// Rewriting counterpart of `ExpressionVisitor`: takes a tree by value and returns
+// the rewritten tree. Every method defaults to rebuilding its node from transformed
+// children, so a pass only overrides the nodes it actually changes.
+pub trait ExpressionTransformer {
+    fn transform_number(&mut self, value: f64) -> Expression {
+        Expression::Number(value)
+    }
+
+    fn transform_add(&mut self, left: Expression, right: Expression) -> Expression {
+        let (left, right) = self.transform_pair(left, right);
+        Expression::Add(left, right)
+    }
+
+    fn transform_subtract(&mut self, left: Expression, right: Expression) -> Expression {
+        let (left, right) = self.transform_pair(left, right);
+        Expression::Subtract(left, right)
+    }
+
+    fn transform_multiply(&mut self, left: Expression, right: Expression) -> Expression {
+        let (left, right) = self.transform_pair(left, right);
+        Expression::Multiply(left, right)
+    }
+
+    fn transform_divide(&mut self, left: Expression, right: Expression) -> Expression {
+        let (left, right) = self.transform_pair(left, right);
+        Expression::Divide(left, right)
+    }
+
+    fn transform_power(&mut self, base: Expression, exponent: Expression) -> Expression {
+        let (base, exponent) = self.transform_pair(base, exponent);
+        Expression::Power(base, exponent)
+    }
+
+    fn transform_negate(&mut self, expr: Expression) -> Expression {
+        Expression::Negate(Box::new(self.transform_expression(expr)))
+    }
+
+    fn transform_grouping(&mut self, expr: Expression) -> Expression {
+        Expression::Grouping(Box::new(self.transform_expression(expr)))
+    }
+
+    fn transform_variable(&mut self, name: String) -> Expression {
+        Expression::Variable(name)
+    }
+
+    fn transform_equation(&mut self, left: Expression, right: Expression) -> Expression {
+        let (left, right) = self.transform_pair(left, right);
+        Expression::Equation(left, right)
+    }
+
+    fn transform_range(&mut self, from: Expression, to: Expression) -> Expression {
+        let (from, to) = self.transform_pair(from, to);
+        Expression::Range(from, to)
+    }
+
+    fn transform_list(&mut self, elements: Vec<Expression>) -> Expression {
+        Expression::List(self.transform_all(elements))
+    }
+
+    fn transform_call(&mut self, name: String, args: Vec<Expression>) -> Expression {
+        Expression::Call(name, self.transform_all(args))
+    }
+
+    fn transform_index(&mut self, target: Expression, indices: Vec<Expression>) -> Expression {
+        Expression::Index(
+            Box::new(self.transform_expression(target)),
+            self.transform_all(indices),
+        )
+    }
+
+    fn transform_expression(&mut self, expr: Expression) -> Expression {
+        walk(self, expr)
+    }
+
+    fn transform_pair(
+        &mut self,
+        left: Expression,
+        right: Expression,
+    ) -> (Box<Expression>, Box<Expression>) {
+        let left = self.transform_expression(left);
+        let right = self.transform_expression(right);
+        (Box::new(left), Box::new(right))
+    }
+
+    fn transform_all(&mut self, exprs: Vec<Expression>) -> Vec<Expression> {
+        exprs
+            .into_iter()
+            .map(|e| self.transform_expression(e))
+            .collect()
+    }
+}
+
+// Dispatches `expr` to the matching `transform_*` method. This is the default
+// `transform_expression`; overrides call it to keep the per-variant dispatch.
+pub fn walk<T: ExpressionTransformer + ?Sized>(
+    transformer: &mut T,
+    mut expr: Expression,
+) -> Expression {
+    match &mut expr {
+        Expression::Number(n) => transformer.transform_number(*n),
+        Expression::Add(a, b) => transformer.transform_add(a.take(), b.take()),
+        Expression::Subtract(a, b) => transformer.transform_subtract(a.take(), b.take()),
+        Expression::Multiply(a, b) => transformer.transform_multiply(a.take(), b.take()),
+        Expression::Divide(a, b) => transformer.transform_divide(a.take(), b.take()),
+        Expression::Power(a, b) => transformer.transform_power(a.take(), b.take()),
+        Expression::Negate(e) => transformer.transform_negate(e.take()),
+        Expression::Grouping(e) => transformer.transform_grouping(e.take()),
+        Expression::Variable(name) => transformer.transform_variable(std::mem::take(name)),
+        Expression::Equation(a, b) => transformer.transform_equation(a.take(), b.take()),
+        Expression::Range(a, b) => transformer.transform_range(a.take(), b.take()),
+        Expression::List(elements) => transformer.transform_list(std::mem::take(elements)),
+        Expression::Call(name, args) => {
+            transformer.transform_call(std::mem::take(name), std::mem::take(args))
+        }
+        Expression::Index(target, indices) => {
+            transformer.transform_index(target.take(), std::mem::take(indices))
+        }
+        Expression::Error => Expression::Error,
+    }
+}
+
+// Calls `f` on every direct child of `expr`, left to right. Passes that edit a
+// tree in place recurse by calling `walk_mut` again from inside `f`.
+pub fn walk_mut(expr: &mut Expression, mut f: impl FnMut(&mut Expression)) {
+    match expr {
+        Expression::Number(_) | Expression::Variable(_) | Expression::Error => {}
+        Expression::Add(a, b)
+        | Expression::Subtract(a, b)
+        | Expression::Multiply(a, b)
+        | Expression::Divide(a, b)
+        | Expression::Power(a, b)
+        | Expression::Equation(a, b)
+        | Expression::Range(a, b) => {
+            f(a);
+            f(b);
+        }
+        Expression::Negate(e) | Expression::Grouping(e) => f(e),
+        Expression::List(elements) | Expression::Call(_, elements) => {
+            elements.iter_mut().for_each(f)
+        }
+        Expression::Index(target, indices) => {
+            f(target);
+            indices.iter_mut().for_each(f);
+        }
+    }
+}
+
+#[cfg(test)]
+mod transform_tests {
+    use super::*;
+    use crate::parsemath::parser::Parser;
+    use crate::parsemath::visitors::{ExpressionVisitor, PrettyPrinterVisitor};
+    use rstest::rstest;
+
+    fn parse(input: &str) -> Expression {
+        Parser::new(input).unwrap().parse().unwrap()
+    }
+
+    fn print(expr: &Expression) -> String {
+        let mut printer = PrettyPrinterVisitor::new();
+        printer.visit_expression(expr).unwrap()
+    }
+
+    struct Identity;
+
+    impl ExpressionTransformer for Identity {}
+
+    // Desugars `a - b` into `a + -b`, leaving every other node to the defaults.
+    struct SubtractionToAddition;
+
+    impl ExpressionTransformer for SubtractionToAddition {
+        fn transform_subtract(&mut self, left: Expression, right: Expression) -> Expression {
+            let left = self.transform_expression(left);
+            let right = self.transform_expression(right);
+            Expression::Add(
+                Box::new(left),
+                Box::new(Expression::Negate(Box::new(right))),
+            )
+        }
+    }
+
+    #[rstest]
+    #[case("f(x, [1, 2][1]) ^ -(y)")]
+    #[case("x = (1..(2 - 3) * 4 / 5)")]
+    fn default_transformer_rebuilds_the_tree(#[case] input: &str) {
+        let ast = parse(input);
+        assert_eq!(Identity.transform_expression(ast.clone()), ast);
+    }
+
+    #[rstest]
+    #[case("a - b", "a + -b")]
+    #[case("sin(a - b) * (c - 1)", "sin(a + -b) * (c + -1)")]
+    fn transformer_overrides_only_selected_nodes(#[case] input: &str, #[case] expected: &str) {
+        let result = SubtractionToAddition.transform_expression(parse(input));
+        assert_eq!(print(&result), expected);
+    }
+
+    #[rstest]
+    fn walk_mut_rewrites_in_place() {
+        fn rename(expr: &mut Expression) {
+            match expr {
+                Expression::Variable(name) if name == "x" => *name = "t".to_string(),
+                _ => walk_mut(expr, rename),
+            }
+        }
+
+        let mut ast = parse("x ^ 2 + max([x, y], x)");
+        rename(&mut ast);
+        assert_eq!(print(&ast), "t ^ 2 + max([t, y], t)");
+    }
+}
@@ -0,0 +1,200 @@
+use super::constructors::{PRIMARY, UNARY};
+use super::parser::Expression;
+use super::render::Renderer;
+use super::visitors::ExpressionVisitor;
+
+// Renders an expression with Unicode math symbols for terminals, e.g.
+// `x² · √(y) ÷ 3`. Simple exponents become superscripts; anything else falls
+// back to `^`. Parentheses are inserted only where precedence requires them.
+pub struct UnicodeVisitor;
+
+const GREEK_LETTERS: [(&str, char); 23] = [
+    ("alpha", 'α'),
+    ("beta", 'β'),
+    ("gamma", 'γ'),
+    ("delta", 'δ'),
+    ("epsilon", 'ε'),
+    ("zeta", 'ζ'),
+    ("eta", 'η'),
+    ("theta", 'θ'),
+    ("iota", 'ι'),
+    ("kappa", 'κ'),
+    ("lambda", 'λ'),
+    ("mu", 'μ'),
+    ("nu", 'ν'),
+    ("xi", 'ξ'),
+    ("pi", 'π'),
+    ("rho", 'ρ'),
+    ("sigma", 'σ'),
+    ("tau", 'τ'),
+    ("upsilon", 'υ'),
+    ("phi", 'φ'),
+    ("chi", 'χ'),
+    ("psi", 'ψ'),
+    ("omega", 'ω'),
+];
+
+// The Greek letter a variable such as `pi` or `theta` stands for.
+pub(crate) fn greek_letter(name: &str) -> Option<char> {
+    GREEK_LETTERS
+        .iter()
+        .find(|(letter, _)| *letter == name)
+        .map(|(_, symbol)| *symbol)
+}
+
+fn superscript(c: char) -> Option<char> {
+    let symbol = match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '−' => '⁻',
+        '(' => '⁽',
+        ')' => '⁾',
+        'n' => 'ⁿ',
+        'i' => 'ⁱ',
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+impl Renderer<String> for UnicodeVisitor {
+    fn bracket(text: String) -> String {
+        format!("({})", text)
+    }
+
+    fn infix(left: String, operator: &str, right: String) -> String {
+        format!("{} {} {}", left, operator, right)
+    }
+
+    fn separated(items: Vec<String>) -> String {
+        items.join(", ")
+    }
+}
+
+impl ExpressionVisitor<String, ()> for UnicodeVisitor {
+    fn visit_number(&mut self, value: f64) -> Result<String, ()> {
+        Ok(value.to_string().replace('-', "−"))
+    }
+
+    fn visit_add(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, "+", right, 1)
+    }
+
+    fn visit_subtract(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, "−", right, 1)
+    }
+
+    fn visit_multiply(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, "·", right, 2)
+    }
+
+    fn visit_divide(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, "÷", right, 2)
+    }
+
+    fn visit_power(&mut self, base: &Expression, exponent: &Expression) -> Result<String, ()> {
+        let base = self.operand(base, PRIMARY)?;
+        let text = self.visit_expression(exponent)?;
+
+        let superscript: Option<String> = text
+            .chars()
+            .filter(|c| *c != ' ')
+            .map(superscript)
+            .collect();
+        match superscript {
+            Some(superscript) => Ok(format!("{}{}", base, superscript)),
+            None => Ok(format!("{}^{}", base, self.operand(exponent, PRIMARY)?)),
+        }
+    }
+
+    fn visit_negate(&mut self, expr: &Expression) -> Result<String, ()> {
+        Ok(format!("−{}", self.operand(expr, UNARY)?))
+    }
+
+    fn visit_grouping(&mut self, expr: &Expression) -> Result<String, ()> {
+        self.visit_expression(expr)
+    }
+
+    fn visit_variable(&mut self, name: &str) -> Result<String, ()> {
+        match greek_letter(name) {
+            Some(letter) => Ok(letter.to_string()),
+            None => Ok(name.to_string()),
+        }
+    }
+
+    fn visit_equation(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        Ok(format!(
+            "{} = {}",
+            self.operand(left, 1)?,
+            self.operand(right, 1)?
+        ))
+    }
+
+    fn visit_range(&mut self, from: &Expression, to: &Expression) -> Result<String, ()> {
+        Ok(format!(
+            "{}…{}",
+            self.operand(from, 1)?,
+            self.operand(to, 1)?
+        ))
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) -> Result<String, ()> {
+        Ok(format!("[{}]", self.visit_all(elements)?))
+    }
+
+    fn visit_call(&mut self, name: &str, args: &[Expression]) -> Result<String, ()> {
+        match (name, args) {
+            ("sqrt", [arg]) => Ok(format!("√({})", self.visit_expression(arg)?)),
+            ("abs", [arg]) => Ok(format!("|{}|", self.visit_expression(arg)?)),
+            _ => Ok(format!("{}({})", name, self.visit_all(args)?)),
+        }
+    }
+
+    fn visit_index(&mut self, target: &Expression, indices: &[Expression]) -> Result<String, ()> {
+        Ok(format!(
+            "{}[{}]",
+            self.operand(target, PRIMARY)?,
+            self.visit_all(indices)?
+        ))
+    }
+
+    fn visit_error(&mut self) -> Result<String, ()> {
+        Ok("?".to_string())
+    }
+}
+
+#[cfg(test)]
+mod unicode_tests {
+    use super::*;
+    use crate::parsemath::parser::Parser;
+    use rstest::rstest;
+
+    fn unicode(input: &str) -> String {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        UnicodeVisitor.visit_expression(&ast).unwrap()
+    }
+
+    #[rstest]
+    #[case("x^2 * sqrt(y) / 3", "x² · √(y) ÷ 3")]
+    #[case("a - (b - c)", "a − (b − c)")]
+    #[case("a / (b * c)", "a ÷ (b · c)")]
+    #[case("(x + 1) ^ -1", "(x + 1)⁻¹")]
+    #[case("x ^ (n + 1)", "xⁿ⁺¹")]
+    #[case("e ^ (2 * x)", "e^(2 · x)")]
+    #[case("(x ^ 2) ^ 3", "(x²)³")]
+    #[case("-abs(theta) * pi", "−|θ| · π")]
+    #[case("(-2) ^ 10", "(−2)¹⁰")]
+    #[case("0..5", "0…5")]
+    #[case("[[1, 2], [3, 4]][1, 2]", "[[1, 2], [3, 4]][1, 2]")]
+    fn render_unicode(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(unicode(input), expected);
+    }
+}
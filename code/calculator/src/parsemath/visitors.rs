@@ -1,4 +1,18 @@
+use std::collections::HashMap;
+
+use super::arena::{ExprId, ExpressionArena, Node, NodeVisitor};
+use super::constructors::{PRIMARY, UNARY, precedence};
+use super::derivative::DerivativeVisitor;
+use super::limits::{self, Budget, EvalLimits, LimitError};
 use super::parser::Expression;
+use super::render::Renderer;
+use crate::calcmath::calculus;
+use crate::calcmath::functions::{self, expect_args};
+use crate::calcmath::matrix::Matrix;
+use crate::calcmath::number_theory::to_integer;
+use crate::calcmath::roots;
+use crate::calcmath::value::Value;
+use thiserror::Error;
 
 pub trait ExpressionVisitor<T, Error> {
     fn visit_number(&mut self, value: f64) -> Result<T, Error>;
@@ -6,8 +20,16 @@ pub trait ExpressionVisitor<T, Error> {
     fn visit_subtract(&mut self, left: &Expression, right: &Expression) -> Result<T, Error>;
     fn visit_multiply(&mut self, left: &Expression, right: &Expression) -> Result<T, Error>;
     fn visit_divide(&mut self, left: &Expression, right: &Expression) -> Result<T, Error>;
+    fn visit_power(&mut self, base: &Expression, exponent: &Expression) -> Result<T, Error>;
     fn visit_negate(&mut self, expr: &Expression) -> Result<T, Error>;
     fn visit_grouping(&mut self, expr: &Expression) -> Result<T, Error>;
+    fn visit_variable(&mut self, name: &str) -> Result<T, Error>;
+    fn visit_equation(&mut self, left: &Expression, right: &Expression) -> Result<T, Error>;
+    fn visit_range(&mut self, from: &Expression, to: &Expression) -> Result<T, Error>;
+    fn visit_list(&mut self, elements: &[Expression]) -> Result<T, Error>;
+    fn visit_call(&mut self, name: &str, args: &[Expression]) -> Result<T, Error>;
+    fn visit_index(&mut self, target: &Expression, indices: &[Expression]) -> Result<T, Error>;
+    fn visit_error(&mut self) -> Result<T, Error>;
 
     fn visit_expression(&mut self, expr: &Expression) -> Result<T, Error> {
         match expr {
@@ -16,70 +38,734 @@ pub trait ExpressionVisitor<T, Error> {
             Expression::Subtract(a, b) => self.visit_subtract(a, b),
             Expression::Multiply(a, b) => self.visit_multiply(a, b),
             Expression::Divide(a, b) => self.visit_divide(a, b),
+            Expression::Power(a, b) => self.visit_power(a, b),
             Expression::Negate(e) => self.visit_negate(e),
             Expression::Grouping(e) => self.visit_grouping(e),
+            Expression::Variable(name) => self.visit_variable(name),
+            Expression::Equation(a, b) => self.visit_equation(a, b),
+            Expression::Range(a, b) => self.visit_range(a, b),
+            Expression::List(elements) => self.visit_list(elements),
+            Expression::Call(name, args) => self.visit_call(name, args),
+            Expression::Index(target, indices) => self.visit_index(target, indices),
+            Expression::Error => self.visit_error(),
         }
     }
 }
 
-#[derive(Debug)]
-pub struct EvaluatorError {
-    message: String,
+#[derive(Error, Debug, PartialEq)]
+pub enum EvaluatorError {
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Dimension mismatch: {0}")]
+    DimensionMismatch(String),
+    #[error("Type mismatch: {0}")]
+    TypeMismatch(String),
+    #[error("Matrix is singular")]
+    SingularMatrix,
+    #[error("Index out of range: {0}")]
+    IndexOutOfRange(String),
+    #[error("Unknown function '{0}'")]
+    UnknownFunction(String),
+    #[error("Function '{function}' expects {expected} argument(s), got {found}")]
+    ArgumentCount {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("Expected an integer, got {0}")]
+    NotAnInteger(f64),
+    #[error("Integer overflow: {0}")]
+    IntegerOverflow(String),
+    #[error("Unknown variable '{0}'")]
+    UnknownVariable(String),
+    #[error("Unexpected expression: {0}")]
+    UnexpectedExpression(String),
+    #[error("No sign change in [{0}, {1}]")]
+    NoSignChange(f64, f64),
+    #[error("No convergence: {0}")]
+    NoConvergence(String),
+    #[error("Not differentiable: {0}")]
+    NotDifferentiable(String),
+    #[error("Cannot compile: {0}")]
+    NotCompilable(String),
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(#[from] LimitError),
 }
 
-pub struct Evaluator;
+#[derive(Default)]
+pub struct Evaluator {
+    variables: HashMap<String, Value>,
+    limits: EvalLimits,
+    // Present while an evaluation runs.
+    budget: Option<Budget>,
+}
 
-impl ExpressionVisitor<f64, EvaluatorError> for Evaluator {
-    fn visit_number(&mut self, value: f64) -> Result<f64, EvaluatorError> {
-        Ok(value)
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_variable(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.set_variable(name, value);
+        self
+    }
+
+    pub fn with_limits(mut self, limits: EvalLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: impl Into<Value>) {
+        self.variables.insert(name.to_string(), value.into());
+    }
+
+    fn restore_variable(&mut self, name: &str, previous: Option<Value>) {
+        match previous {
+            Some(value) => self.set_variable(name, value),
+            None => {
+                self.variables.remove(name);
+            }
+        }
+    }
+
+    // Runs `body` with `f(x)`, the value of `expression` when `variable` is bound
+    // to `x`. Any outer binding of `variable` is restored afterwards.
+    fn with_function_of<R>(
+        &mut self,
+        expression: &Expression,
+        variable: &str,
+        body: impl FnOnce(
+            &mut dyn FnMut(f64) -> Result<f64, EvaluatorError>,
+        ) -> Result<R, EvaluatorError>,
+    ) -> Result<R, EvaluatorError> {
+        let previous = self.variables.remove(variable);
+        let result = body(&mut |x| {
+            self.set_variable(variable, x);
+            self.visit_expression(expression)?.as_number()
+        });
+        self.restore_variable(variable, previous);
+        result
+    }
+
+    // Built-ins that receive their arguments unevaluated, so they can evaluate an
+    // expression repeatedly as a function of one variable. Returns `None` when
+    // the call is an ordinary one, e.g. the linear `solve(A, b)`.
+    fn call_lazy(
+        &mut self,
+        name: &str,
+        args: &[Expression],
+    ) -> Option<Result<Value, EvaluatorError>> {
+        match (name, args) {
+            ("solve", [equation, Expression::Variable(variable)])
+                if solves_for(equation, variable, self.variables.contains_key(variable)) =>
+            {
+                Some(self.solve_equation(equation, variable, None))
+            }
+            ("solve", [equation, Expression::Variable(variable), search]) => {
+                Some(self.solve_equation(equation, variable, Some(search)))
+            }
+            ("integrate", _) => Some(self.integrate(args)),
+            ("diff", _) => Some(self.differentiate(args)),
+            _ => None,
+        }
+    }
+
+    // `solve(lhs = rhs, x)` uses Newton's method from x = 1, `solve(lhs = rhs, x, x0)`
+    // starts from x0 and `solve(lhs = rhs, x, a..b)` reports every root in [a, b].
+    // A bare expression instead of an equation is solved for `expression = 0`.
+    fn solve_equation(
+        &mut self,
+        equation: &Expression,
+        variable: &str,
+        search: Option<&Expression>,
+    ) -> Result<Value, EvaluatorError> {
+        let function = match equation {
+            Expression::Equation(left, right) => Expression::Subtract(left.clone(), right.clone()),
+            expression => expression.clone(),
+        };
+
+        let roots = match search {
+            Some(Expression::Range(from, to)) => {
+                let from = self.visit_expression(from)?.as_number()?;
+                let to = self.visit_expression(to)?.as_number()?;
+                self.with_function_of(&function, variable, |f| roots::find_roots(f, from, to))?
+            }
+            _ => {
+                let guess = match search {
+                    Some(guess) => self.visit_expression(guess)?.as_number()?,
+                    None => 1.0,
+                };
+                vec![self.with_function_of(&function, variable, |f| roots::newton(f, guess))?]
+            }
+        };
+
+        if roots.len() == 1 {
+            return Ok(Value::Number(roots[0]));
+        }
+        Ok(Matrix::row_vector(roots).into())
+    }
+
+    // `integrate(expression, x, a, b)`
+    fn integrate(&mut self, args: &[Expression]) -> Result<Value, EvaluatorError> {
+        let [body, variable, from, to] = expect_args("integrate", args)?;
+        let variable = Self::variable_name("integrate", variable)?;
+        let from = self.visit_expression(from)?.as_number()?;
+        let to = self.visit_expression(to)?.as_number()?;
+
+        let value = self.with_function_of(body, variable, |f| calculus::integrate(f, from, to))?;
+        Ok(Value::Number(value))
+    }
+
+    // `diff(expression, x, at)`
+    fn differentiate(&mut self, args: &[Expression]) -> Result<Value, EvaluatorError> {
+        let [body, variable, at] = expect_args("diff", args)?;
+        let variable = Self::variable_name("diff", variable)?;
+        let at = self.visit_expression(at)?.as_number()?;
+
+        // Prefer the exact symbolic derivative; fall back to finite differences
+        // for expressions without derivative rules.
+        let value = match DerivativeVisitor::new(variable).visit_expression(body) {
+            Ok(derivative) => {
+                limits::check_tree(&derivative, &self.limits)?;
+                self.with_function_of(&derivative, variable, |f| f(at))?
+            }
+            Err(EvaluatorError::NotDifferentiable(_)) => {
+                self.with_function_of(body, variable, |f| calculus::derivative(f, at))?
+            }
+            Err(error) => return Err(error),
+        };
+        Ok(Value::Number(value))
+    }
+
+    fn variable_name<'a>(
+        function: &str,
+        expression: &'a Expression,
+    ) -> Result<&'a str, EvaluatorError> {
+        match expression {
+            Expression::Variable(name) => Ok(name),
+            _ => Err(EvaluatorError::InvalidArgument(format!(
+                "{} expects a variable name as its second argument",
+                function
+            ))),
+        }
+    }
+
+    // Converts a 1-based index into a 0-based one, checking it against `len`.
+    fn index(value: Value, len: usize) -> Result<usize, EvaluatorError> {
+        let index = value.as_number()?;
+        if index.fract() != 0.0 || index < 1.0 || index > len as f64 {
+            return Err(EvaluatorError::IndexOutOfRange(format!(
+                "{} is not in 1..={}",
+                index, len
+            )));
+        }
+        Ok(index as usize - 1)
     }
 
-    fn visit_add(&mut self, left: &Expression, right: &Expression) -> Result<f64, EvaluatorError> {
-        Ok(self.visit_expression(left)? + self.visit_expression(right)?)
+    fn unexpected_equation() -> EvaluatorError {
+        EvaluatorError::UnexpectedExpression(
+            "equations are only allowed inside solve()".to_string(),
+        )
+    }
+
+    fn unexpected_range() -> EvaluatorError {
+        EvaluatorError::UnexpectedExpression("ranges are only allowed inside solve()".to_string())
+    }
+
+    fn add(left: Value, right: Value) -> Result<Value, EvaluatorError> {
+        match (left, right) {
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+            (Value::Matrix(a), Value::Matrix(b)) => Ok(a.add(&b)?.into()),
+            _ => Err(EvaluatorError::TypeMismatch(
+                "cannot add a number and a matrix".to_string(),
+            )),
+        }
+    }
+
+    fn subtract(left: Value, right: Value) -> Result<Value, EvaluatorError> {
+        match (left, right) {
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a - b)),
+            (Value::Matrix(a), Value::Matrix(b)) => Ok(a.subtract(&b)?.into()),
+            _ => Err(EvaluatorError::TypeMismatch(
+                "cannot subtract a number and a matrix".to_string(),
+            )),
+        }
+    }
+
+    fn multiply(left: Value, right: Value) -> Result<Value, EvaluatorError> {
+        match (left, right) {
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a * b)),
+            (Value::Number(a), Value::Matrix(m)) | (Value::Matrix(m), Value::Number(a)) => {
+                Ok(m.map(|v| a * v).into())
+            }
+            (Value::Matrix(a), Value::Matrix(b)) => Ok(a.multiply(&b)?.into()),
+        }
+    }
+
+    // The divisor is checked before the dividend is evaluated.
+    fn check_divisor(divisor: &Value) -> Result<(), EvaluatorError> {
+        if *divisor == 0.0 {
+            return Err(EvaluatorError::DivisionByZero);
+        }
+        Ok(())
+    }
+
+    fn divide(left: Value, right: Value) -> Result<Value, EvaluatorError> {
+        match (left, right) {
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a / b)),
+            (Value::Matrix(m), Value::Number(b)) => Ok(m.map(|v| v / b).into()),
+            _ => Err(EvaluatorError::TypeMismatch(
+                "cannot divide by a matrix, use inv() instead".to_string(),
+            )),
+        }
+    }
+
+    fn power(base: Value, exponent: Value) -> Result<Value, EvaluatorError> {
+        match (base, exponent) {
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a.powf(b))),
+            (Value::Matrix(m), Value::Number(n)) => Ok(m.power(to_integer(n)?)?.into()),
+            _ => Err(EvaluatorError::TypeMismatch(
+                "exponent must be a number".to_string(),
+            )),
+        }
+    }
+
+    fn negate(value: Value) -> Value {
+        match value {
+            Value::Number(n) => Value::Number(-n),
+            Value::Matrix(m) => m.map(|v| -v).into(),
+        }
+    }
+
+    // Numbers form a row; rows (or whole matrices) stack vertically.
+    fn list(values: Vec<Value>) -> Result<Value, EvaluatorError> {
+        if values.iter().all(|v| matches!(v, Value::Number(_))) {
+            let row = values.iter().map(Value::as_number).collect::<Result<_, _>>()?;
+            return Ok(Matrix::row_vector(row).into());
+        }
+
+        let mut rows = Vec::new();
+        for value in &values {
+            let m = value.as_matrix()?;
+            rows.extend((0..m.rows()).map(|r| m.row(r).values().to_vec()));
+        }
+        Ok(Matrix::from_rows(rows)?.into())
+    }
+
+    // `m[i]` selects a row (or an element of a row vector), `m[i, j]` an element.
+    fn select(target: &Value, indices: Vec<Value>) -> Result<Value, EvaluatorError> {
+        let m = target.as_matrix()?;
+        let mut indices = indices.into_iter();
+        match (indices.next(), indices.next(), indices.next()) {
+            (Some(i), None, None) if m.rows() == 1 => {
+                Ok(Value::Number(m.get(0, Self::index(i, m.cols())?)))
+            }
+            (Some(i), None, None) => Ok(m.row(Self::index(i, m.rows())?).into()),
+            (Some(i), Some(j), None) => {
+                let row = Self::index(i, m.rows())?;
+                let col = Self::index(j, m.cols())?;
+                Ok(Value::Number(m.get(row, col)))
+            }
+            _ => Err(EvaluatorError::IndexOutOfRange(
+                "a matrix takes one or two indices".to_string(),
+            )),
+        }
+    }
+}
+
+impl ExpressionVisitor<Value, EvaluatorError> for Evaluator {
+    fn visit_number(&mut self, value: f64) -> Result<Value, EvaluatorError> {
+        Ok(Value::Number(value))
+    }
+
+    fn visit_add(&mut self, left: &Expression, right: &Expression) -> Result<Value, EvaluatorError> {
+        Self::add(self.visit_expression(left)?, self.visit_expression(right)?)
     }
 
     fn visit_subtract(
         &mut self,
         left: &Expression,
         right: &Expression,
-    ) -> Result<f64, EvaluatorError> {
-        Ok(self.visit_expression(left)? - self.visit_expression(right)?)
+    ) -> Result<Value, EvaluatorError> {
+        Self::subtract(self.visit_expression(left)?, self.visit_expression(right)?)
     }
 
     fn visit_multiply(
         &mut self,
         left: &Expression,
         right: &Expression,
-    ) -> Result<f64, EvaluatorError> {
-        Ok(self.visit_expression(left)? * self.visit_expression(right)?)
+    ) -> Result<Value, EvaluatorError> {
+        Self::multiply(self.visit_expression(left)?, self.visit_expression(right)?)
     }
 
     fn visit_divide(
         &mut self,
         left: &Expression,
         right: &Expression,
-    ) -> Result<f64, EvaluatorError> {
-        let right_value = self.visit_expression(right)?;
-        if right_value == 0.0 {
-            return Err(EvaluatorError {
-                message: "Division by zero".to_string(),
-            });
-        }
+    ) -> Result<Value, EvaluatorError> {
+        let right = self.visit_expression(right)?;
+        Self::check_divisor(&right)?;
+        Self::divide(self.visit_expression(left)?, right)
+    }
 
-        Ok(self.visit_expression(left)? / self.visit_expression(right)?)
+    fn visit_power(
+        &mut self,
+        base: &Expression,
+        exponent: &Expression,
+    ) -> Result<Value, EvaluatorError> {
+        Self::power(
+            self.visit_expression(base)?,
+            self.visit_expression(exponent)?,
+        )
     }
 
-    fn visit_negate(&mut self, expr: &Expression) -> Result<f64, EvaluatorError> {
-        Ok(-self.visit_expression(expr)?)
+    fn visit_negate(&mut self, expr: &Expression) -> Result<Value, EvaluatorError> {
+        Ok(Self::negate(self.visit_expression(expr)?))
     }
 
-    fn visit_grouping(&mut self, expr: &Expression) -> Result<f64, EvaluatorError> {
+    fn visit_grouping(&mut self, expr: &Expression) -> Result<Value, EvaluatorError> {
         self.visit_expression(expr)
     }
+
+    fn visit_variable(&mut self, name: &str) -> Result<Value, EvaluatorError> {
+        if let Some(value) = self.variables.get(name) {
+            return Ok(value.clone());
+        }
+        functions::constant(name)
+            .map(Value::Number)
+            .ok_or_else(|| EvaluatorError::UnknownVariable(name.to_string()))
+    }
+
+    fn visit_equation(&mut self, _: &Expression, _: &Expression) -> Result<Value, EvaluatorError> {
+        Err(Self::unexpected_equation())
+    }
+
+    fn visit_range(&mut self, _: &Expression, _: &Expression) -> Result<Value, EvaluatorError> {
+        Err(Self::unexpected_range())
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) -> Result<Value, EvaluatorError> {
+        let values = elements
+            .iter()
+            .map(|e| self.visit_expression(e))
+            .collect::<Result<Vec<Value>, EvaluatorError>>()?;
+        Self::list(values)
+    }
+
+    fn visit_call(&mut self, name: &str, args: &[Expression]) -> Result<Value, EvaluatorError> {
+        if let Some(result) = self.call_lazy(name, args) {
+            return result;
+        }
+
+        let values = args
+            .iter()
+            .map(|arg| self.visit_expression(arg))
+            .collect::<Result<Vec<Value>, EvaluatorError>>()?;
+
+        functions::call_with_limits(name, &values, &self.limits, self.budget.as_mut())
+    }
+
+    fn visit_index(
+        &mut self,
+        target: &Expression,
+        indices: &[Expression],
+    ) -> Result<Value, EvaluatorError> {
+        let target = self.visit_expression(target)?;
+        target.as_matrix()?;
+        let indices = indices
+            .iter()
+            .map(|i| self.visit_expression(i))
+            .collect::<Result<Vec<Value>, EvaluatorError>>()?;
+        Self::select(&target, indices)
+    }
+
+    fn visit_error(&mut self) -> Result<Value, EvaluatorError> {
+        Err(EvaluatorError::UnexpectedExpression(
+            "the expression contains a parse error".to_string(),
+        ))
+    }
+
+    // Nested evaluations inside `solve`, `integrate` and `diff` share the
+    // budget of the outermost one.
+    fn visit_expression(&mut self, expr: &Expression) -> Result<Value, EvaluatorError> {
+        if self.budget.is_some() {
+            return self.evaluate(expr);
+        }
+        limits::check_tree(expr, &self.limits)?;
+        self.budget = Some(Budget::start(&self.limits));
+        let result = self.evaluate(expr);
+        self.budget = None;
+        result
+    }
+}
+
+impl Evaluator {
+    // Evaluates with an explicit work stack instead of recursing into the
+    // visit_* methods, so the depth of a tree is bounded by memory rather than
+    // by the call stack. Only `solve`, `integrate` and `diff` recurse, once per
+    // nested call. The order of evaluation, and so which error is reported
+    // first, is the same as in the visit_* methods.
+    fn evaluate(&mut self, expr: &Expression) -> Result<Value, EvaluatorError> {
+        enum Task<'e> {
+            Visit(&'e Expression),
+            Apply(&'e Expression),
+            CheckDivisor,
+            CheckMatrix,
+        }
+
+        fn pop(values: &mut Vec<Value>) -> Value {
+            values.pop().expect("operand was evaluated")
+        }
+
+        fn pop_pair(values: &mut Vec<Value>) -> (Value, Value) {
+            let right = pop(values);
+            (pop(values), right)
+        }
+
+        let mut tasks = vec![Task::Visit(expr)];
+        let mut values: Vec<Value> = Vec::new();
+        while let Some(task) = tasks.pop() {
+            if let (Task::Visit(_), Some(budget)) = (&task, &mut self.budget) {
+                budget.step()?;
+            }
+            match task {
+                Task::Visit(expr) => match expr {
+                    Expression::Number(n) => values.push(Value::Number(*n)),
+                    Expression::Variable(name) => values.push(self.visit_variable(name)?),
+                    Expression::Error => return self.visit_error(),
+                    Expression::Grouping(e) => tasks.push(Task::Visit(e)),
+                    Expression::Equation(a, b) => return self.visit_equation(a, b),
+                    Expression::Range(a, b) => return self.visit_range(a, b),
+                    Expression::Add(a, b)
+                    | Expression::Subtract(a, b)
+                    | Expression::Multiply(a, b)
+                    | Expression::Power(a, b) => {
+                        tasks.extend([Task::Apply(expr), Task::Visit(b), Task::Visit(a)]);
+                    }
+                    Expression::Divide(a, b) => tasks.extend([
+                        Task::Apply(expr),
+                        Task::Visit(a),
+                        Task::CheckDivisor,
+                        Task::Visit(b),
+                    ]),
+                    Expression::Negate(e) => tasks.extend([Task::Apply(expr), Task::Visit(e)]),
+                    Expression::List(elements) => {
+                        tasks.push(Task::Apply(expr));
+                        tasks.extend(elements.iter().rev().map(Task::Visit));
+                    }
+                    Expression::Call(name, args) => match self.call_lazy(name, args) {
+                        Some(result) => values.push(result?),
+                        None => {
+                            tasks.push(Task::Apply(expr));
+                            tasks.extend(args.iter().rev().map(Task::Visit));
+                        }
+                    },
+                    Expression::Index(target, indices) => {
+                        tasks.push(Task::Apply(expr));
+                        tasks.extend(indices.iter().rev().map(Task::Visit));
+                        tasks.extend([Task::CheckMatrix, Task::Visit(target)]);
+                    }
+                },
+                Task::CheckDivisor => Self::check_divisor(values.last().expect("divisor"))?,
+                Task::CheckMatrix => {
+                    values.last().expect("index target").as_matrix()?;
+                }
+                Task::Apply(expr) => {
+                    let value = match expr {
+                        Expression::Add(..) => {
+                            let (a, b) = pop_pair(&mut values);
+                            Self::add(a, b)?
+                        }
+                        Expression::Subtract(..) => {
+                            let (a, b) = pop_pair(&mut values);
+                            Self::subtract(a, b)?
+                        }
+                        Expression::Multiply(..) => {
+                            let (a, b) = pop_pair(&mut values);
+                            if let (Value::Matrix(a), Value::Matrix(b)) = (&a, &b) {
+                                self.check_size(a.rows() * b.cols())?;
+                            }
+                            Self::multiply(a, b)?
+                        }
+                        // The divisor was evaluated first.
+                        Expression::Divide(..) => {
+                            let (divisor, dividend) = pop_pair(&mut values);
+                            Self::divide(dividend, divisor)?
+                        }
+                        Expression::Power(..) => {
+                            let (a, b) = pop_pair(&mut values);
+                            Self::power(a, b)?
+                        }
+                        Expression::Negate(_) => Self::negate(pop(&mut values)),
+                        Expression::List(elements) => {
+                            Self::list(values.split_off(values.len() - elements.len()))?
+                        }
+                        Expression::Call(name, args) => {
+                            let args = values.split_off(values.len() - args.len());
+                            self.charge_elements(&args)?;
+                            functions::call_with_limits(name, &args, &self.limits, self.budget.as_mut())?
+                        }
+                        Expression::Index(_, indices) => {
+                            let indices = values.split_off(values.len() - indices.len());
+                            Self::select(&pop(&mut values), indices)?
+                        }
+                        _ => unreachable!("only operators are applied"),
+                    };
+                    self.check_size(value.size())?;
+                    values.push(value);
+                }
+            }
+        }
+        Ok(pop(&mut values))
+    }
+
+    // Matrix products, whose result can be much larger than their operands, are
+    // also checked before they are allocated; so is `identity(n)`, by
+    // `functions::call_with_limits`.
+    fn check_size(&self, size: usize) -> Result<(), EvaluatorError> {
+        Ok(limits::check_value_size(size, &self.limits)?)
+    }
+
+    // Built-in functions take time proportional to the size of their arguments.
+    fn charge_elements(&mut self, args: &[Value]) -> Result<(), EvaluatorError> {
+        if let Some(budget) = &mut self.budget {
+            budget.charge(args.iter().map(|arg| arg.size() as u64).sum())?;
+        }
+        Ok(())
+    }
 }
 
+// Evaluates arena trees node by node, under the same limits and in the same
+// order as `evaluate`, so both report the same error first. Only calls that take
+// their arguments unevaluated rebuild their sub-tree as an `Expression`. A step
+// is charged per distinct node, as each one is evaluated once.
+impl NodeVisitor<Value, EvaluatorError> for Evaluator {
+    fn visit_tree(
+        &mut self,
+        arena: &ExpressionArena,
+        id: ExprId,
+    ) -> Result<Value, EvaluatorError> {
+        if self.budget.is_some() {
+            return arena.walk(id, self);
+        }
+        arena.check_limits(id, &self.limits)?;
+        self.budget = Some(Budget::start(&self.limits));
+        let result = arena.walk(id, self);
+        self.budget = None;
+        result
+    }
+
+    fn enter(
+        &mut self,
+        arena: &ExpressionArena,
+        id: ExprId,
+    ) -> Option<Result<Value, EvaluatorError>> {
+        if let Some(budget) = &mut self.budget
+            && let Err(error) = budget.step()
+        {
+            return Some(Err(error.into()));
+        }
+        match arena.get(id) {
+            Node::Equation(..) => Some(Err(Self::unexpected_equation())),
+            Node::Range(..) => Some(Err(Self::unexpected_range())),
+            Node::Call(name, _) if matches!(name.as_str(), "solve" | "integrate" | "diff") => {
+                match &arena.to_expression(id) {
+                    Expression::Call(name, args) => self.call_lazy(name, args),
+                    _ => unreachable!("a call node builds a call"),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // The divisor first, as in `visit_divide`.
+    fn child_order(&self, arena: &ExpressionArena, id: ExprId) -> Vec<usize> {
+        match arena.get(id) {
+            Node::Divide(..) => vec![1, 0],
+            _ => (0..arena.children(id).len()).collect(),
+        }
+    }
+
+    fn child_visited(
+        &mut self,
+        arena: &ExpressionArena,
+        id: ExprId,
+        index: usize,
+        value: &Value,
+    ) -> Result<(), EvaluatorError> {
+        match (arena.get(id), index) {
+            (Node::Divide(..), 1) => Self::check_divisor(value),
+            (Node::Index(..), 0) => value.as_matrix().map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    fn visit_node(
+        &mut self,
+        arena: &ExpressionArena,
+        id: ExprId,
+        children: Vec<Value>,
+    ) -> Result<Value, EvaluatorError> {
+        let mut children = children.into_iter();
+        let mut next = || children.next().expect("child was visited");
+        let value = match arena.get(id) {
+            Node::Number(n) => Value::Number(n.0),
+            Node::Variable(name) => self.visit_variable(name)?,
+            Node::Error => return self.visit_error(),
+            Node::Grouping(_) => next(),
+            Node::Add(..) => Self::add(next(), next())?,
+            Node::Subtract(..) => Self::subtract(next(), next())?,
+            Node::Multiply(..) => {
+                let (a, b) = (next(), next());
+                if let (Value::Matrix(a), Value::Matrix(b)) = (&a, &b) {
+                    self.check_size(a.rows() * b.cols())?;
+                }
+                Self::multiply(a, b)?
+            }
+            Node::Divide(..) => Self::divide(next(), next())?,
+            Node::Power(..) => Self::power(next(), next())?,
+            Node::Negate(_) => Self::negate(next()),
+            Node::List(_) => Self::list(children.collect())?,
+            Node::Call(name, _) => {
+                let args = children.collect::<Vec<_>>();
+                self.charge_elements(&args)?;
+                functions::call_with_limits(name, &args, &self.limits, self.budget.as_mut())?
+            }
+            Node::Index(..) => {
+                let target = next();
+                Self::select(&target, children.collect())?
+            }
+            Node::Equation(..) | Node::Range(..) => unreachable!("rejected in enter"),
+        };
+        self.check_size(value.size())?;
+        Ok(value)
+    }
+}
+
+// Whether `solve(function, variable)` finds a root of `function` rather than
+// being the linear `solve(A, b)`: it does when `variable` is unbound, or when
+// `function` is an equation or mentions it.
+pub(crate) fn solves_for(function: &Expression, variable: &str, bound: bool) -> bool {
+    !bound || matches!(function, Expression::Equation(..)) || function.mentions(variable)
+}
+
+// Prints an expression back as source text, inserting exactly the parentheses
+// needed for the text to re-parse to the same tree. Parentheses written by the user
+// (`Grouping` nodes) are kept unless `with_groupings(false)` is set.
 pub struct PrettyPrinterVisitor {
     pub output: String,
+    keep_groupings: bool,
+}
+
+impl Default for PrettyPrinterVisitor {
+    fn default() -> Self {
+        PrettyPrinterVisitor {
+            output: String::new(),
+            keep_groupings: true,
+        }
+    }
 }
 
 impl ExpressionVisitor<String, ()> for PrettyPrinterVisitor {
@@ -88,51 +774,195 @@ impl ExpressionVisitor<String, ()> for PrettyPrinterVisitor {
     }
 
     fn visit_add(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, " + ", right, 1)
+    }
+
+    fn visit_subtract(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, " - ", right, 1)
+    }
+
+    fn visit_multiply(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, " * ", right, 2)
+    }
+
+    fn visit_divide(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+        self.binary(left, " / ", right, 2)
+    }
+
+    // `^` is right-associative and its base must be a primary expression.
+    fn visit_power(&mut self, base: &Expression, exponent: &Expression) -> Result<String, ()> {
         Ok(format!(
-            "{} + {}",
-            self.visit_expression(left)?,
-            self.visit_expression(right)?
+            "{} ^ {}",
+            self.operand(base, PRIMARY)?,
+            self.operand(exponent, UNARY)?
         ))
     }
 
-    fn visit_subtract(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+    fn visit_negate(&mut self, expr: &Expression) -> Result<String, ()> {
+        Ok(format!("-{}", self.operand(expr, UNARY)?))
+    }
+
+    fn visit_grouping(&mut self, expr: &Expression) -> Result<String, ()> {
+        if self.keep_groupings {
+            Ok(format!("({})", self.visit_expression(expr)?))
+        } else {
+            self.visit_expression(expr)
+        }
+    }
+
+    fn visit_variable(&mut self, name: &str) -> Result<String, ()> {
+        Ok(name.to_string())
+    }
+
+    // `=` and `..` do not chain, so neither side may be another equation or range.
+    fn visit_equation(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
         Ok(format!(
-            "{} - {}",
-            self.visit_expression(left)?,
-            self.visit_expression(right)?
+            "{} = {}",
+            self.operand(left, 1)?,
+            self.operand(right, 1)?
         ))
     }
 
-    fn visit_multiply(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+    fn visit_range(&mut self, from: &Expression, to: &Expression) -> Result<String, ()> {
         Ok(format!(
-            "{} * {}",
-            self.visit_expression(left)?,
-            self.visit_expression(right)?
+            "{}..{}",
+            self.operand(from, 1)?,
+            self.operand(to, 1)?
         ))
     }
 
-    fn visit_divide(&mut self, left: &Expression, right: &Expression) -> Result<String, ()> {
+    fn visit_list(&mut self, elements: &[Expression]) -> Result<String, ()> {
+        Ok(format!("[{}]", self.visit_all(elements)?))
+    }
+
+    fn visit_call(&mut self, name: &str, args: &[Expression]) -> Result<String, ()> {
+        Ok(format!("{}({})", name, self.visit_all(args)?))
+    }
+
+    fn visit_index(&mut self, target: &Expression, indices: &[Expression]) -> Result<String, ()> {
         Ok(format!(
-            "{} / {}",
-            self.visit_expression(left)?,
-            self.visit_expression(right)?
+            "{}[{}]",
+            self.operand(target, PRIMARY)?,
+            self.visit_all(indices)?
         ))
     }
 
-    fn visit_negate(&mut self, expr: &Expression) -> Result<String, ()> {
-        Ok(format!("-{}", self.visit_expression(expr)?))
+    fn visit_error(&mut self) -> Result<String, ()> {
+        Ok("?".to_string())
     }
+}
 
-    fn visit_grouping(&mut self, expr: &Expression) -> Result<String, ()> {
-        Ok(format!("({})", self.visit_expression(expr)?))
+impl PrettyPrinterVisitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_groupings(mut self, keep_groupings: bool) -> Self {
+        self.keep_groupings = keep_groupings;
+        self
+    }
+}
+
+impl Renderer<String> for PrettyPrinterVisitor {
+    fn bracket(text: String) -> String {
+        parenthesize(text, true)
+    }
+
+    fn infix(left: String, operator: &str, right: String) -> String {
+        format!("{}{}{}", left, operator, right)
+    }
+
+    fn separated(items: Vec<String>) -> String {
+        items.join(", ")
+    }
+
+    fn precedence(&self, expr: &Expression) -> u8 {
+        match expr {
+            Expression::Grouping(_) if self.keep_groupings => PRIMARY,
+            Expression::Grouping(inner) => self.precedence(inner),
+            expr => precedence(expr),
+        }
+    }
+}
+
+fn parenthesize(text: String, parenthesize: bool) -> String {
+    if parenthesize {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+impl NodeVisitor<String, ()> for PrettyPrinterVisitor {
+    fn visit_node(
+        &mut self,
+        arena: &ExpressionArena,
+        id: ExprId,
+        children: Vec<String>,
+    ) -> Result<String, ()> {
+        let ids = arena.children(id);
+        let mut children = children.into_iter().zip(&ids);
+        let mut operand = |min_precedence: u8| {
+            let (text, id) = children.next().expect("child was visited");
+            parenthesize(text, self.node_precedence(arena, *id) < min_precedence)
+        };
+        Ok(match arena.get(id) {
+            Node::Number(n) => n.0.to_string(),
+            Node::Variable(name) => name.clone(),
+            Node::Error => "?".to_string(),
+            Node::Add(..) => format!("{} + {}", operand(1), operand(2)),
+            Node::Subtract(..) => format!("{} - {}", operand(1), operand(2)),
+            Node::Multiply(..) => format!("{} * {}", operand(2), operand(3)),
+            Node::Divide(..) => format!("{} / {}", operand(2), operand(3)),
+            Node::Power(..) => format!("{} ^ {}", operand(PRIMARY), operand(UNARY)),
+            Node::Negate(_) => format!("-{}", operand(UNARY)),
+            Node::Grouping(_) => parenthesize(operand(0), self.keep_groupings),
+            Node::Equation(..) => format!("{} = {}", operand(1), operand(1)),
+            Node::Range(..) => format!("{}..{}", operand(1), operand(1)),
+            Node::List(_) => format!(
+                "[{}]",
+                (0..ids.len())
+                    .map(|_| operand(0))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Node::Call(name, _) => format!(
+                "{}({})",
+                name,
+                (0..ids.len())
+                    .map(|_| operand(0))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Node::Index(..) => {
+                let target = operand(PRIMARY);
+                let indices = (1..ids.len()).map(|_| operand(0)).collect::<Vec<_>>();
+                format!("{}[{}]", target, indices.join(", "))
+            }
+        })
+    }
+}
+
+impl PrettyPrinterVisitor {
+    // `precedence` for arena nodes.
+    fn node_precedence(&self, arena: &ExpressionArena, mut id: ExprId) -> u8 {
+        while let Node::Grouping(inner) = arena.get(id) {
+            if self.keep_groupings {
+                return PRIMARY;
+            }
+            id = *inner;
+        }
+        arena.precedence(id)
     }
 }
 
 #[cfg(test)]
 mod visitor_tests {
     use super::*;
-    use crate::parsemath::parser::Expression;
+    use crate::parsemath::parser::{DeepExpression, Expression, Parser};
+    use crate::parsemath::transform::ExpressionTransformer;
     use rstest::{fixture, rstest};
+    use std::time::Duration;
 
     #[fixture]
     fn expression() -> Expression {
@@ -150,7 +980,7 @@ mod visitor_tests {
 
     #[rstest]
     fn evaluate_expression_with_visitor(expression: Expression) {
-        let mut evaluator = Evaluator {};
+        let mut evaluator = Evaluator::new();
         let result = evaluator.visit_expression(&expression);
         assert_eq!(result.unwrap(), -3.0);
     }
@@ -162,17 +992,427 @@ mod visitor_tests {
             Box::new(Expression::Number(0.0)),
         );
 
-        let mut evaluator = Evaluator {};
+        let mut evaluator = Evaluator::new();
         let result = evaluator.visit_expression(&expression);
         assert!(result.is_err());
     }
 
     #[rstest]
     fn pretty_print_expression_with_visitor(expression: Expression) {
-        let mut printer = PrettyPrinterVisitor {
-            output: String::new(),
-        };
+        let mut printer = PrettyPrinterVisitor::new();
         let result = printer.visit_expression(&expression);
         assert_eq!(result.unwrap(), "(1 + 2) * (3 - 4)");
     }
+
+    fn evaluate(input: &str) -> Result<Value, EvaluatorError> {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        Evaluator::new().visit_expression(&ast)
+    }
+
+    #[rstest]
+    #[case("[[1, 2], [3, 4]] * [[5], [6]]", "[[17], [39]]")]
+    #[case("2 * [1, 2] - [1, 1]", "[1, 3]")]
+    #[case("transpose([[1, 2], [3, 4]])", "[[1, 3], [2, 4]]")]
+    #[case("det([[2, 0], [0, 3]])", "6")]
+    #[case("inv([[2, 0], [0, 4]])", "[[0.5, 0], [0, 0.25]]")]
+    #[case("solve([[2, 0], [0, 4]], [2, 8])", "[1, 2]")]
+    #[case("identity(2)", "[[1, 0], [0, 1]]")]
+    #[case("[[1, 2], [3, 4]][2, 1]", "3")]
+    #[case("[[1, 2], [3, 4]][2]", "[3, 4]")]
+    #[case("[[1, 2], [3, 4]][2][2]", "4")]
+    #[case("[[1, 2], [3, 4], [5, 6]]", "[[1, 2], [3, 4], [5, 6]]")]
+    fn evaluate_matrix_expressions(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(evaluate(input).unwrap().to_string(), expected);
+    }
+
+    #[rstest]
+    #[case("[[1, 2], [3]]")]
+    #[case("[1, 2] + [1, 2, 3]")]
+    #[case("[[1, 2]] * [[1, 2]]")]
+    #[case("det([[1, 2]])")]
+    fn evaluate_matrix_dimension_mismatch(#[case] input: &str) {
+        assert!(matches!(evaluate(input), Err(EvaluatorError::DimensionMismatch(_))));
+    }
+
+    #[rstest]
+    #[case("[1, 2][0]")]
+    #[case("[1, 2][3]")]
+    #[case("[[1, 2], [3, 4]][1, 3]")]
+    fn evaluate_matrix_index_out_of_range(#[case] input: &str) {
+        assert!(matches!(evaluate(input), Err(EvaluatorError::IndexOutOfRange(_))));
+    }
+
+    #[rstest]
+    fn evaluate_inverse_of_singular_matrix() {
+        assert_eq!(evaluate("inv([[1, 2], [2, 4]])"), Err(EvaluatorError::SingularMatrix));
+    }
+
+    #[rstest]
+    #[case("2 ^ 10", "1024")]
+    #[case("-2 ^ 2", "-4")]
+    #[case("[[1, 1], [0, 1]] ^ 3", "[[1, 3], [0, 1]]")]
+    #[case("[[2, 0], [0, 4]] ^ -1", "[[0.5, 0], [0, 0.25]]")]
+    fn evaluate_power(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(evaluate(input).unwrap().to_string(), expected);
+    }
+
+    #[rstest]
+    fn evaluate_bound_variable() {
+        let ast = Parser::new("2 * x + y").unwrap().parse().unwrap();
+        let mut evaluator = Evaluator::new().with_variable("x", 3.0).with_variable("y", 1.0);
+        assert_eq!(evaluator.visit_expression(&ast).unwrap(), 7.0);
+    }
+
+    #[rstest]
+    #[case("x + 1", EvaluatorError::UnknownVariable("x".to_string()))]
+    #[case("x = 1", EvaluatorError::UnexpectedExpression("equations are only allowed inside solve()".to_string()))]
+    fn evaluate_unbound_variable_or_equation(#[case] input: &str, #[case] expected: EvaluatorError) {
+        assert_eq!(evaluate(input), Err(expected));
+    }
+
+    fn assert_roots(value: Value, expected: &[f64]) {
+        let roots = match value {
+            Value::Number(n) => vec![n],
+            Value::Matrix(m) => m.values().to_vec(),
+        };
+        assert_eq!(roots.len(), expected.len(), "{:?}", roots);
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-9, "{} != {}", root, expected);
+        }
+    }
+
+    #[rstest]
+    #[case("solve(x^2 - 2 = 0, x)", &[2f64.sqrt()])]
+    #[case("solve(x^2 = 2, x, -1)", &[-(2f64.sqrt())])]
+    #[case("solve(x^3 - x, x, -2..2)", &[-1.0, 0.0, 1.0])]
+    #[case("solve((x - 1) * (x - 3) = 0, x, 0..5)", &[1.0, 3.0])]
+    #[case("solve(1 / x - 1, x, -2..2)", &[1.0])]
+    #[case("solve(sqrt(x) - 1, x, -1..4)", &[1.0])]
+    #[case("solve(x, x, 0..0)", &[0.0])]
+    fn solve_finds_roots(#[case] input: &str, #[case] expected: &[f64]) {
+        assert_roots(evaluate(input).unwrap(), expected);
+    }
+
+    #[rstest]
+    fn solve_reports_missing_sign_change() {
+        assert_eq!(evaluate("solve(x^2 + 1, x, 0..5)"), Err(EvaluatorError::NoSignChange(0.0, 5.0)));
+    }
+
+    #[rstest]
+    fn solve_rejects_a_function_that_is_zero_everywhere() {
+        assert_eq!(
+            evaluate("solve(x - x, x, 0..5)"),
+            Err(EvaluatorError::InvalidArgument("the function is zero everywhere in [0, 5]".to_string()))
+        );
+    }
+
+    #[rstest]
+    #[case("solve(A, b)")]
+    #[case("solve([[2, 0], [0, 4]], b)")]
+    #[case("solve(A, [2, 8])")]
+    fn solve_linear_system_with_bound_variables(#[case] input: &str) {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        let mut evaluator = Evaluator::new()
+            .with_variable(
+                "A",
+                Matrix::from_rows(vec![vec![2.0, 0.0], vec![0.0, 4.0]]).unwrap(),
+            )
+            .with_variable("b", Matrix::row_vector(vec![2.0, 8.0]));
+        let result = evaluator.visit_expression(&ast).unwrap();
+        assert_eq!(result.to_string(), "[1, 2]");
+    }
+
+    #[rstest]
+    fn solve_restores_outer_binding() {
+        let ast = Parser::new("solve(x - 2, x) + x").unwrap().parse().unwrap();
+        let mut evaluator = Evaluator::new().with_variable("x", 10.0);
+        let result = evaluator.visit_expression(&ast).unwrap().as_number().unwrap();
+        assert!((result - 12.0).abs() < 1e-9);
+    }
+
+    #[rstest]
+    #[case("integrate(x^2, x, 0, 3)", 9.0)]
+    #[case("integrate(sin(t), t, 0, pi)", 2.0)]
+    #[case("integrate(exp(-x^2), x, -10, 10) ^ 2", std::f64::consts::PI)]
+    #[case("diff(x^3, x, 2)", 12.0)]
+    #[case("diff(ln(x), x, 4)", 0.25)]
+    #[case("diff(integrate(t^2, t, 0, x), x, 3)", 9.0)]
+    #[case("diff(x^2 * sin(x), x, pi)", -std::f64::consts::PI.powi(2))]
+    fn evaluate_integrals_and_derivatives(#[case] input: &str, #[case] expected: f64) {
+        let result = evaluate(input).unwrap().as_number().unwrap();
+        assert!(
+            (result - expected).abs() < 1e-8,
+            "{} != {}",
+            result,
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "integrate(x, 1, 0, 1)",
+        "Invalid argument: integrate expects a variable name as its second argument"
+    )]
+    #[case("diff(y * x, x, 1)", "Unknown variable 'y'")]
+    #[case(
+        "integrate(x, x, 0)",
+        "Function 'integrate' expects 4 argument(s), got 3"
+    )]
+    fn evaluate_invalid_quoted_arguments(#[case] input: &str, #[case] expected: &str) {
+        assert_eq!(evaluate(input).unwrap_err().to_string(), expected);
+    }
+
+    #[rstest]
+    fn pretty_print_matrix_expression() {
+        let ast = Parser::new("det([[1, 2], [3, 4]])[1]").unwrap().parse().unwrap();
+        let mut printer = PrettyPrinterVisitor::new();
+        assert_eq!(printer.visit_expression(&ast).unwrap(), "det([[1, 2], [3, 4]])[1]");
+    }
+
+    fn num(n: f64) -> Box<Expression> {
+        Box::new(Expression::Number(n))
+    }
+
+    fn var(name: &str) -> Box<Expression> {
+        Box::new(Expression::Variable(name.to_string()))
+    }
+
+    fn without_groupings(expr: Expression) -> Expression {
+        struct DropGroupings;
+        impl ExpressionTransformer for DropGroupings {
+            fn transform_grouping(&mut self, expr: Expression) -> Expression {
+                self.transform_expression(expr)
+            }
+        }
+        DropGroupings.transform_expression(expr)
+    }
+
+    #[rstest]
+    #[case(
+        Expression::Subtract(num(1.0), Box::new(Expression::Subtract(num(2.0), num(3.0)))),
+        "1 - (2 - 3)"
+    )]
+    #[case(
+        Expression::Subtract(Box::new(Expression::Subtract(num(1.0), num(2.0))), num(3.0)),
+        "1 - 2 - 3"
+    )]
+    #[case(
+        Expression::Divide(var("a"), Box::new(Expression::Multiply(var("b"), var("c")))),
+        "a / (b * c)"
+    )]
+    #[case(
+        Expression::Multiply(Box::new(Expression::Add(var("a"), var("b"))), var("c")),
+        "(a + b) * c"
+    )]
+    #[case(
+        Expression::Power(Box::new(Expression::Power(var("a"), var("b"))), var("c")),
+        "(a ^ b) ^ c"
+    )]
+    #[case(
+        Expression::Power(var("a"), Box::new(Expression::Power(var("b"), var("c")))),
+        "a ^ b ^ c"
+    )]
+    #[case(Expression::Power(num(-2.0), num(2.0)), "(-2) ^ 2")]
+    #[case(
+        Expression::Negate(Box::new(Expression::Add(var("a"), var("b")))),
+        "-(a + b)"
+    )]
+    #[case(
+        Expression::Negate(Box::new(Expression::Power(var("a"), num(2.0)))),
+        "-a ^ 2"
+    )]
+    #[case(Expression::Index(Box::new(Expression::Negate(var("a"))), vec![Expression::Number(1.0)]), "(-a)[1]")]
+    #[case(
+        Expression::Equation(Box::new(Expression::Equation(var("a"), var("b"))), var("c")),
+        "(a = b) = c"
+    )]
+    fn pretty_print_inserts_needed_parentheses(
+        #[case] expression: Expression,
+        #[case] expected: &str,
+    ) {
+        let mut printer = PrettyPrinterVisitor::new();
+        assert_eq!(printer.visit_expression(&expression).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("((1 + 2)) * (3)", "((1 + 2)) * (3)", "(1 + 2) * 3")]
+    #[case("(a * b) + (c ^ 2)", "(a * b) + (c ^ 2)", "a * b + c ^ 2")]
+    #[case("-(x)^(2)", "-(x) ^ (2)", "-x ^ 2")]
+    fn pretty_print_keeps_or_drops_groupings(
+        #[case] input: &str,
+        #[case] kept: &str,
+        #[case] dropped: &str,
+    ) {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        let mut printer = PrettyPrinterVisitor::new();
+        assert_eq!(printer.visit_expression(&ast).unwrap(), kept);
+        let mut printer = PrettyPrinterVisitor::new().with_groupings(false);
+        assert_eq!(printer.visit_expression(&ast).unwrap(), dropped);
+    }
+
+    #[rstest]
+    #[case("1 - (2 - (3 - 4)) - 5")]
+    #[case("(2 ^ 3) ^ (-(1 + 1)) / (4 * 5)")]
+    #[case("-(-(a)) * (b / (c / d))")]
+    #[case("f((x = 1), (1..2)[1], [(a + b) * c])")]
+    fn pretty_print_without_groupings_round_trips(#[case] input: &str) {
+        let ast = without_groupings(Parser::new(input).unwrap().parse().unwrap());
+        let mut printer = PrettyPrinterVisitor::new().with_groupings(false);
+        let printed = printer.visit_expression(&ast).unwrap();
+
+        let reparsed = Parser::new(&printed).unwrap().parse().unwrap();
+        assert_eq!(without_groupings(reparsed), ast, "{}", printed);
+    }
+
+    #[rstest]
+    fn evaluate_long_sums_with_default_limits() {
+        let sum = format!("1{}", " + 1".repeat(199));
+        assert_eq!(evaluate(&sum), Ok(200.0.into()));
+    }
+
+    #[rstest]
+    fn evaluate_deep_trees_without_recursion() {
+        let unlimited = EvalLimits {
+            max_depth: usize::MAX,
+            max_nodes: usize::MAX,
+            ..EvalLimits::default()
+        };
+
+        let mut negations = Expression::Number(2.0);
+        for _ in 0..1_000_000 {
+            negations = Expression::Negate(Box::new(negations));
+        }
+        let negations = DeepExpression::new(negations);
+        let negated = Evaluator::new()
+            .with_limits(unlimited.clone())
+            .visit_expression(&negations);
+        let rejected = Evaluator::new().visit_expression(&negations);
+
+        let mut sum = Expression::Variable("x".to_string());
+        for _ in 0..1_000_000 {
+            sum = Expression::Add(Box::new(sum), Box::new(Expression::Number(1.0)));
+        }
+        let sum = DeepExpression::new(sum);
+        let mut evaluator = Evaluator::new()
+            .with_variable("x", 0.5)
+            .with_limits(unlimited);
+        let summed = evaluator.visit_expression(&sum);
+
+        assert_eq!(negated, Ok(2.0.into()));
+        assert_eq!(summed, Ok(1_000_000.5.into()));
+        assert_eq!(
+            rejected,
+            Err(LimitError::TooDeeplyNested(limits::DEFAULT_MAX_DEPTH).into())
+        );
+    }
+
+    #[rstest]
+    #[case("x / 0", Err(EvaluatorError::DivisionByZero))]
+    #[case("y / x", Err(EvaluatorError::UnknownVariable("x".to_string())))]
+    #[case("1[y]", Err(EvaluatorError::TypeMismatch("expected a matrix, got a number".to_string())))]
+    #[case("[1, 2][2] ^ 2 + integrate(t, t, 0, 2) * diff(t ^ 3, t, 1)", Ok(10.0.into()))]
+    #[case("max(1, (2 + 3) * 4, -[5][1])", Ok(20.0.into()))]
+    fn evaluate_in_order(#[case] input: &str, #[case] expected: Result<Value, EvaluatorError>) {
+        let result = evaluate(input);
+        match (&result, &expected) {
+            (Ok(Value::Number(a)), Ok(Value::Number(b))) => assert!((a - b).abs() < 1e-6, "{}", a),
+            _ => assert_eq!(result, expected),
+        }
+    }
+
+    fn evaluate_with(input: &str, limits: EvalLimits) -> Result<Value, EvaluatorError> {
+        let ast = Parser::new(input).unwrap().parse().unwrap();
+        Evaluator::new().with_limits(limits).visit_expression(&ast)
+    }
+
+    #[rstest]
+    #[case("1 + 2 * 3", 5, Ok(7.0.into()))]
+    #[case("1 + 2 * 3", 4, Err(LimitError::StepBudgetExhausted(4).into()))]
+    #[case("(((1)))", 4, Ok(1.0.into()))]
+    // Built-in functions are charged one step per element of their arguments.
+    #[case("mean([1, 2, 3])", 8, Ok(2.0.into()))]
+    #[case("mean([1, 2, 3])", 7, Err(LimitError::StepBudgetExhausted(7).into()))]
+    // The body of `integrate` is evaluated once per sample.
+    #[case("integrate(x, x, 0, 1)", 10, Err(LimitError::StepBudgetExhausted(10).into()))]
+    // So is the trial division in `factor`, which is not bounded by the size of its argument.
+    #[case("factor(9007195909437503)", 1000, Err(LimitError::StepBudgetExhausted(1000).into()))]
+    fn evaluate_with_step_budget(
+        #[case] input: &str,
+        #[case] max_steps: u64,
+        #[case] expected: Result<Value, EvaluatorError>,
+    ) {
+        let limits = EvalLimits {
+            max_steps,
+            ..EvalLimits::default()
+        };
+        assert_eq!(evaluate_with(input, limits), expected);
+    }
+
+    #[rstest]
+    fn step_budget_is_per_evaluation() {
+        let ast = Parser::new("1 + 2").unwrap().parse().unwrap();
+        let mut evaluator = Evaluator::new().with_limits(EvalLimits {
+            max_steps: 3,
+            ..EvalLimits::default()
+        });
+        for _ in 0..3 {
+            assert_eq!(evaluator.visit_expression(&ast), Ok(3.0.into()));
+        }
+    }
+
+    #[rstest]
+    #[case("identity(3)", Ok(9))]
+    #[case("identity(4)", Err(16))]
+    #[case("identity(1000)", Err(1_000_000))]
+    #[case("[[1], [2], [3]] * [1, 2, 3]", Ok(9))]
+    #[case("[[1], [2], [3], [4]] * [1, 2, 3, 4]", Err(16))]
+    #[case("[[1, 2, 3], [4, 5, 6], [7, 8, 9], [1, 2, 3]]", Err(12))]
+    #[case("[1, 2, 3] * [[1], [2], [3]]", Ok(1))]
+    fn evaluate_with_value_size_limit(#[case] input: &str, #[case] expected: Result<usize, usize>) {
+        let limits = EvalLimits {
+            max_value_size: 9,
+            ..EvalLimits::default()
+        };
+        match (evaluate_with(input, limits), expected) {
+            (Ok(value), Ok(size)) => assert_eq!(value.size(), size),
+            (result, Err(size)) => assert_eq!(
+                result,
+                Err(LimitError::ValueTooLarge { size, limit: 9 }.into())
+            ),
+            (result, expected) => panic!("{:?}, expected {:?}", result, expected),
+        }
+    }
+
+    #[rstest]
+    fn evaluate_with_deadline() {
+        let timeout = Duration::ZERO;
+        let limits = EvalLimits {
+            timeout: Some(timeout),
+            ..EvalLimits::default()
+        };
+        assert_eq!(evaluate_with("1 + 2", limits.clone()), Ok(3.0.into()));
+        // The clock is read every 64 steps.
+        let sum = format!("1{}", " + 1".repeat(40));
+        assert_eq!(
+            evaluate_with(&sum, limits.clone()),
+            Err(LimitError::DeadlineExceeded(timeout).into())
+        );
+        assert_eq!(
+            evaluate_with("factor(9007195909437503)", limits),
+            Err(LimitError::DeadlineExceeded(timeout).into())
+        );
+    }
+
+    #[rstest]
+    fn evaluate_with_node_limit() {
+        let limits = EvalLimits {
+            max_nodes: 12,
+            ..EvalLimits::default()
+        };
+        assert_eq!(
+            evaluate_with("diff(sin(x) / cos(x), x, 1)", limits.clone()),
+            Err(LimitError::TooManyNodes(12).into())
+        );
+        assert!(evaluate_with("diff(x * x, x, 1)", limits).is_ok());
+    }
 }
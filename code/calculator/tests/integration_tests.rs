@@ -1,6 +1,23 @@
+use std::collections::HashMap;
 use rstest::{fixture, rstest};
-use calculator::parsemath::parser::Expression;
-use calculator::parsemath::visitors::{Evaluator, ExpressionVisitor, PrettyPrinterVisitor};
+use calculator::parsemath::arena::ExpressionArena;
+use calculator::parsemath::ascii_art::AsciiArtVisitor;
+use calculator::parsemath::batch::BatchEvaluator;
+use calculator::parsemath::bytecode::{Program, Vm};
+use calculator::parsemath::closures;
+use calculator::parsemath::derivative::DerivativeVisitor;
+use calculator::parsemath::dot::DotVisitor;
+use calculator::parsemath::latex::LatexVisitor;
+use calculator::parsemath::limits::{DEFAULT_MAX_NODES, EvalLimits, LimitError};
+use calculator::parsemath::mathml::MathMLVisitor;
+use calculator::parsemath::parser::{Expression, Parser, ParserError};
+use calculator::parsemath::rpn::RpnPrinterVisitor;
+use calculator::parsemath::sexpr::SExprVisitor;
+use calculator::parsemath::simplifier::Simplifier;
+use calculator::parsemath::substitution::{partially_evaluate, substitute};
+use calculator::parsemath::transform::{ExpressionTransformer, walk_mut};
+use calculator::parsemath::unicode::UnicodeVisitor;
+use calculator::parsemath::visitors::{Evaluator, EvaluatorError, ExpressionVisitor, PrettyPrinterVisitor};
 
 #[fixture]
 fn expression() -> Expression {
@@ -18,7 +35,7 @@ fn expression() -> Expression {
 
 #[rstest]
 fn integration_test_evaluate_expression_with_visitor(expression: Expression) {
-    let mut evaluator = Evaluator {};
+    let mut evaluator = Evaluator::new();
     let result = evaluator.visit_expression(&expression);
     assert_eq!(result.unwrap(), -3.0);
 }
@@ -30,16 +47,135 @@ fn integration_test_evaluate_expression_with_visitor_div_by_zero() {
         Box::new(Expression::Number(0.0)),
     );
 
-    let mut evaluator = Evaluator {};
+    let mut evaluator = Evaluator::new();
     let result = evaluator.visit_expression(&expression);
     assert!(result.is_err());
 }
 
 #[rstest]
 fn pretty_print_expression_with_visitor(expression: Expression) {
-    let mut printer = PrettyPrinterVisitor {
-        output: String::new(),
-    };
+    let mut printer = PrettyPrinterVisitor::new();
     let result = printer.visit_expression(&expression);
     assert_eq!(result.unwrap(), "(1 + 2) * (3 - 4)");
 }
+
+#[rstest]
+fn integration_test_solve_linear_system() {
+    let mut parser = Parser::new("solve([[1, 1], [1, -1]], [3, 1]) * 2").unwrap();
+    let ast = parser.parse().unwrap();
+
+    let mut evaluator = Evaluator::new();
+    let result = evaluator.visit_expression(&ast);
+    assert_eq!(result.unwrap().to_string(), "[4, 2]");
+}
+
+#[rstest]
+fn integration_test_simplify_before_evaluating(expression: Expression) {
+    let simplified = Simplifier::simplify(&expression);
+    assert_eq!(simplified, Expression::Number(-3.0));
+
+    // With matrix variables, `0 * y` is kept and fails like the original while
+    // `y` is unbound.
+    let mut evaluator = Evaluator::new().with_variable("x", 4.0);
+    let ast = Parser::new("x * x / x + 0 * y").unwrap().parse().unwrap();
+    let simplified = Simplifier::new()
+        .with_matrix_variables(true)
+        .transform_expression(ast.clone());
+    let result = evaluator.visit_expression(&simplified);
+    assert_eq!(result, evaluator.visit_expression(&ast));
+    assert_eq!(result, Err(EvaluatorError::UnknownVariable("y".to_string())));
+
+    evaluator.set_variable("y", 1.0);
+    let result = evaluator.visit_expression(&Simplifier::simplify(&ast));
+    assert_eq!(result.unwrap(), 4.0);
+}
+
+#[rstest]
+#[case("2 * (3 + 4)", Ok("14".to_string()))]
+#[case("1 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 1", Err(LimitError::TooManyTokens(32)))]
+#[case("-----------------1", Err(LimitError::TooDeeplyNested(16)))]
+#[case("integrate(sin(x), x, 0, 100)", Err(LimitError::StepBudgetExhausted(1000)))]
+#[case("identity(20)", Err(LimitError::ValueTooLarge { size: 400, limit: 100 }))]
+fn integration_test_evaluate_untrusted_input(#[case] input: &str, #[case] expected: Result<String, LimitError>) {
+    let limits = EvalLimits {
+        max_input_length: 100,
+        max_tokens: 32,
+        max_depth: 16,
+        max_nodes: 32,
+        max_steps: 1000,
+        max_value_size: 100,
+        ..EvalLimits::default()
+    };
+    let result = Parser::with_limits(input, limits.clone())
+        .and_then(|mut parser| parser.parse())
+        .map_err(|error| match error {
+            ParserError::LimitExceeded(limit) => limit,
+            error => panic!("{}", error),
+        })
+        .and_then(|ast| {
+            let mut evaluator = Evaluator::new().with_limits(limits);
+            evaluator.visit_expression(&ast).map_err(|error| match error {
+                EvaluatorError::LimitExceeded(limit) => limit,
+                error => panic!("{}", error),
+            })
+        });
+    assert_eq!(result.map(|value| value.to_string()), expected);
+}
+// The longest input of the form `prefix term term ... suffix` that the default
+// parser accepts.
+fn longest_accepted(prefix: &str, term: &str, suffix: &str) -> String {
+    let input = |count: usize| format!("{}{}{}", prefix, term.repeat(count), suffix);
+    let accepted = |count: usize| Parser::new(&input(count)).unwrap().parse().is_ok();
+    let (mut low, mut high) = (0, DEFAULT_MAX_NODES);
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+        if accepted(middle) { low = middle } else { high = middle - 1 }
+    }
+    assert_eq!(
+        Parser::new(&input(low + 1)).unwrap().parse(),
+        Err(LimitError::TooManyNodes(DEFAULT_MAX_NODES).into())
+    );
+    input(low)
+}
+
+#[rstest]
+#[case::sum("x", " + 1", "")]
+#[case::difference("2 * x", " - x", "")]
+#[case::product("-(x", " * 2 / x", ")")]
+#[case::indices("v", "[1]", "")]
+#[case::solve("solve(x", " + 1", " = 3, x)")]
+fn integration_test_every_pass_handles_the_longest_accepted_tree(#[case] prefix: &str, #[case] term: &str, #[case] suffix: &str) {
+    let input = longest_accepted(prefix, term, suffix);
+    let ast = Parser::new(&input).unwrap().parse().unwrap();
+
+    let _ = Evaluator::new().with_variable("x", 2.0).visit_expression(&ast);
+    let _ = PrettyPrinterVisitor::new().visit_expression(&ast);
+    let _ = LatexVisitor.visit_expression(&ast);
+    let _ = MathMLVisitor.visit_expression(&ast);
+    let _ = UnicodeVisitor.visit_expression(&ast);
+    let _ = AsciiArtVisitor.visit_expression(&ast);
+    let _ = SExprVisitor.visit_expression(&ast);
+    let _ = RpnPrinterVisitor.visit_expression(&ast);
+    let _ = DotVisitor::render(&ast);
+    let _ = DerivativeVisitor::new("x").visit_expression(&ast);
+    let _ = Simplifier::simplify(&ast);
+    let _ = substitute(&ast, &HashMap::from([("x".to_string(), Expression::Variable("y".to_string()))]));
+    let _ = partially_evaluate(&ast, &HashMap::from([("x".to_string(), 2.0)]));
+    if let Ok(program) = Program::compile(&ast) {
+        let _ = Vm::new().run(&program, &vec![2.0; program.variables().len()]);
+    }
+    if let Ok(f) = closures::compile(&ast) {
+        let _ = f(&[2.0]);
+    }
+    let _ = BatchEvaluator::new(2).with_column("x", &[1.0, 2.0]).evaluate(&ast);
+    let mut arena = ExpressionArena::new();
+    let id = arena.insert(&ast);
+    assert_eq!(arena.to_expression(id), ast);
+    let _ = arena.visit(id, &mut Evaluator::new().with_variable("x", 2.0));
+    let _ = arena.visit(id, &mut PrettyPrinterVisitor::new());
+
+    let mut copy = ast.clone();
+    assert_eq!(copy, ast);
+    let _ = format!("{:?}", copy);
+    walk_mut(&mut copy, |child| *child = Expression::Number(1.0));
+}